@@ -1,18 +1,144 @@
 use std::{env, path::PathBuf};
 
+/// Types and variables to generate bindings for when each cargo feature is enabled.
+/// Allowlisted types pull in the types they depend on.
+const INTERFACES: &[(&str, &[&str], &[&str])] = &[
+    (
+        "SYSTEM",
+        &[
+            "VR_IVRSystem_FnTable",
+            "VREvent_t",
+            "EVREventType",
+            "ETrackedDeviceProperty",
+            "ETrackedPropertyError",
+            "ETrackedControllerRole",
+            "ETrackedDeviceClass",
+            "EDeviceActivityLevel",
+            "ETrackingUniverseOrigin",
+            "ETrackingResult",
+            "TrackedDevicePose_t",
+            "VRControllerState_t",
+            "EVRButtonId",
+            "ETextureType",
+        ],
+        &[
+            "IVRSystem_Version",
+            "k_unTrackedDeviceIndex_Hmd",
+            "k_unTrackedDeviceIndexInvalid",
+            "k_unMaxTrackedDeviceCount",
+        ],
+    ),
+    (
+        "APPLICATIONS",
+        &[
+            "VR_IVRApplications_FnTable",
+            "EVRApplicationError",
+            "EVRApplicationProperty",
+            "EVRSceneApplicationState",
+        ],
+        &["IVRApplications_Version", "k_unMaxApplicationKeyLength"],
+    ),
+    (
+        "CHAPERONE",
+        &["VR_IVRChaperone_FnTable", "ChaperoneCalibrationState"],
+        &["IVRChaperone_Version"],
+    ),
+    (
+        "COMPOSITOR",
+        &[
+            "VR_IVRCompositor_FnTable",
+            "EVRCompositorError",
+            "Compositor_FrameTiming",
+            "Compositor_CumulativeStats",
+        ],
+        &[
+            "IVRCompositor_Version",
+            "VRCompositor_ReprojectionAsync",
+            "VRCompositor_ThrottleMask",
+        ],
+    ),
+    (
+        "DRIVER_MANAGER",
+        &["VR_IVRDriverManager_FnTable"],
+        &["IVRDriverManager_Version"],
+    ),
+    (
+        "OVERLAY",
+        &["VR_IVROverlay_FnTable", "EVROverlayError"],
+        &["IVROverlay_Version", "k_unVROverlayMaxKeyLength", "k_unVROverlayMaxNameLength"],
+    ),
+    (
+        "SETTINGS",
+        &["VR_IVRSettings_FnTable", "EVRSettingsError"],
+        &["IVRSettings_Version"],
+    ),
+    (
+        "TRACKED_CAMERA",
+        &["VR_IVRTrackedCamera_FnTable", "EVRTrackedCameraError"],
+        &["IVRTrackedCamera_Version"],
+    ),
+    (
+        "INPUT",
+        &["VR_IVRInput_FnTable", "EVRInputError"],
+        &["IVRInput_Version"],
+    ),
+];
+
 fn main() {
     windows::build! {
-        Windows::Win32::UI::WindowsAndMessaging::MessageBoxW,
+        Windows::Win32::System::Power::{
+            PowerRegisterSuspendResumeNotification, PowerUnregisterSuspendResumeNotification,
+            DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS,
+        },
+        Windows::Win32::Foundation::{CloseHandle, ERROR_FILE_NOT_FOUND, ERROR_SUCCESS, FILETIME},
+        Windows::Win32::Graphics::Dxgi::{CreateDXGIFactory1, IDXGIFactory1, DXGI_ADAPTER_DESC1},
+        Windows::Win32::System::LibraryLoader::GetModuleHandleW,
+        Windows::Win32::System::Registry::{
+            RegDeleteKeyValueW, RegGetValueW, RegSetKeyValueW, HKEY_CURRENT_USER, REG_SZ,
+            RRF_RT_REG_DWORD, RRF_RT_REG_SZ,
+        },
+        Windows::Win32::System::ProcessStatus::{K32GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS},
+        Windows::Win32::System::RemoteDesktop::{
+            WTSRegisterSessionNotification, NOTIFY_FOR_THIS_SESSION,
+        },
+        Windows::Win32::System::SystemInformation::{GetSystemInfo, SYSTEM_INFO},
+        Windows::Win32::System::Threading::{
+            GetProcessTimes, OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+            PROCESS_QUERY_LIMITED_INFORMATION,
+        },
+        Windows::Win32::System::WindowsProgramming::GetUserNameW,
+        Windows::Win32::UI::WindowsAndMessaging::{
+            CreateWindowExW, DefWindowProcW, DispatchMessageW, GetMessageW, MessageBoxW,
+            RegisterClassW, DEVICE_NOTIFY_CALLBACK, MSG, PBT_APMRESUMEAUTOMATIC, PBT_APMSUSPEND,
+            WM_WTSSESSION_CHANGE, WNDCLASSW, WTS_SESSION_LOCK, WTS_SESSION_UNLOCK,
+        },
     };
 
     println!("cargo:rustc-link-search={}/openvr/lib/win64", env::var("CARGO_MANIFEST_DIR").unwrap());
     println!("cargo:rustc-link-lib=openvr_api");
     println!("cargo:rerun-if-changed=openvr.h");
+    println!("cargo:rerun-if-changed=build.rs");
 
-    let bindings = bindgen::Builder::default()
+    let mut builder = bindgen::Builder::default()
         .header("openvr.h")
         .clang_arg("-Iopenvr/headers")
         .parse_callbacks(Box::new(bindgen::CargoCallbacks))
+        .allowlist_type("EVRInitError")
+        .allowlist_type("EVRApplicationType")
+        .allowlist_type("TrackedDeviceIndex_t");
+    for (feature, types, vars) in INTERFACES {
+        if env::var_os(format!("CARGO_FEATURE_{}", feature)).is_none() {
+            continue;
+        }
+        for name in *types {
+            builder = builder.allowlist_type(name);
+        }
+        for name in *vars {
+            builder = builder.allowlist_var(name);
+        }
+    }
+
+    let bindings = builder
         .generate()
         .expect("Unable to generate openvr bindings");
 
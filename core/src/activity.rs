@@ -0,0 +1,93 @@
+use std::time::{Duration, Instant};
+
+use bindings::openvr::{
+    ETrackedDeviceProperty_ETrackedDeviceProperty_Prop_ControllerType_String,
+    EVRButtonId_k_EButton_Grip, TrackedDeviceIndex_t, VRControllerState_t,
+};
+
+use crate::{openvr::VrSystem, properties::PropertyCache, settings::ControllerActivitySettings};
+
+/// How far a trackpad, thumbstick, or trigger has to move to count as use rather than
+/// sensor noise.
+const AXIS_THRESHOLD: f32 = 0.1;
+
+/// The controller type of Valve Index controllers, whose grips sense the hand around them.
+const INDEX_CONTROLLER_TYPE: &str = "knuckles";
+
+/// Watches controller input for buttons being touched or pressed and axes moving, to
+/// tell active play from watching something hands-free.
+pub struct ControllerActivity {
+    idle: Duration,
+    /// The state of each controller when it was last seen being used, so slow movements
+    /// add up rather than each poll's change falling under the threshold.
+    previous: [Option<VRControllerState_t>; 2],
+    last_used: Option<Instant>,
+}
+
+impl ControllerActivity {
+    pub fn new(settings: &ControllerActivitySettings) -> Self {
+        Self {
+            idle: Duration::from_secs(settings.idle_secs),
+            previous: [None, None],
+            last_used: None,
+        }
+    }
+
+    /// Takes the states of the left and right controllers, `None` for one that isn't
+    /// connected, and returns whether either has been used within the idle time.
+    pub fn update(&mut self, current: [Option<VRControllerState_t>; 2], now: Instant) -> bool {
+        for (previous, current) in self.previous.iter_mut().zip(current.iter()) {
+            match (&*previous, current) {
+                (Some(before), Some(after)) if !changed(before, after) => continue,
+                (Some(_), Some(_)) => self.last_used = Some(now),
+                _ => {}
+            }
+            *previous = *current;
+        }
+        self.last_used
+            .map_or(false, |last_used| now.duration_since(last_used) < self.idle)
+    }
+}
+
+/// Whether a hand is holding either of `controllers`, or `None` when neither is an Index
+/// controller. SteamVR only gives skeletal input to the scene application, but the legacy
+/// controller state reports the grip sensor as the grip button being touched.
+pub fn hands_on_controllers(
+    system: &VrSystem,
+    properties: &mut PropertyCache,
+    controllers: &[Option<TrackedDeviceIndex_t>],
+) -> Option<bool> {
+    controllers
+        .iter()
+        .flatten()
+        .filter(|&&index| {
+            properties
+                .string(
+                    system,
+                    index,
+                    ETrackedDeviceProperty_ETrackedDeviceProperty_Prop_ControllerType_String,
+                )
+                .map_or(false, |controller_type| {
+                    controller_type == INDEX_CONTROLLER_TYPE
+                })
+        })
+        .filter_map(|&index| system.get_controller_state(index))
+        .map(|state| state.ulButtonTouched & 1 << EVRButtonId_k_EButton_Grip != 0)
+        .fold(None, |held, hand| Some(held.unwrap_or(false) || hand))
+}
+
+fn changed(previous: &VRControllerState_t, current: &VRControllerState_t) -> bool {
+    // A held button keeps counting, but a thumb resting on a touchpad only counts when
+    // it lands.
+    previous.ulButtonPressed != current.ulButtonPressed
+        || previous.ulButtonTouched != current.ulButtonTouched
+        || current.ulButtonPressed != 0
+        || previous
+            .rAxis
+            .iter()
+            .zip(current.rAxis.iter())
+            .any(|(previous, current)| {
+                (previous.x - current.x).abs() > AXIS_THRESHOLD
+                    || (previous.y - current.y).abs() > AXIS_THRESHOLD
+            })
+}
@@ -0,0 +1,38 @@
+use std::collections::BTreeMap;
+
+use bindings::openvr::{
+    k_unMaxTrackedDeviceCount,
+    EDeviceActivityLevel_EDeviceActivityLevel_k_EDeviceActivityLevel_Standby,
+    ETrackedDeviceClass_ETrackedDeviceClass_TrackedDeviceClass_TrackingReference,
+    ETrackedDeviceProperty_ETrackedDeviceProperty_Prop_SerialNumber_String,
+};
+use log::debug;
+
+use crate::openvr::VrSystem;
+
+/// Returns whether each known base station is awake, keyed by serial number.
+pub fn read_base_stations(system: &VrSystem) -> BTreeMap<String, bool> {
+    let mut base_stations = BTreeMap::new();
+    for index in 0..k_unMaxTrackedDeviceCount {
+        if system.get_tracked_device_class(index)
+            != ETrackedDeviceClass_ETrackedDeviceClass_TrackedDeviceClass_TrackingReference
+        {
+            continue;
+        }
+        let serial = match system.get_string_tracked_device_property(
+            index,
+            ETrackedDeviceProperty_ETrackedDeviceProperty_Prop_SerialNumber_String,
+        ) {
+            Ok(serial) => serial,
+            Err(error) => {
+                debug!("Failed to read base station {} serial: {:?}", index, error);
+                continue;
+            }
+        };
+        let awake = system.is_tracked_device_connected(index)
+            && system.get_tracked_device_activity_level(index)
+                != EDeviceActivityLevel_EDeviceActivityLevel_k_EDeviceActivityLevel_Standby;
+        base_stations.insert(serial, awake);
+    }
+    base_stations
+}
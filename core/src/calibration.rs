@@ -0,0 +1,96 @@
+use bindings::openvr::{
+    ChaperoneCalibrationState, ChaperoneCalibrationState_ChaperoneCalibrationState_Error,
+    ChaperoneCalibrationState_ChaperoneCalibrationState_Error_BaseStationConflict,
+    ChaperoneCalibrationState_ChaperoneCalibrationState_Error_BaseStationUninitialized,
+    ChaperoneCalibrationState_ChaperoneCalibrationState_Error_CollisionBoundsInvalid,
+    ChaperoneCalibrationState_ChaperoneCalibrationState_Error_PlayAreaInvalid,
+    ChaperoneCalibrationState_ChaperoneCalibrationState_OK,
+    ChaperoneCalibrationState_ChaperoneCalibrationState_Warning_BaseStationMayHaveMoved,
+    ChaperoneCalibrationState_ChaperoneCalibrationState_Warning_BaseStationRemoved,
+    ChaperoneCalibrationState_ChaperoneCalibrationState_Warning_SeatedBoundsInvalid,
+};
+
+/// Whether the room setup still matches the tracking hardware.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Calibration {
+    Ok,
+    /// Room setup may be stale and tracking may be offset.
+    BaseStationMayHaveMoved,
+    BaseStationRemoved,
+    SeatedBoundsInvalid,
+    Warning,
+    /// Room setup has not been run or can't be used.
+    BaseStationUninitialized,
+    BaseStationConflict,
+    PlayAreaInvalid,
+    CollisionBoundsInvalid,
+    Error,
+}
+
+impl Calibration {
+    pub const ALL: &'static [Calibration] = &[
+        Calibration::Ok,
+        Calibration::BaseStationMayHaveMoved,
+        Calibration::BaseStationRemoved,
+        Calibration::SeatedBoundsInvalid,
+        Calibration::Warning,
+        Calibration::BaseStationUninitialized,
+        Calibration::BaseStationConflict,
+        Calibration::PlayAreaInvalid,
+        Calibration::CollisionBoundsInvalid,
+        Calibration::Error,
+    ];
+
+    #[allow(non_upper_case_globals)]
+    pub fn from_state(state: ChaperoneCalibrationState) -> Self {
+        match state {
+            ChaperoneCalibrationState_ChaperoneCalibrationState_OK => Calibration::Ok,
+            ChaperoneCalibrationState_ChaperoneCalibrationState_Warning_BaseStationMayHaveMoved => {
+                Calibration::BaseStationMayHaveMoved
+            }
+            ChaperoneCalibrationState_ChaperoneCalibrationState_Warning_BaseStationRemoved => {
+                Calibration::BaseStationRemoved
+            }
+            ChaperoneCalibrationState_ChaperoneCalibrationState_Warning_SeatedBoundsInvalid => {
+                Calibration::SeatedBoundsInvalid
+            }
+            ChaperoneCalibrationState_ChaperoneCalibrationState_Error_BaseStationUninitialized => {
+                Calibration::BaseStationUninitialized
+            }
+            ChaperoneCalibrationState_ChaperoneCalibrationState_Error_BaseStationConflict => {
+                Calibration::BaseStationConflict
+            }
+            ChaperoneCalibrationState_ChaperoneCalibrationState_Error_PlayAreaInvalid => {
+                Calibration::PlayAreaInvalid
+            }
+            ChaperoneCalibrationState_ChaperoneCalibrationState_Error_CollisionBoundsInvalid => {
+                Calibration::CollisionBoundsInvalid
+            }
+            // Newer runtimes may add more specific states within each range.
+            state if state >= ChaperoneCalibrationState_ChaperoneCalibrationState_Error => {
+                Calibration::Error
+            }
+            _ => Calibration::Warning,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Calibration::Ok => "ok",
+            Calibration::BaseStationMayHaveMoved => "base_station_may_have_moved",
+            Calibration::BaseStationRemoved => "base_station_removed",
+            Calibration::SeatedBoundsInvalid => "seated_bounds_invalid",
+            Calibration::Warning => "warning",
+            Calibration::BaseStationUninitialized => "base_station_uninitialized",
+            Calibration::BaseStationConflict => "base_station_conflict",
+            Calibration::PlayAreaInvalid => "play_area_invalid",
+            Calibration::CollisionBoundsInvalid => "collision_bounds_invalid",
+            Calibration::Error => "error",
+        }
+    }
+
+    /// Whether someone should run room setup before the headset is used.
+    pub fn needs_setup(&self) -> bool {
+        *self != Calibration::Ok
+    }
+}
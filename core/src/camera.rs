@@ -0,0 +1,27 @@
+use bindings::openvr::k_unTrackedDeviceIndex_Hmd;
+use log::debug;
+
+use crate::openvr::VrTrackedCamera;
+
+#[derive(Clone, PartialEq)]
+pub struct CameraStatus {
+    pub available: bool,
+    /// Whether something (e.g. the room view) is streaming from the camera.
+    pub active: bool,
+}
+
+impl CameraStatus {
+    pub fn new(tracked_camera: &VrTrackedCamera) -> Self {
+        let available = match tracked_camera.has_camera(k_unTrackedDeviceIndex_Hmd) {
+            Ok(available) => available,
+            Err(error) => {
+                debug!("Failed to check for headset camera: {:?}", error);
+                false
+            }
+        };
+        CameraStatus {
+            available,
+            active: false,
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use crate::settings::Payloads;
+
+/// A request received over MQTT or IPC that needs to be handled by the OpenVR loop.
+#[derive(Debug)]
+pub enum Command {
+    Identify(String),
+    MirrorWindow(bool),
+    Supersampling(f32),
+    MotionSmoothing(bool),
+    StandbyTimeout(f32),
+    /// Headset display brightness in percent.
+    Brightness(f32),
+    LaunchApplication(String),
+    /// Opens a URL in whichever VR application handles its scheme.
+    LaunchUrl(String),
+    /// Stops vr-status without waiting for SteamVR to quit.
+    Quit,
+}
+
+/// The supersampling scales SteamVR offers in its own settings.
+pub const SUPERSAMPLING_RANGE: (f32, f32) = (0.2, 5.0);
+/// Standby timeouts in seconds, up to an hour.
+pub const STANDBY_TIMEOUT_RANGE: (f32, f32) = (0.0, 3600.0);
+/// Headset brightness in percent. Values outside this can leave the display unreadable.
+pub const BRIGHTNESS_RANGE: (f32, f32) = (10.0, 160.0);
+
+/// Clamps a number for a command into `range`, or returns `None` if it isn't finite.
+pub fn clamp(value: f32, (min, max): (f32, f32)) -> Option<f32> {
+    if value.is_finite() {
+        Some(value.max(min).min(max))
+    } else {
+        None
+    }
+}
+
+fn parse_number(payload: &[u8], range: (f32, f32)) -> Option<f32> {
+    clamp(
+        std::str::from_utf8(payload).ok()?.trim().parse().ok()?,
+        range,
+    )
+}
+
+/// Parses a command from a message on a topic under `<prefix>/<id>/`.
+pub fn parse_command(
+    base: &str,
+    topic: &str,
+    payload: &[u8],
+    payloads: &Payloads,
+) -> Option<Command> {
+    let topic = topic.strip_prefix(base)?.strip_prefix('/')?;
+    let parts: Vec<&str> = topic.split('/').collect();
+    match parts.as_slice() {
+        ["identify", device, "set"] => Some(Command::Identify(device.to_string())),
+        ["mirror_window", "set"] => payloads
+            .get("mirror_window")
+            .parse(payload)
+            .map(Command::MirrorWindow),
+        ["motion_smoothing", "set"] => payloads
+            .get("motion_smoothing")
+            .parse(payload)
+            .map(Command::MotionSmoothing),
+        ["supersampling", "set"] => {
+            parse_number(payload, SUPERSAMPLING_RANGE).map(Command::Supersampling)
+        }
+        ["quit", "set"] => Some(Command::Quit),
+        ["launch_url", "set"] => std::str::from_utf8(payload)
+            .ok()
+            .map(|url| Command::LaunchUrl(url.trim().to_string())),
+        ["standby_timeout", "set"] => {
+            parse_number(payload, STANDBY_TIMEOUT_RANGE).map(Command::StandbyTimeout)
+        }
+        ["brightness", "set"] => parse_number(payload, BRIGHTNESS_RANGE).map(Command::Brightness),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(topic: &str, payload: &str) -> Option<Command> {
+        parse_command(
+            "vr/test",
+            &format!("vr/test/{}", topic),
+            payload.as_bytes(),
+            &Payloads::default(),
+        )
+    }
+
+    #[test]
+    fn topics_outside_the_base_are_ignored() {
+        assert!(parse_command("vr/test", "vr/other/quit/set", b"", &Payloads::default()).is_none());
+        assert!(
+            parse_command("vr/test", "vr/testing/quit/set", b"", &Payloads::default()).is_none()
+        );
+        assert!(parse("quit", "").is_none());
+        assert!(parse("unknown/set", "").is_none());
+    }
+
+    #[test]
+    fn commands() {
+        assert!(matches!(parse("quit/set", ""), Some(Command::Quit)));
+        assert!(matches!(
+            parse("identify/LHR-1234/set", ""),
+            Some(Command::Identify(device)) if device == "LHR-1234"
+        ));
+        assert!(matches!(
+            parse("mirror_window/set", "ON"),
+            Some(Command::MirrorWindow(true))
+        ));
+        assert!(matches!(
+            parse("motion_smoothing/set", "OFF"),
+            Some(Command::MotionSmoothing(false))
+        ));
+        assert!(parse("motion_smoothing/set", "on").is_none());
+        assert!(matches!(
+            parse("launch_url/set", " https://example.com/\n"),
+            Some(Command::LaunchUrl(url)) if url == "https://example.com/"
+        ));
+    }
+
+    #[test]
+    fn numbers_are_clamped() {
+        assert!(matches!(
+            parse("supersampling/set", " 1.5\n"),
+            Some(Command::Supersampling(scale)) if scale == 1.5
+        ));
+        assert!(matches!(
+            parse("supersampling/set", "0"),
+            Some(Command::Supersampling(scale)) if scale == 0.2
+        ));
+        assert!(matches!(
+            parse("supersampling/set", "100"),
+            Some(Command::Supersampling(scale)) if scale == 5.0
+        ));
+        assert!(matches!(
+            parse("standby_timeout/set", "-30"),
+            Some(Command::StandbyTimeout(seconds)) if seconds == 0.0
+        ));
+        assert!(matches!(
+            parse("standby_timeout/set", "86400"),
+            Some(Command::StandbyTimeout(seconds)) if seconds == 3600.0
+        ));
+        assert!(matches!(
+            parse("brightness/set", "5"),
+            Some(Command::Brightness(percent)) if percent == 10.0
+        ));
+        assert!(matches!(
+            parse("brightness/set", "200"),
+            Some(Command::Brightness(percent)) if percent == 160.0
+        ));
+    }
+
+    #[test]
+    fn numbers_must_be_finite() {
+        for payload in &["NaN", "inf", "-inf", "1e100", "", "fast"] {
+            assert!(parse("supersampling/set", payload).is_none(), "{}", payload);
+            assert!(
+                parse("standby_timeout/set", payload).is_none(),
+                "{}",
+                payload
+            );
+            assert!(parse("brightness/set", payload).is_none(), "{}", payload);
+        }
+    }
+}
@@ -0,0 +1,348 @@
+use std::{iter::Peekable, str::Chars};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    String(String),
+}
+
+impl Value {
+    pub fn truthy(&self) -> bool {
+        match self {
+            Value::Bool(value) => *value,
+            Value::String(value) => !value.is_empty(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Identifier(String),
+    String(String),
+    Not,
+    And,
+    Or,
+    Equal,
+    NotEqual,
+    OpenParen,
+    CloseParen,
+}
+
+fn read_string(chars: &mut Peekable<Chars>, quote: char) -> Result<String> {
+    let mut value = String::new();
+    loop {
+        match chars.next() {
+            Some('\\') => value.push(chars.next().context("Unterminated string")?),
+            Some(c) if c == quote => return Ok(value),
+            Some(c) => value.push(c),
+            None => bail!("Unterminated string"),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::OpenParen,
+            ')' => Token::CloseParen,
+            '\'' | '"' => Token::String(read_string(&mut chars, c)?),
+            '!' if chars.peek() == Some(&'=') => {
+                chars.next();
+                Token::NotEqual
+            }
+            '!' => Token::Not,
+            '=' | '&' | '|' => {
+                if chars.next() != Some(c) {
+                    bail!("Expected {}{}", c, c);
+                }
+                match c {
+                    '=' => Token::Equal,
+                    '&' => Token::And,
+                    _ => Token::Or,
+                }
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut identifier = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_alphanumeric() && c != '_' {
+                        break;
+                    }
+                    identifier.push(c);
+                    chars.next();
+                }
+                Token::Identifier(identifier)
+            }
+            c => bail!("Unexpected character {:?}", c),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// The names an expression can refer to. The lookup in the MQTT loop answers for each.
+pub const VARIABLES: &[&str] = &[
+    "power",
+    "active",
+    "application",
+    "locked",
+    "controllers_in_use",
+    "simulated",
+];
+
+/// A boolean expression over the published state, e.g. `power && application != ''`.
+#[derive(Debug)]
+pub enum Expression {
+    Literal(Value),
+    Variable(String),
+    Not(Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Equal(Box<Expression>, Box<Expression>),
+    NotEqual(Box<Expression>, Box<Expression>),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        self.position += 1;
+        self.tokens.get(self.position - 1).cloned()
+    }
+
+    fn or(&mut self) -> Result<Expression> {
+        let mut left = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            left = Expression::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expression> {
+        let mut left = self.equality()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            left = Expression::And(Box::new(left), Box::new(self.equality()?));
+        }
+        Ok(left)
+    }
+
+    fn equality(&mut self) -> Result<Expression> {
+        let mut left = self.unary()?;
+        loop {
+            left = match self.peek().cloned() {
+                Some(Token::Equal) => {
+                    self.next();
+                    Expression::Equal(Box::new(left), Box::new(self.unary()?))
+                }
+                Some(Token::NotEqual) => {
+                    self.next();
+                    Expression::NotEqual(Box::new(left), Box::new(self.unary()?))
+                }
+                _ => return Ok(left),
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expression> {
+        match self.next() {
+            Some(Token::Not) => Ok(Expression::Not(Box::new(self.unary()?))),
+            Some(Token::OpenParen) => {
+                let inner = self.or()?;
+                match self.next() {
+                    Some(Token::CloseParen) => Ok(inner),
+                    _ => bail!("Expected )"),
+                }
+            }
+            Some(Token::String(value)) => Ok(Expression::Literal(Value::String(value))),
+            Some(Token::Identifier(name)) => Ok(match name.as_str() {
+                "true" => Expression::Literal(Value::Bool(true)),
+                "false" => Expression::Literal(Value::Bool(false)),
+                _ => Expression::Variable(name),
+            }),
+            Some(token) => bail!("Unexpected {:?}", token),
+            None => bail!("Unexpected end of expression"),
+        }
+    }
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+        };
+        let expression = parser.or()?;
+        if let Some(token) = parser.peek() {
+            bail!("Unexpected {:?}", token);
+        }
+        Ok(expression)
+    }
+
+    /// Fails if the expression refers to anything that isn't in [`VARIABLES`], so a typo
+    /// is caught when the settings are loaded instead of when the sensor is evaluated.
+    pub fn check_variables(&self) -> Result<()> {
+        match self {
+            Expression::Literal(_) => Ok(()),
+            Expression::Variable(name) if VARIABLES.contains(&name.as_str()) => Ok(()),
+            Expression::Variable(name) => bail!(
+                "Unknown variable {}; expected one of {}",
+                name,
+                VARIABLES.join(", ")
+            ),
+            Expression::Not(inner) => inner.check_variables(),
+            Expression::And(left, right)
+            | Expression::Or(left, right)
+            | Expression::Equal(left, right)
+            | Expression::NotEqual(left, right) => {
+                left.check_variables()?;
+                right.check_variables()
+            }
+        }
+    }
+
+    pub fn evaluate(&self, lookup: &dyn Fn(&str) -> Option<Value>) -> Result<Value> {
+        Ok(match self {
+            Expression::Literal(value) => value.clone(),
+            Expression::Variable(name) => {
+                lookup(name).ok_or_else(|| anyhow!("Unknown variable {}", name))?
+            }
+            Expression::Not(inner) => Value::Bool(!inner.evaluate(lookup)?.truthy()),
+            Expression::And(left, right) => {
+                Value::Bool(left.evaluate(lookup)?.truthy() && right.evaluate(lookup)?.truthy())
+            }
+            Expression::Or(left, right) => {
+                Value::Bool(left.evaluate(lookup)?.truthy() || right.evaluate(lookup)?.truthy())
+            }
+            Expression::Equal(left, right) => {
+                Value::Bool(left.evaluate(lookup)? == right.evaluate(lookup)?)
+            }
+            Expression::NotEqual(left, right) => {
+                Value::Bool(left.evaluate(lookup)? != right.evaluate(lookup)?)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(source: &str) -> Value {
+        let lookup = |name: &str| match name {
+            "power" => Some(Value::Bool(true)),
+            "active" => Some(Value::Bool(false)),
+            "locked" => Some(Value::Bool(false)),
+            "application" => Some(Value::String("steam.app.620".to_string())),
+            _ => None,
+        };
+        Expression::parse(source)
+            .unwrap()
+            .evaluate(&lookup)
+            .unwrap()
+    }
+
+    fn parse_error(source: &str) -> String {
+        match Expression::parse(source) {
+            Ok(expression) => panic!("{:?} parsed as {:?}", source, expression),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(evaluate("true || false && false"), Value::Bool(true));
+        assert_eq!(evaluate("(true || false) && false"), Value::Bool(false));
+        assert_eq!(evaluate("false && false || true"), Value::Bool(true));
+    }
+
+    #[test]
+    fn equality_binds_tighter_than_and() {
+        assert_eq!(
+            evaluate("power && application == 'steam.app.620'"),
+            Value::Bool(true)
+        );
+        assert_eq!(evaluate("active == false && power"), Value::Bool(true));
+    }
+
+    #[test]
+    fn not() {
+        assert_eq!(evaluate("!active"), Value::Bool(true));
+        assert_eq!(evaluate("!!power"), Value::Bool(true));
+        assert_eq!(evaluate("!power || !locked"), Value::Bool(true));
+        assert_eq!(evaluate("!(power && active)"), Value::Bool(true));
+        assert_eq!(evaluate("!application"), Value::Bool(false));
+        assert_eq!(evaluate("!''"), Value::Bool(true));
+    }
+
+    #[test]
+    fn equal_and_not_equal() {
+        assert_eq!(evaluate("power == true"), Value::Bool(true));
+        assert_eq!(evaluate("power != active"), Value::Bool(true));
+        assert_eq!(evaluate("application != ''"), Value::Bool(true));
+        assert_eq!(evaluate("application == 'steam.app.1'"), Value::Bool(false));
+        // Values of different types are never equal.
+        assert_eq!(evaluate("power == 'true'"), Value::Bool(false));
+    }
+
+    #[test]
+    fn string_literals() {
+        assert_eq!(evaluate("'it'"), Value::String("it".to_string()));
+        assert_eq!(evaluate("\"it\""), Value::String("it".to_string()));
+        assert_eq!(evaluate("'it\\'s'"), Value::String("it's".to_string()));
+        assert_eq!(evaluate("\"a\\\\b\""), Value::String("a\\b".to_string()));
+        assert_eq!(evaluate("'a \"b\"'"), Value::String("a \"b\"".to_string()));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(parse_error("'open"), "Unterminated string");
+        assert_eq!(parse_error("'open\\"), "Unterminated string");
+        assert_eq!(parse_error("power & active"), "Expected &&");
+        assert_eq!(parse_error("power = active"), "Expected ==");
+        assert_eq!(parse_error("(power"), "Expected )");
+        assert_eq!(
+            parse_error("power active"),
+            "Unexpected Identifier(\"active\")"
+        );
+        assert_eq!(parse_error("power &&"), "Unexpected end of expression");
+        assert_eq!(parse_error(""), "Unexpected end of expression");
+        assert_eq!(parse_error("power > 1"), "Unexpected character '>'");
+    }
+
+    #[test]
+    fn unknown_variables() {
+        assert!(Expression::parse(
+            "power && active && !locked && controllers_in_use != simulated && application != ''"
+        )
+        .unwrap()
+        .check_variables()
+        .is_ok());
+        // Short circuiting would skip the typo when evaluating, but not when checking.
+        let expression = Expression::parse("false && powr").unwrap();
+        assert_eq!(
+            expression.check_variables().unwrap_err().to_string(),
+            format!(
+                "Unknown variable powr; expected one of {}",
+                VARIABLES.join(", ")
+            )
+        );
+        assert_eq!(expression.evaluate(&|_| None).unwrap(), Value::Bool(false));
+        assert!(Expression::parse("powr")
+            .unwrap()
+            .evaluate(&|_| None)
+            .is_err());
+    }
+}
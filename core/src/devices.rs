@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+
+use bindings::openvr::{
+    k_unMaxTrackedDeviceCount,
+    ETrackedControllerRole_ETrackedControllerRole_TrackedControllerRole_LeftHand,
+    ETrackedControllerRole_ETrackedControllerRole_TrackedControllerRole_RightHand,
+    ETrackedDeviceClass_ETrackedDeviceClass_TrackedDeviceClass_Controller,
+    ETrackedDeviceClass_ETrackedDeviceClass_TrackedDeviceClass_GenericTracker,
+    ETrackedDeviceProperty_ETrackedDeviceProperty_Prop_DeviceBatteryPercentage_Float,
+    ETrackedDeviceProperty_ETrackedDeviceProperty_Prop_ModelNumber_String,
+    ETrackedDeviceProperty_ETrackedDeviceProperty_Prop_SerialNumber_String,
+};
+use log::debug;
+
+use crate::{openvr::VrSystem, properties::PropertyCache};
+
+#[derive(Clone, PartialEq)]
+pub struct Device {
+    /// Role, model, and serial, e.g. `Left Controller (Knuckles Left) LHR-12345678`.
+    pub label: String,
+    pub battery: Option<f32>,
+}
+
+/// Returns each connected controller and tracker keyed by serial number, which unlike the
+/// device index stays the same across SteamVR restarts.
+pub fn read_devices(system: &VrSystem, properties: &mut PropertyCache) -> BTreeMap<String, Device> {
+    let mut devices = BTreeMap::new();
+    for index in 0..k_unMaxTrackedDeviceCount {
+        #[allow(non_upper_case_globals)]
+        let role = match system.get_tracked_device_class(index) {
+            ETrackedDeviceClass_ETrackedDeviceClass_TrackedDeviceClass_Controller => {
+                match system.get_controller_role_for_tracked_device_index(index) {
+                    ETrackedControllerRole_ETrackedControllerRole_TrackedControllerRole_LeftHand => {
+                        "Left Controller"
+                    }
+                    ETrackedControllerRole_ETrackedControllerRole_TrackedControllerRole_RightHand => {
+                        "Right Controller"
+                    }
+                    _ => "Controller",
+                }
+            }
+            ETrackedDeviceClass_ETrackedDeviceClass_TrackedDeviceClass_GenericTracker => "Tracker",
+            _ => continue,
+        };
+        if !system.is_tracked_device_connected(index) {
+            continue;
+        }
+        let serial = match properties.string(
+            system,
+            index,
+            ETrackedDeviceProperty_ETrackedDeviceProperty_Prop_SerialNumber_String,
+        ) {
+            Some(serial) => serial,
+            None => {
+                debug!("Failed to read device {} serial", index);
+                continue;
+            }
+        };
+        let label = match properties.string(
+            system,
+            index,
+            ETrackedDeviceProperty_ETrackedDeviceProperty_Prop_ModelNumber_String,
+        ) {
+            Some(model) => format!("{} ({}) {}", role, model, serial),
+            None => format!("{} {}", role, serial),
+        };
+        let battery = properties.float(
+            system,
+            index,
+            ETrackedDeviceProperty_ETrackedDeviceProperty_Prop_DeviceBatteryPercentage_Float,
+        );
+        devices.insert(serial, Device { label, battery });
+    }
+    devices
+}
+
+/// Returns the serial and device with the least charge, if any device reports a battery.
+pub fn lowest_battery(devices: &BTreeMap<String, Device>) -> Option<(&str, &Device, f32)> {
+    devices
+        .iter()
+        .filter_map(|(serial, device)| Some((serial.as_str(), device, device.battery?)))
+        .min_by(|(_, _, a), (_, _, b)| a.total_cmp(b))
+}
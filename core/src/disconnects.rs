@@ -0,0 +1,50 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::settings::DisconnectSettings;
+
+/// Tracked device disconnects within the window, and whether that's enough to be a problem.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DisconnectStatus {
+    pub recent: usize,
+    pub flapping: bool,
+}
+
+/// Counts tracked device disconnects over a rolling window. A flaky USB cable or hub
+/// usually shows up as devices dropping and coming back over and over before the headset
+/// finally grays out.
+pub struct DisconnectTracker {
+    window: Duration,
+    threshold: usize,
+    disconnects: VecDeque<Instant>,
+}
+
+impl DisconnectTracker {
+    pub fn new(settings: &DisconnectSettings) -> Self {
+        Self {
+            window: Duration::from_secs(settings.window_secs),
+            threshold: settings.threshold,
+            disconnects: VecDeque::new(),
+        }
+    }
+
+    pub fn record(&mut self, now: Instant) {
+        self.disconnects.push_back(now);
+    }
+
+    /// Forgets disconnects older than the window and returns what's left.
+    pub fn status(&mut self, now: Instant) -> DisconnectStatus {
+        while let Some(oldest) = self.disconnects.front() {
+            if now.duration_since(*oldest) < self.window {
+                break;
+            }
+            self.disconnects.pop_front();
+        }
+        DisconnectStatus {
+            recent: self.disconnects.len(),
+            flapping: self.disconnects.len() >= self.threshold,
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use rumqttc::AsyncClient;
+use serde::Serialize;
+
+use crate::{
+    mqtt::publish,
+    numbers::Formats,
+    settings::{DiscoveryFormat, Settings},
+    topics::TopicRegistry,
+    updater::INSTALLED_VERSION,
+};
+
+/// Names Home Assistant entities from `hass_name_template`, with the English sensor names
+/// replaced by `hass_names` where given. `{name}` is left in place and filled in by
+/// `publish_discovery`, so the device can be renamed without rebuilding every component.
+pub struct EntityNames<'a> {
+    template: String,
+    names: &'a HashMap<String, String>,
+}
+
+impl<'a> EntityNames<'a> {
+    pub fn new(settings: &'a Settings) -> Self {
+        Self {
+            template: settings.hass_name_template.clone(),
+            names: &settings.hass_names,
+        }
+    }
+
+    /// Names the entity with `object_id`, called `sensor` unless overridden.
+    pub fn get(&self, object_id: &str, sensor: &str) -> String {
+        let sensor = self.names.get(object_id).map_or(sensor, String::as_str);
+        self.template.replace("{sensor}", sensor)
+    }
+
+    /// Names one of several entities sharing `key`, such as one per base station. `{}` in
+    /// `sensor` or its override is replaced with `value`.
+    pub fn get_with(&self, key: &str, sensor: &str, value: &str) -> String {
+        let sensor = self.names.get(key).map_or(sensor, String::as_str);
+        self.template
+            .replace("{sensor}", &sensor.replace("{}", value))
+    }
+}
+
+/// How Home Assistant records long-term statistics for a numeric sensor.
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StateClass {
+    /// The value is a reading at a point in time, like a battery level.
+    Measurement,
+    /// The value counts up, and a drop means it was reset, like dropped frames.
+    TotalIncreasing,
+}
+
+/// Builds the discovery configuration of a numeric sensor, including the statistics
+/// metadata Home Assistant needs to keep long-term statistics rather than plain history.
+#[derive(Clone, Serialize)]
+pub struct DiscoveryEntity {
+    #[serde(skip)]
+    object_id: String,
+    name: String,
+    unique_id: String,
+    state_topic: String,
+    state_class: StateClass,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device_class: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unit_of_measurement: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    suggested_display_precision: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entity_category: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    json_attributes_topic: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    availability: Option<serde_json::Value>,
+}
+
+impl DiscoveryEntity {
+    pub fn sensor(
+        object_id: impl Into<String>,
+        name: String,
+        unique_id: &str,
+        state_topic: impl Into<String>,
+        state_class: StateClass,
+    ) -> Self {
+        let object_id = object_id.into();
+        Self {
+            unique_id: format!("{}_{}", unique_id, object_id),
+            object_id,
+            name,
+            state_topic: state_topic.into(),
+            state_class,
+            device_class: None,
+            unit_of_measurement: None,
+            suggested_display_precision: None,
+            entity_category: None,
+            json_attributes_topic: None,
+            availability: None,
+        }
+    }
+
+    pub fn device_class(mut self, device_class: &'static str) -> Self {
+        self.device_class = Some(device_class);
+        self
+    }
+
+    /// Sets the unit and how many decimal places Home Assistant shows by default.
+    pub fn unit(mut self, unit: &'static str, precision: u32) -> Self {
+        self.unit_of_measurement = Some(unit);
+        self.suggested_display_precision = Some(precision);
+        self
+    }
+
+    /// Sets how many decimal places Home Assistant shows by default, for unitless counts.
+    pub fn precision(mut self, precision: u32) -> Self {
+        self.suggested_display_precision = Some(precision);
+        self
+    }
+
+    /// Replaces the unit and precision with those `formats` publishes `key` with.
+    pub fn formatted(mut self, formats: &Formats, key: &str) -> Self {
+        let (unit, decimals) = formats.get(key);
+        if let Some(unit) = unit {
+            self.unit_of_measurement = unit.symbol();
+        }
+        self.suggested_display_precision = Some(decimals);
+        self
+    }
+
+    pub fn diagnostic(mut self) -> Self {
+        self.entity_category = Some("diagnostic");
+        self
+    }
+
+    pub fn attributes_topic(mut self, topic: impl Into<String>) -> Self {
+        self.json_attributes_topic = Some(topic.into());
+        self
+    }
+
+    pub fn availability(mut self, availability: &serde_json::Value) -> Self {
+        self.availability = Some(availability.clone());
+        self
+    }
+
+    /// Produces the `(platform, object_id, config)` component for `publish_discovery`.
+    pub fn build(self) -> (&'static str, String, serde_json::Value) {
+        let config = serde_json::to_value(&self).unwrap();
+        ("sensor", self.object_id, config)
+    }
+}
+
+/// Publishes Home Assistant discovery configuration for `(platform, object_id, config)`
+/// components, either one topic per entity or all together as a device called `name`.
+/// Returns `false` if the broker stalled before everything was published.
+pub async fn publish_discovery(
+    client: &AsyncClient,
+    registry: &mut TopicRegistry,
+    settings: &Settings,
+    unique_id: &str,
+    name: &str,
+    mut components: Vec<(&str, String, serde_json::Value)>,
+) -> Result<bool> {
+    for (_, _, config) in &mut components {
+        if let Some(entity_name) = config.get_mut("name") {
+            if let Some(template) = entity_name.as_str() {
+                *entity_name = template.replace("{name}", name).into();
+            }
+        }
+    }
+    match settings.hass_discovery {
+        DiscoveryFormat::Entity => {
+            for (platform, object_id, mut config) in components {
+                // Device triggers can't exist without a device.
+                if platform == "device_automation" {
+                    config["device"] = serde_json::json!({
+                        "identifiers": [unique_id],
+                        "name": name,
+                        "sw_version": INSTALLED_VERSION,
+                    });
+                }
+                let topic = format!(
+                    "{}/{}/{}_{}/config",
+                    settings.hass_prefix, platform, settings.id, object_id
+                );
+                registry.record(&topic);
+                if !publish(
+                    client,
+                    &topic,
+                    true,
+                    serde_json::to_string(&config).unwrap(),
+                )
+                .await?
+                {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+        DiscoveryFormat::Device => {
+            let components: serde_json::Map<_, _> = components
+                .into_iter()
+                .map(|(platform, object_id, mut config)| {
+                    config["platform"] = platform.into();
+                    (format!("{}_{}", settings.id, object_id), config)
+                })
+                .collect();
+            let topic = format!("{}/device/{}/config", settings.hass_prefix, settings.id);
+            registry.record(&topic);
+            publish(
+                client,
+                &topic,
+                true,
+                serde_json::to_string(&serde_json::json!({
+                    "device": {
+                        "identifiers": [unique_id],
+                        "name": name,
+                        "sw_version": INSTALLED_VERSION,
+                    },
+                    "origin": {
+                        "name": "vr-status",
+                        "sw_version": INSTALLED_VERSION,
+                        "support_url": "https://github.com/mdonoughe/vr-status",
+                    },
+                    "components": components,
+                }))
+                .unwrap(),
+            )
+            .await
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use anyhow::Result;
+use bindings::openvr::{
+    k_unTrackedDeviceIndex_Hmd,
+    ETrackedDeviceProperty_ETrackedDeviceProperty_Prop_DisplayFrequency_Float,
+    ETrackedDeviceProperty_ETrackedDeviceProperty_Prop_TrackingSystemName_String,
+};
+
+use crate::openvr::VrSystem;
+
+#[derive(Clone, PartialEq)]
+pub struct DisplayStatus {
+    pub frequency: f32,
+    /// The per-eye render target size SteamVR recommends at the current resolution scale.
+    pub render_width: u32,
+    pub render_height: u32,
+    /// Whether SteamVR drives the headset directly instead of as a desktop monitor.
+    pub direct_mode: bool,
+    /// Whether the headset is the null driver's simulated one rather than real hardware.
+    pub simulated: bool,
+    /// The name of the graphics adapter SteamVR renders on, which on laptops with two
+    /// GPUs is sometimes the integrated one.
+    pub gpu: Option<String>,
+}
+
+/// The tracking system of the simulated headset SteamVR uses with `forcedDriver: null`.
+const NULL_DRIVER: &str = "null";
+
+pub fn read_display_status(system: &VrSystem) -> Result<DisplayStatus> {
+    let frequency = system.get_float_tracked_device_property(
+        k_unTrackedDeviceIndex_Hmd,
+        ETrackedDeviceProperty_ETrackedDeviceProperty_Prop_DisplayFrequency_Float,
+    )?;
+    let tracking_system = system.get_string_tracked_device_property(
+        k_unTrackedDeviceIndex_Hmd,
+        ETrackedDeviceProperty_ETrackedDeviceProperty_Prop_TrackingSystemName_String,
+    )?;
+    let (render_width, render_height) = system.get_recommended_render_target_size();
+    Ok(DisplayStatus {
+        frequency,
+        render_width,
+        render_height,
+        direct_mode: !system.is_display_on_desktop(),
+        simulated: tracking_system == NULL_DRIVER,
+        gpu: None,
+    })
+}
@@ -0,0 +1,19 @@
+use std::collections::BTreeMap;
+
+use log::debug;
+
+use crate::openvr::VrDriverManager;
+
+/// Returns whether each installed driver is enabled, keyed by driver name.
+pub fn read_drivers(driver_manager: &VrDriverManager) -> BTreeMap<String, bool> {
+    let mut drivers = BTreeMap::new();
+    for driver in 0..driver_manager.get_driver_count() {
+        match driver_manager.get_driver_name(driver) {
+            Ok(name) => {
+                drivers.insert(name, driver_manager.is_enabled(driver));
+            }
+            Err(error) => debug!("Failed to read driver {} name: {:?}", driver, error),
+        }
+    }
+    drivers
+}
@@ -0,0 +1,179 @@
+use bindings::openvr::{TrackedDeviceIndex_t, VREvent_t};
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::openvr::{VrApplications, VrSystem};
+
+pub struct RawEvent {
+    pub name: String,
+    pub device_index: TrackedDeviceIndex_t,
+    pub age_seconds: f32,
+}
+
+impl RawEvent {
+    pub fn new(system: &VrSystem, event: &VREvent_t) -> Self {
+        RawEvent {
+            name: system
+                .get_event_type_name_from_enum(event.eventType as _)
+                .to_string_lossy()
+                .into_owned(),
+            device_index: event.trackedDeviceIndex,
+            age_seconds: event.eventAgeSeconds,
+        }
+    }
+}
+
+/// A one-shot happening published as a Home Assistant event entity.
+#[derive(Clone, Copy)]
+pub enum Occurrence {
+    Screenshot,
+    ChaperoneCollision,
+    QuitRequested,
+    HeadsetDonned,
+    HeadsetDoffed,
+    /// Someone in the headset held the panic gesture.
+    Panic,
+    /// `in_game` turned on.
+    GameStarted,
+    /// `in_game` turned off.
+    GameEnded,
+    /// The watchdog restarted vr-status after it stopped making progress or crashed.
+    WatchdogRestart,
+    StandbyEntered,
+    StandbyLeft,
+    /// The recommended per-eye render size changed, usually from SteamVR's automatic
+    /// resolution.
+    RenderResolutionChanged,
+}
+
+impl Occurrence {
+    pub const ALL: &'static [Occurrence] = &[
+        Occurrence::Screenshot,
+        Occurrence::ChaperoneCollision,
+        Occurrence::QuitRequested,
+        Occurrence::HeadsetDonned,
+        Occurrence::HeadsetDoffed,
+        Occurrence::Panic,
+        Occurrence::GameStarted,
+        Occurrence::GameEnded,
+        Occurrence::WatchdogRestart,
+        Occurrence::StandbyEntered,
+        Occurrence::StandbyLeft,
+        Occurrence::RenderResolutionChanged,
+    ];
+
+    /// These are also offered as Home Assistant device triggers, as `(type, subtype)`.
+    pub const TRIGGERS: &'static [(Occurrence, &'static str, &'static str)] = &[
+        (Occurrence::HeadsetDonned, "donned", "headset"),
+        (Occurrence::HeadsetDoffed, "doffed", "headset"),
+        (Occurrence::GameStarted, "started", "game"),
+        (Occurrence::GameEnded, "ended", "game"),
+    ];
+
+    /// The `event_type` reported to Home Assistant.
+    pub fn name(self) -> &'static str {
+        match self {
+            Occurrence::Screenshot => "screenshot",
+            Occurrence::ChaperoneCollision => "chaperone_collision",
+            Occurrence::QuitRequested => "quit_requested",
+            Occurrence::HeadsetDonned => "headset_donned",
+            Occurrence::HeadsetDoffed => "headset_doffed",
+            Occurrence::Panic => "panic",
+            Occurrence::GameStarted => "game_started",
+            Occurrence::GameEnded => "game_ended",
+            Occurrence::WatchdogRestart => "watchdog_restart",
+            Occurrence::StandbyEntered => "standby_entered",
+            Occurrence::StandbyLeft => "standby_left",
+            Occurrence::RenderResolutionChanged => "render_resolution_changed",
+        }
+    }
+}
+
+/// An occurrence stamped with when it happened rather than when it was published, which can
+/// be later if the broker is slow.
+pub struct OccurrenceEvent {
+    pub occurrence: Occurrence,
+    pub at: DateTime<Utc>,
+    /// What caused it, for occurrences that can happen more than one way.
+    pub reason: Option<&'static str>,
+    /// Extra fields published alongside `event_type`.
+    pub details: serde_json::Map<String, serde_json::Value>,
+}
+
+impl OccurrenceEvent {
+    pub fn new(occurrence: Occurrence, reason: Option<&'static str>) -> Self {
+        OccurrenceEvent {
+            occurrence,
+            at: Utc::now(),
+            reason,
+            details: serde_json::Map::new(),
+        }
+    }
+
+    pub fn detail(mut self, key: &str, value: impl Into<serde_json::Value>) -> Self {
+        self.details.insert(key.to_string(), value.into());
+        self
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut json = serde_json::json!({
+            "event_type": self.occurrence.name(),
+            "at": self.at.to_rfc3339_opts(SecondsFormat::Millis, true),
+        });
+        if let Some(reason) = self.reason {
+            json["reason"] = reason.into();
+        }
+        for (key, value) in &self.details {
+            json[key] = value.clone();
+        }
+        json
+    }
+}
+
+/// Why SteamVR last went inactive or away, published as Home Assistant attributes.
+#[derive(Clone, PartialEq)]
+pub enum Reason {
+    Standby,
+    Suspend,
+    Quit {
+        pid: u32,
+        application: Option<String>,
+        forced: bool,
+        connection_lost: bool,
+    },
+}
+
+impl Reason {
+    /// Decodes the process that SteamVR is shutting down for from a quit event.
+    pub fn quit(applications: &VrApplications, event: &VREvent_t) -> Self {
+        let process = unsafe { event.data.process };
+        let application = match applications.get_application_key_by_process_id(process.pid) {
+            Ok(key) => Some(key.to_string_lossy().into_owned()),
+            Err(_) => None,
+        };
+        Reason::Quit {
+            pid: process.pid,
+            application,
+            forced: process.bForced,
+            connection_lost: process.bConnectionLost,
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Reason::Standby => serde_json::json!({ "reason": "standby" }),
+            Reason::Suspend => serde_json::json!({ "reason": "suspend" }),
+            Reason::Quit {
+                pid,
+                application,
+                forced,
+                connection_lost,
+            } => serde_json::json!({
+                "reason": "quit",
+                "pid": pid,
+                "application": application,
+                "forced": forced,
+                "connection_lost": connection_lost,
+            }),
+        }
+    }
+}
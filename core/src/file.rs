@@ -0,0 +1,57 @@
+use std::{ffi::OsString, path::Path};
+
+use anyhow::{Context, Result};
+use log::error;
+
+use crate::{mqtt::State, settings::FileSettings};
+
+async fn write_state(path: &Path, power: bool, active: bool, application: &str) -> Result<()> {
+    let contents = serde_json::to_vec(&serde_json::json!({
+        "power": power,
+        "active": active,
+        "application": application,
+    }))
+    .unwrap();
+
+    // Write to a temporary file and then move it into place so readers never see a
+    // partially written file.
+    let mut temp_name = path
+        .file_name()
+        .map(OsString::from)
+        .context("State file path has no file name")?;
+    temp_name.push(".tmp");
+    let temp_path = path.with_file_name(temp_name);
+    tokio::fs::write(&temp_path, contents)
+        .await
+        .context("Failed to write temporary state file")?;
+    tokio::fs::rename(&temp_path, path)
+        .await
+        .context("Failed to replace state file")
+}
+
+pub async fn file_loop(settings: &FileSettings, mut state: State) -> Result<()> {
+    loop {
+        let vr_state = state.vr_state.borrow_and_update().clone();
+        if let Err(error) = write_state(
+            &settings.path,
+            vr_state.power,
+            vr_state.active,
+            &vr_state.application,
+        )
+        .await
+        {
+            error!("Failed to write state file: {:?}", error);
+        }
+
+        tokio::select! {
+            recv = state.vr_state.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+            }
+            _ = state.shutdown.cancelled() => break,
+        }
+    }
+
+    write_state(&settings.path, false, false, "").await
+}
@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+
+use crate::settings::Payloads;
+
+/// A state message published by one station of an arcade.
+pub struct StationUpdate {
+    station: String,
+    name: String,
+    payload: Vec<u8>,
+}
+
+impl StationUpdate {
+    /// Parses a message on a topic under `<prefix>/stations/`.
+    pub fn parse(prefix: &str, topic: &str, payload: &[u8]) -> Option<Self> {
+        let mut parts = topic.strip_prefix(prefix)?.split('/');
+        // Stations with include_timestamps wrap the value.
+        let payload = match serde_json::from_slice::<serde_json::Value>(payload) {
+            Ok(serde_json::Value::Object(stamped)) => match stamped.get("value") {
+                Some(serde_json::Value::String(value)) => value.as_bytes().to_vec(),
+                _ => payload.to_vec(),
+            },
+            _ => payload.to_vec(),
+        };
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(station), Some(name), None) => Some(StationUpdate {
+                station: station.to_string(),
+                name: name.to_string(),
+                payload,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Station {
+    power: bool,
+    active: bool,
+    application: String,
+    link_problem: bool,
+}
+
+#[derive(Default)]
+pub struct FleetSummary {
+    pub in_use: Vec<String>,
+    pub idle: Vec<String>,
+    pub offline: Vec<String>,
+    pub problems: Vec<String>,
+}
+
+/// The last known state of every station publishing under `<prefix>/stations/`.
+#[derive(Default)]
+pub struct Fleet {
+    stations: BTreeMap<String, Station>,
+}
+
+impl Fleet {
+    pub fn update(&mut self, update: StationUpdate, payloads: &Payloads) {
+        let station = self.stations.entry(update.station).or_default();
+        match update.name.as_str() {
+            "power" => {
+                if let Some(power) = payloads.get("power").parse(&update.payload) {
+                    station.power = power;
+                }
+            }
+            "active" => {
+                if let Some(active) = payloads.get("active").parse(&update.payload) {
+                    station.active = active;
+                }
+            }
+            "application" => {
+                station.application = String::from_utf8_lossy(&update.payload).into_owned()
+            }
+            "link_problem" => {
+                if let Some(problem) = payloads.get("link_problem").parse(&update.payload) {
+                    station.link_problem = problem;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn summary(&self) -> FleetSummary {
+        let mut summary = FleetSummary::default();
+        for (id, station) in &self.stations {
+            if !station.power {
+                summary.offline.push(id.clone());
+            } else if station.active && !station.application.is_empty() {
+                summary.in_use.push(id.clone());
+            } else {
+                summary.idle.push(id.clone());
+            }
+            if station.power && station.link_problem {
+                summary.problems.push(id.clone());
+            }
+        }
+        summary
+    }
+}
@@ -0,0 +1,103 @@
+use anyhow::Result;
+use bindings::openvr::{
+    k_unTrackedDeviceIndex_Hmd, Compositor_CumulativeStats, Compositor_FrameTiming,
+    ETrackedDeviceProperty_ETrackedDeviceProperty_Prop_DisplayFrequency_Float,
+    VRCompositor_ReprojectionAsync, VRCompositor_ThrottleMask,
+};
+
+use crate::openvr::{VrCompositor, VrSystem};
+
+/// How many recent frames to average over. The compositor keeps a limited history.
+const SAMPLE_FRAMES: u32 = 64;
+
+#[derive(Clone, PartialEq)]
+pub struct FrameStats {
+    pub gpu_ms: f32,
+    pub cpu_ms: f32,
+    pub budget_percent: f32,
+    /// Most recent frames were reprojected on the compositor's own thread.
+    pub async_reprojection: bool,
+    /// Most recent frames were throttled so the application renders every other frame.
+    pub interleaved_reprojection: bool,
+}
+
+/// Averages the timing of recent frames. Returns `None` when nothing has been rendered.
+pub fn sample_frame_stats(
+    system: &VrSystem,
+    compositor: &VrCompositor,
+) -> Result<Option<FrameStats>> {
+    let frequency = system.get_float_tracked_device_property(
+        k_unTrackedDeviceIndex_Hmd,
+        ETrackedDeviceProperty_ETrackedDeviceProperty_Prop_DisplayFrequency_Float,
+    )?;
+    let timings = compositor.get_frame_timings(SAMPLE_FRAMES);
+    if timings.is_empty() || frequency <= 0.0 {
+        return Ok(None);
+    }
+
+    let count = timings.len() as f32;
+    let gpu_ms = timings
+        .iter()
+        .map(|timing| timing.m_flTotalRenderGpuMs)
+        .sum::<f32>()
+        / count;
+    // The application's CPU time is the span between it receiving poses and submitting
+    // the frame rendered with them.
+    let cpu_ms = timings
+        .iter()
+        .map(|timing| (timing.m_flNewFrameReadyMs - timing.m_flNewPosesReadyMs).max(0.0))
+        .sum::<f32>()
+        / count;
+    let budget_ms = 1000.0 / frequency;
+    // The mode flips back and forth around the threshold, so it counts once it's the
+    // majority.
+    let mostly = |flagged: fn(&Compositor_FrameTiming) -> bool| {
+        timings.iter().filter(|timing| flagged(timing)).count() * 2 > timings.len()
+    };
+
+    Ok(Some(FrameStats {
+        gpu_ms,
+        cpu_ms,
+        budget_percent: gpu_ms.max(cpu_ms) / budget_ms * 100.0,
+        async_reprojection: mostly(|timing| {
+            timing.m_nReprojectionFlags & VRCompositor_ReprojectionAsync != 0
+        }),
+        interleaved_reprojection: mostly(|timing| {
+            timing.m_nReprojectionFlags & VRCompositor_ThrottleMask != 0
+        }),
+    }))
+}
+
+#[derive(Clone, Default, PartialEq)]
+pub struct FrameCounts {
+    pub dropped: u64,
+    pub reprojected: u64,
+}
+
+/// Totals frames since the SteamVR session started.
+///
+/// The compositor only keeps totals for the current scene application, so they're
+/// accumulated here across application changes.
+#[derive(Default)]
+pub struct FrameCounter {
+    pid: u32,
+    dropped: u32,
+    reprojected: u32,
+    pub counts: FrameCounts,
+}
+
+impl FrameCounter {
+    pub fn update(&mut self, stats: &Compositor_CumulativeStats) {
+        if stats.m_nPid != self.pid {
+            self.pid = stats.m_nPid;
+            self.dropped = 0;
+            self.reprojected = 0;
+        }
+        self.counts.dropped += stats.m_nNumDroppedFrames.saturating_sub(self.dropped) as u64;
+        self.counts.reprojected += stats
+            .m_nNumReprojectedFrames
+            .saturating_sub(self.reprojected) as u64;
+        self.dropped = stats.m_nNumDroppedFrames;
+        self.reprojected = stats.m_nNumReprojectedFrames;
+    }
+}
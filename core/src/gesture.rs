@@ -0,0 +1,62 @@
+use std::time::{Duration, Instant};
+
+use bindings::openvr::{
+    EVRButtonId, EVRButtonId_k_EButton_A, EVRButtonId_k_EButton_ApplicationMenu,
+    EVRButtonId_k_EButton_Grip, EVRButtonId_k_EButton_SteamVR_Touchpad,
+    EVRButtonId_k_EButton_SteamVR_Trigger,
+};
+
+use crate::settings::{ControllerButton, PanicGestureSettings};
+
+fn button_id(button: ControllerButton) -> EVRButtonId {
+    match button {
+        ControllerButton::Menu => EVRButtonId_k_EButton_ApplicationMenu,
+        ControllerButton::Grip => EVRButtonId_k_EButton_Grip,
+        ControllerButton::A => EVRButtonId_k_EButton_A,
+        ControllerButton::Touchpad => EVRButtonId_k_EButton_SteamVR_Touchpad,
+        ControllerButton::Trigger => EVRButtonId_k_EButton_SteamVR_Trigger,
+    }
+}
+
+/// Watches for the panic gesture: every configured button held on both controllers for
+/// the configured time. Fires once per hold so a guest who keeps holding doesn't flood
+/// the broker.
+pub struct PanicGesture {
+    mask: u64,
+    hold: Duration,
+    held_since: Option<Instant>,
+    fired: bool,
+}
+
+impl PanicGesture {
+    pub fn new(settings: &PanicGestureSettings) -> Self {
+        Self {
+            mask: settings
+                .buttons
+                .iter()
+                .fold(0, |mask, button| mask | 1 << button_id(*button)),
+            hold: Duration::from_secs_f32(settings.hold_secs),
+            held_since: None,
+            fired: false,
+        }
+    }
+
+    /// Takes the pressed button masks of the left and right controllers, `None` for one
+    /// that isn't connected, and returns true when the gesture completes.
+    pub fn update(&mut self, left: Option<u64>, right: Option<u64>, now: Instant) -> bool {
+        let held = [left, right]
+            .iter()
+            .all(|pressed| matches!(pressed, Some(pressed) if pressed & self.mask == self.mask));
+        if !held {
+            self.held_since = None;
+            self.fired = false;
+            return false;
+        }
+        let held_since = *self.held_since.get_or_insert(now);
+        if self.fired || now.duration_since(held_since) < self.hold {
+            return false;
+        }
+        self.fired = true;
+        true
+    }
+}
@@ -0,0 +1,45 @@
+use std::{collections::BTreeMap, io::ErrorKind, path::PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::paths::data_file;
+
+/// Remembers when each sensor last changed, persisted in the data directory so it
+/// survives restarts.
+pub struct History {
+    path: PathBuf,
+    last_changed: BTreeMap<String, DateTime<Utc>>,
+}
+
+impl History {
+    pub async fn load() -> Result<Self> {
+        let path = data_file("vr-status.history.json")?;
+        let last_changed = match tokio::fs::read(&path).await {
+            Ok(contents) => serde_json::from_slice(&contents).context("Failed to parse history")?,
+            Err(error) if error.kind() == ErrorKind::NotFound => BTreeMap::new(),
+            Err(error) => return Err(error).context("Failed to read history"),
+        };
+        Ok(Self { path, last_changed })
+    }
+
+    pub fn last_changed(&self) -> impl Iterator<Item = (&str, String)> {
+        self.last_changed
+            .iter()
+            .map(|(name, time)| (name.as_str(), format_time(time)))
+    }
+
+    /// Records that `name` changed now and returns the formatted time.
+    pub async fn record(&mut self, name: &str) -> Result<String> {
+        let now = Utc::now();
+        self.last_changed.insert(name.to_string(), now);
+        tokio::fs::write(&self.path, serde_json::to_vec(&self.last_changed).unwrap())
+            .await
+            .context("Failed to write history")?;
+        Ok(format_time(&now))
+    }
+}
+
+fn format_time(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
@@ -0,0 +1,67 @@
+use log::{debug, error, warn};
+use tokio::process::Command;
+
+use crate::{events::OccurrenceEvent, settings::HookSettings, state::VrState};
+
+/// Keeps console windows from flashing up when a hook is a batch file or script.
+#[cfg(windows)]
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+/// Runs the configured commands when occurrences happen, for local automation that
+/// shouldn't need a broker.
+pub struct Hooks<'a> {
+    hooks: &'a [HookSettings],
+}
+
+impl<'a> Hooks<'a> {
+    pub fn new(hooks: &'a [HookSettings]) -> Self {
+        Hooks { hooks }
+    }
+
+    /// Starts every hook for `event` without waiting for them to finish. The state is
+    /// passed in `VR_STATUS_*` environment variables.
+    pub fn run(&self, event: &OccurrenceEvent, application_key: &str, state: &VrState) {
+        let name = event.occurrence.name();
+        for hook in self
+            .hooks
+            .iter()
+            .filter(|hook| hook.on.iter().any(|on| on == name))
+        {
+            let mut command = Command::new(&hook.command);
+            command
+                .args(&hook.args)
+                .env("VR_STATUS_EVENT", name)
+                .env(
+                    "VR_STATUS_AT",
+                    event
+                        .at
+                        .to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                )
+                .env("VR_STATUS_REASON", event.reason.unwrap_or_default())
+                .env("VR_STATUS_POWER", state.power.to_string())
+                .env("VR_STATUS_ACTIVE", state.active.to_string())
+                .env("VR_STATUS_APPLICATION", &state.application)
+                .env("VR_STATUS_APPLICATION_KEY", application_key);
+            #[cfg(windows)]
+            command.creation_flags(CREATE_NO_WINDOW);
+            let mut child = match command.spawn() {
+                Ok(child) => child,
+                Err(error) => {
+                    error!(
+                        "Failed to run hook {} for {}: {:?}",
+                        hook.command, name, error
+                    );
+                    continue;
+                }
+            };
+            let description = format!("{} for {}", hook.command, name);
+            tokio::spawn(async move {
+                match child.wait().await {
+                    Ok(status) if status.success() => debug!("Hook {} finished", description),
+                    Ok(status) => warn!("Hook {} failed with {}", description, status),
+                    Err(error) => error!("Failed to wait for hook {}: {:?}", description, error),
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use anyhow::{Context, Result};
+use log::{debug, error};
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::windows::named_pipe::{NamedPipeServer, ServerOptions},
+    sync::mpsc::UnboundedSender,
+};
+
+use crate::{
+    command::{clamp, Command, BRIGHTNESS_RANGE, SUPERSAMPLING_RANGE},
+    mqtt::State,
+    notify::Notification,
+    settings::IpcSettings,
+};
+
+// Standard JSON-RPC 2.0 error codes.
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+struct Client {
+    state: State,
+    commands: UnboundedSender<Command>,
+    notifications: Option<UnboundedSender<Notification>>,
+    subscribed: bool,
+}
+
+impl Client {
+    /// Handles one request, returning its result or a JSON-RPC error code and message.
+    fn call(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        let string_param = |name: &str| {
+            params[name]
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| (INVALID_PARAMS, format!("Missing string parameter {}", name)))
+        };
+        let number_param = |name: &str, range| {
+            params[name]
+                .as_f64()
+                .and_then(|value| clamp(value as f32, range))
+                .ok_or_else(|| (INVALID_PARAMS, format!("Missing number parameter {}", name)))
+        };
+        let command = match method {
+            "get_state" => return Ok(self.state.snapshot()),
+            "subscribe" => {
+                self.subscribed = true;
+                return Ok(self.state.snapshot());
+            }
+            "notify" => {
+                let notification = Notification {
+                    title: string_param("title")?,
+                    message: string_param("message")?,
+                };
+                return match &self.notifications {
+                    Some(notifications) => {
+                        let _ = notifications.send(notification);
+                        Ok(Value::Null)
+                    }
+                    None => Err((INVALID_PARAMS, "Notifications are not configured".into())),
+                };
+            }
+            "launch_application" => Command::LaunchApplication(string_param("key")?),
+            "launch_url" => Command::LaunchUrl(string_param("url")?),
+            "identify" => Command::Identify(string_param("device")?),
+            "set_mirror_window" => Command::MirrorWindow(
+                params["visible"]
+                    .as_bool()
+                    .ok_or_else(|| (INVALID_PARAMS, "Missing boolean parameter visible".into()))?,
+            ),
+            "set_supersampling" => {
+                Command::Supersampling(number_param("scale", SUPERSAMPLING_RANGE)?)
+            }
+            "set_brightness" => Command::Brightness(number_param("percent", BRIGHTNESS_RANGE)?),
+            _ => return Err((METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+        };
+        let _ = self.commands.send(command);
+        Ok(Value::Null)
+    }
+
+    fn respond(&mut self, line: &str) -> Value {
+        let request: Value = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(error) => {
+                return json!({
+                    "jsonrpc": "2.0",
+                    "id": Value::Null,
+                    "error": { "code": PARSE_ERROR, "message": error.to_string() },
+                })
+            }
+        };
+        let method = request["method"].as_str().unwrap_or_default();
+        match self.call(method, &request["params"]) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "error": { "code": code, "message": message },
+            }),
+        }
+    }
+}
+
+async fn write_message(pipe: &mut (impl AsyncWriteExt + Unpin), message: &Value) -> Result<()> {
+    let mut line = serde_json::to_vec(message).unwrap();
+    line.push(b'\n');
+    pipe.write_all(&line)
+        .await
+        .context("Failed to write to pipe")
+}
+
+fn mark_seen(state: &mut State) {
+    state.vr_state.borrow_and_update();
+    state.scene_state.borrow_and_update();
+    state.mirror_window.borrow_and_update();
+}
+
+/// Serves newline-delimited JSON-RPC requests from one connection until it closes.
+async fn serve_client(pipe: NamedPipeServer, mut client: Client) -> Result<()> {
+    let (reader, mut writer) = tokio::io::split(pipe);
+    let mut lines = BufReader::new(reader).lines();
+    let mut changes = client.state.clone();
+    loop {
+        let changed = tokio::select! {
+            line = lines.next_line() => {
+                let line = match line.context("Failed to read from pipe")? {
+                    Some(line) => line,
+                    None => return Ok(()),
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let was_subscribed = client.subscribed;
+                let response = client.respond(&line);
+                if client.subscribed && !was_subscribed {
+                    // The response already has the current state.
+                    mark_seen(&mut changes);
+                }
+                write_message(&mut writer, &response).await?;
+                false
+            }
+            _ = changes.vr_state.changed(), if client.subscribed => true,
+            _ = changes.scene_state.changed(), if client.subscribed => true,
+            _ = changes.mirror_window.changed(), if client.subscribed => true,
+        };
+        if changed {
+            // Any change sends the full snapshot again.
+            mark_seen(&mut changes);
+            let notification = json!({
+                "jsonrpc": "2.0",
+                "method": "state",
+                "params": client.state.snapshot(),
+            });
+            write_message(&mut writer, &notification).await?;
+        }
+    }
+}
+
+pub async fn ipc_loop(
+    settings: &IpcSettings,
+    state: State,
+    commands: UnboundedSender<Command>,
+    notifications: Option<UnboundedSender<Notification>>,
+) -> Result<()> {
+    let mut server = ServerOptions::new()
+        .first_pipe_instance(true)
+        .create(&settings.pipe)
+        .with_context(|| format!("Failed to create pipe {}", settings.pipe))?;
+    loop {
+        server
+            .connect()
+            .await
+            .context("Failed to accept connection")?;
+        let pipe = server;
+        // Create the next instance before handing this one off so there's always one
+        // waiting for a client.
+        server = ServerOptions::new()
+            .create(&settings.pipe)
+            .with_context(|| format!("Failed to create pipe {}", settings.pipe))?;
+        debug!("IPC client connected");
+        let client = Client {
+            state: state.clone(),
+            commands: commands.clone(),
+            notifications: notifications.clone(),
+            subscribed: false,
+        };
+        tokio::spawn(async move {
+            if let Err(error) = serve_client(pipe, client).await {
+                error!("IPC client failed: {:?}", error);
+            }
+        });
+    }
+}
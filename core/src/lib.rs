@@ -0,0 +1,107 @@
+//! Reports SteamVR state to MQTT and other sinks.
+//!
+//! [`openvr::OpenVr`] loads the OpenVR runtime and hands out typed wrappers for its
+//! interfaces. Whatever drives those interfaces pushes changes through an
+//! [`mqtt::MqttHandle`], and each sink ([`mqtt::mqtt_loop`], [`file::file_loop`],
+//! [`ipc::ipc_loop`]) watches the matching [`mqtt::State`] for as long as it runs.
+//! [`settings::load_settings`] reads the same `vr-status.yaml` the vr-status binary uses.
+
+/// Whether the controllers are being used.
+pub mod activity;
+
+/// Power state of Lighthouse base stations.
+pub mod base_stations;
+/// Whether room setup needs to be run again.
+pub mod calibration;
+/// Tracked camera availability and streaming.
+pub mod camera;
+/// Requests from sinks to change SteamVR state.
+pub mod command;
+/// Expressions for derived binary sensors.
+pub mod derived;
+/// Controllers and trackers.
+pub mod devices;
+/// Tracked devices dropping out repeatedly.
+pub mod disconnects;
+/// Home Assistant MQTT discovery configuration.
+#[cfg(feature = "discovery")]
+pub mod discovery;
+/// Headset refresh rate and render resolution.
+pub mod display;
+/// Installed SteamVR drivers.
+pub mod drivers;
+/// OpenVR events that are reported as they happen rather than as state.
+pub mod events;
+/// Sink that writes the state to a file.
+#[cfg(feature = "file")]
+pub mod file;
+/// Summaries of every station in arcade mode.
+pub mod fleet;
+/// Compositor frame timing.
+pub mod frame_timing;
+/// Controller button gestures.
+pub mod gesture;
+/// When each state last changed, remembered across restarts.
+pub mod history;
+/// Commands run when events happen.
+pub mod hooks;
+/// Sink that serves the state over a named pipe.
+#[cfg(feature = "ipc")]
+pub mod ipc;
+/// The SteamVR application manifest.
+pub mod manifest;
+/// Finding an MQTT broker on the local network.
+#[cfg(feature = "mdns")]
+pub mod mdns;
+/// State channels and the MQTT sink.
+pub mod mqtt;
+/// Push notifications for conditions that need attention.
+pub mod notify;
+/// Unit conversion and rounding of published numbers.
+pub mod numbers;
+/// Wrappers for the OpenVR runtime and its interfaces.
+pub mod openvr;
+/// Dashboard overlay applications and which overlays are visible.
+pub mod overlays;
+/// Where the program and its settings and state live.
+pub mod paths;
+/// Streaming headset and controller poses to local tools.
+pub mod pose_stream;
+/// Hiding application names from shared brokers.
+pub mod privacy;
+/// The scene application's executable.
+pub mod process;
+/// SteamVR settings changed while particular applications run.
+pub mod profiles;
+/// Tracked device properties remembered between polls.
+pub mod properties;
+/// Whether everything needed to play is on and charged.
+pub mod readiness;
+/// Counts of SteamVR restarts and crashes, remembered across restarts.
+pub mod sessions;
+/// `vr-status.yaml`.
+pub mod settings;
+/// Finding an MQTT broker with DNS SRV records.
+#[cfg(feature = "srv")]
+pub mod srv;
+/// The validated power, active, and application state.
+pub mod state;
+/// VR time rolled up by day and week.
+pub mod stats;
+/// When periodic publishes run, which can be less often during standby.
+pub mod telemetry;
+/// TLS configuration for the MQTT connection.
+pub mod tls;
+/// Every retained topic vr-status has published, for clearing them later.
+pub mod topics;
+/// Checks for and stages new releases.
+pub mod updater;
+/// The Windows user and Steam account using the PC.
+pub mod user;
+/// Progress of the long-running loops, for the watchdog.
+pub mod watchdog;
+/// Sink that serves a settings page over HTTP.
+#[cfg(feature = "web")]
+pub mod web;
+/// Wireless adapter link quality.
+pub mod wireless;
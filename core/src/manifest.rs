@@ -0,0 +1,50 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+
+use crate::settings::{ManifestLaunchType, ManifestSettings};
+
+/// The manifest shipped beside the executable, which names it with a relative path.
+const SHIPPED_MANIFEST: &str = include_str!("../../vr-status.vrmanifest");
+
+/// Writes a copy of the shipped manifest with the executable's absolute path and any changes
+/// from `settings` to `%LOCALAPPDATA%\vr-status` and returns where it went. That path stays
+/// the same when the install folder moves, so registering it again replaces the stale binary
+/// path instead of leaving SteamVR with a manifest that no longer exists.
+pub async fn write_manifest(settings: &ManifestSettings) -> Result<PathBuf> {
+    let exe = ::std::env::current_exe().context("Could not find installation directory")?;
+    let exe = exe
+        .to_str()
+        .context("Invalid characters in installation path")?;
+    let mut manifest: serde_json::Value =
+        serde_json::from_str(SHIPPED_MANIFEST).context("Failed to parse shipped manifest")?;
+    for application in manifest["applications"]
+        .as_array_mut()
+        .context("Shipped manifest has no applications")?
+    {
+        application["binary_path_windows"] = exe.into();
+        if let Some(name) = &settings.name {
+            application["strings"]["en_us"]["name"] = name.as_str().into();
+        }
+        if let Some(description) = &settings.description {
+            application["strings"]["en_us"]["description"] = description.as_str().into();
+        }
+        if let (ManifestLaunchType::Url, Some(url)) = (&settings.launch_type, &settings.url) {
+            application["launch_type"] = "url".into();
+            application["url"] = url.as_str().into();
+        }
+    }
+
+    let mut path: PathBuf = ::std::env::var_os("LOCALAPPDATA")
+        .context("LOCALAPPDATA is not set")?
+        .into();
+    path.push("vr-status");
+    tokio::fs::create_dir_all(&path)
+        .await
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    path.push("vr-status.vrmanifest");
+    tokio::fs::write(&path, serde_json::to_string_pretty(&manifest).unwrap())
+        .await
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
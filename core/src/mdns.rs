@@ -0,0 +1,42 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use log::{debug, info};
+use mdns_sd::{ServiceDaemon, ServiceEvent};
+
+use crate::settings::MqttTransport;
+
+/// How long to wait for a broker to answer before falling back to the configured hosts.
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Looks for an MQTT broker advertising itself on the local network, as Mosquitto does
+/// with the Home Assistant add-on, and returns its address and port.
+pub async fn discover_broker(transport: &MqttTransport) -> Result<Option<(String, u16)>> {
+    let service = match transport {
+        MqttTransport::Tcp => "_mqtt._tcp.local.",
+        MqttTransport::Tls => "_secure-mqtt._tcp.local.",
+    };
+    let daemon = ServiceDaemon::new().context("Failed to start mDNS")?;
+    let events = daemon
+        .browse(service)
+        .context("Failed to browse for MQTT brokers")?;
+    let found = tokio::time::timeout(DISCOVERY_TIMEOUT, async {
+        while let Ok(event) = events.recv_async().await {
+            if let ServiceEvent::ServiceResolved(info) = event {
+                debug!("Found MQTT broker {}", info.get_fullname());
+                if let Some(address) = info.get_addresses().iter().next() {
+                    return Some((address.to_string(), info.get_port()));
+                }
+            }
+        }
+        None
+    })
+    .await
+    .unwrap_or(None);
+    let _ = daemon.shutdown();
+    match &found {
+        Some((host, port)) => info!("Discovered MQTT broker at {}:{}", host, port),
+        None => info!("No MQTT broker found on the local network"),
+    }
+    Ok(found)
+}
@@ -0,0 +1,3054 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{SecondsFormat, Utc};
+use log::{error, info, warn};
+use rumqttc::{
+    AsyncClient, ConnAck, Event, LastWill, MqttOptions, Outgoing, Packet, QoS, TlsConfiguration,
+    Transport,
+};
+use tokio_util::sync::CancellationToken;
+
+#[cfg(feature = "discovery")]
+use crate::discovery::{publish_discovery, DiscoveryEntity, EntityNames, StateClass};
+#[cfg(feature = "mdns")]
+use crate::mdns::discover_broker;
+#[cfg(feature = "srv")]
+use crate::srv::lookup_brokers;
+use crate::{
+    calibration::Calibration,
+    camera::CameraStatus,
+    command::{parse_command, Command},
+    derived::{Expression, Value},
+    devices::{lowest_battery, Device},
+    disconnects::DisconnectStatus,
+    display::DisplayStatus,
+    events::{Occurrence, OccurrenceEvent, RawEvent, Reason},
+    fleet::{Fleet, StationUpdate},
+    frame_timing::{FrameCounts, FrameStats},
+    history::History,
+    hooks::Hooks,
+    numbers::Formats,
+    overlays::OverlayStatus,
+    privacy::anonymize,
+    process::{ProcessInfo, ProcessUsage},
+    readiness::Readiness,
+    sessions::SessionCounts,
+    settings::{save_name, IpFamily, MqttTransport, RateLimitSettings, Settings},
+    state::VrState,
+    stats::UsageStats,
+    telemetry::Schedule,
+    tls::client_config,
+    topics::TopicRegistry,
+    updater::{Release, INSTALLED_VERSION},
+    user::UserInfo,
+    watchdog::Progress,
+    wireless::LinkStatus,
+};
+
+pub struct MqttHandle {
+    pub vr_state: tokio::sync::watch::Sender<VrState>,
+    pub link: tokio::sync::watch::Sender<Option<LinkStatus>>,
+    pub mirror_window: tokio::sync::watch::Sender<bool>,
+    pub scene_state: tokio::sync::watch::Sender<String>,
+    pub frame_stats: tokio::sync::watch::Sender<Option<FrameStats>>,
+    pub frame_counts: tokio::sync::watch::Sender<Option<FrameCounts>>,
+    pub base_stations: tokio::sync::watch::Sender<BTreeMap<String, bool>>,
+    pub devices: tokio::sync::watch::Sender<BTreeMap<String, Device>>,
+    pub application_key: tokio::sync::watch::Sender<String>,
+    pub supersampling: tokio::sync::watch::Sender<Option<f32>>,
+    pub brightness: tokio::sync::watch::Sender<Option<f32>>,
+    pub standby_timeout: tokio::sync::watch::Sender<Option<f32>>,
+    pub motion_smoothing: tokio::sync::watch::Sender<Option<bool>>,
+    pub safe_mode: tokio::sync::watch::Sender<Option<bool>>,
+    pub events: tokio::sync::mpsc::UnboundedSender<RawEvent>,
+    pub occurrences: tokio::sync::mpsc::UnboundedSender<OccurrenceEvent>,
+    pub reason: tokio::sync::watch::Sender<Option<Reason>>,
+    pub camera: tokio::sync::watch::Sender<Option<CameraStatus>>,
+    pub drivers: tokio::sync::watch::Sender<BTreeMap<String, bool>>,
+    pub display: tokio::sync::watch::Sender<Option<DisplayStatus>>,
+    pub display_problem: tokio::sync::watch::Sender<bool>,
+    pub controllers_in_use: tokio::sync::watch::Sender<bool>,
+    pub ready_to_play: tokio::sync::watch::Sender<Option<Readiness>>,
+    pub hands_on_controllers: tokio::sync::watch::Sender<Option<bool>>,
+    pub calibration: tokio::sync::watch::Sender<Option<Calibration>>,
+    pub process: tokio::sync::watch::Sender<Option<ProcessInfo>>,
+    pub process_usage: tokio::sync::watch::Sender<Option<ProcessUsage>>,
+    pub overlays: tokio::sync::watch::Sender<OverlayStatus>,
+    pub boundary_collisions: tokio::sync::watch::Sender<u64>,
+    pub disconnects: tokio::sync::watch::Sender<DisconnectStatus>,
+    pub user: tokio::sync::watch::Sender<Option<UserInfo>>,
+    /// Milliseconds taken by the slowest recent pass through the OpenVR loop.
+    pub poll_latency: tokio::sync::watch::Sender<Option<u64>>,
+    pub progress: Arc<Progress>,
+    /// Cancelled to ask the OpenVR loop to stop.
+    pub shutdown: CancellationToken,
+}
+
+impl MqttHandle {
+    /// Applies a change to the VR state, rejecting combinations that can't happen.
+    pub fn update_vr_state(&mut self, update: impl FnOnce(&mut VrState)) -> Result<()> {
+        let vr_state = self.vr_state.borrow().updated(update)?;
+        if *self.vr_state.borrow() == vr_state {
+            return Ok(());
+        }
+        self.vr_state
+            .send(vr_state)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_power(&mut self, power: bool) -> Result<()> {
+        self.update_vr_state(|vr_state| vr_state.set_power(power))
+    }
+    pub fn set_active(&mut self, active: bool) -> Result<()> {
+        self.update_vr_state(|vr_state| vr_state.active = active)
+    }
+    pub fn set_application(&mut self, name: String) -> Result<()> {
+        self.update_vr_state(|vr_state| vr_state.application = name)
+    }
+    pub fn set_application_key(&mut self, key: String) -> Result<()> {
+        if *self.application_key.borrow() == key {
+            return Ok(());
+        }
+        self.application_key
+            .send(key)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_brightness(&mut self, percent: Option<f32>) -> Result<()> {
+        if *self.brightness.borrow() == percent {
+            return Ok(());
+        }
+        self.brightness
+            .send(percent)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_supersampling(&mut self, scale: Option<f32>) -> Result<()> {
+        if *self.supersampling.borrow() == scale {
+            return Ok(());
+        }
+        self.supersampling
+            .send(scale)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_standby_timeout(&mut self, seconds: Option<f32>) -> Result<()> {
+        if *self.standby_timeout.borrow() == seconds {
+            return Ok(());
+        }
+        self.standby_timeout
+            .send(seconds)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_motion_smoothing(&mut self, enabled: Option<bool>) -> Result<()> {
+        if *self.motion_smoothing.borrow() == enabled {
+            return Ok(());
+        }
+        self.motion_smoothing
+            .send(enabled)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_safe_mode(&mut self, enabled: Option<bool>) -> Result<()> {
+        if *self.safe_mode.borrow() == enabled {
+            return Ok(());
+        }
+        self.safe_mode
+            .send(enabled)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_ready_to_play(&mut self, readiness: Readiness) -> Result<()> {
+        if self.ready_to_play.borrow().as_ref() == Some(&readiness) {
+            return Ok(());
+        }
+        self.ready_to_play
+            .send(Some(readiness))
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_controllers_in_use(&mut self, in_use: bool) -> Result<()> {
+        if *self.controllers_in_use.borrow() == in_use {
+            return Ok(());
+        }
+        self.controllers_in_use
+            .send(in_use)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_hands_on_controllers(&mut self, held: Option<bool>) -> Result<()> {
+        if *self.hands_on_controllers.borrow() == held {
+            return Ok(());
+        }
+        self.hands_on_controllers
+            .send(held)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_display_problem(&mut self, problem: bool) -> Result<()> {
+        if *self.display_problem.borrow() == problem {
+            return Ok(());
+        }
+        self.display_problem
+            .send(problem)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_disconnects(&mut self, status: DisconnectStatus) -> Result<()> {
+        if *self.disconnects.borrow() == status {
+            return Ok(());
+        }
+        self.disconnects
+            .send(status)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_process_usage(&mut self, usage: Option<ProcessUsage>) -> Result<()> {
+        if *self.process_usage.borrow() == usage {
+            return Ok(());
+        }
+        self.process_usage
+            .send(usage)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_overlays(&mut self, overlays: OverlayStatus) -> Result<()> {
+        if *self.overlays.borrow() == overlays {
+            return Ok(());
+        }
+        self.overlays
+            .send(overlays)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_calibration(&mut self, calibration: Option<Calibration>) -> Result<()> {
+        if *self.calibration.borrow() == calibration {
+            return Ok(());
+        }
+        self.calibration
+            .send(calibration)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_process(&mut self, process: Option<ProcessInfo>) -> Result<()> {
+        if *self.process.borrow() == process {
+            return Ok(());
+        }
+        self.process
+            .send(process)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_user(&mut self, user: Option<UserInfo>) -> Result<()> {
+        if *self.user.borrow() == user {
+            return Ok(());
+        }
+        self.user
+            .send(user)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_poll_latency(&mut self, millis: Option<u64>) -> Result<()> {
+        if *self.poll_latency.borrow() == millis {
+            return Ok(());
+        }
+        self.poll_latency
+            .send(millis)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_display(&mut self, display: Option<DisplayStatus>) -> Result<()> {
+        if *self.display.borrow() == display {
+            return Ok(());
+        }
+        self.display
+            .send(display)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_drivers(&mut self, drivers: BTreeMap<String, bool>) -> Result<()> {
+        if *self.drivers.borrow() == drivers {
+            return Ok(());
+        }
+        self.drivers
+            .send(drivers)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_camera(&mut self, camera: Option<CameraStatus>) -> Result<()> {
+        if *self.camera.borrow() == camera {
+            return Ok(());
+        }
+        self.camera
+            .send(camera)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_reason(&mut self, reason: Reason) -> Result<()> {
+        if self.reason.borrow().as_ref() == Some(&reason) {
+            return Ok(());
+        }
+        self.reason
+            .send(Some(reason))
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn send_event(&mut self, event: RawEvent) -> Result<()> {
+        self.events
+            .send(event)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn send_occurrence(&mut self, occurrence: Occurrence) -> Result<()> {
+        self.occurrences
+            .send(OccurrenceEvent::new(occurrence, None))
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    /// Like `send_occurrence`, but says what caused it.
+    pub fn send_occurrence_because(
+        &mut self,
+        occurrence: Occurrence,
+        reason: &'static str,
+    ) -> Result<()> {
+        self.send_occurrence_event(OccurrenceEvent::new(occurrence, Some(reason)))
+    }
+    pub fn send_occurrence_event(&mut self, event: OccurrenceEvent) -> Result<()> {
+        self.occurrences
+            .send(event)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn add_boundary_collision(&mut self) -> Result<()> {
+        let count = *self.boundary_collisions.borrow() + 1;
+        self.boundary_collisions
+            .send(count)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_base_stations(&mut self, base_stations: BTreeMap<String, bool>) -> Result<()> {
+        if *self.base_stations.borrow() == base_stations {
+            return Ok(());
+        }
+        self.base_stations
+            .send(base_stations)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_devices(&mut self, devices: BTreeMap<String, Device>) -> Result<()> {
+        if *self.devices.borrow() == devices {
+            return Ok(());
+        }
+        self.devices
+            .send(devices)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_frame_counts(&mut self, frame_counts: Option<FrameCounts>) -> Result<()> {
+        if *self.frame_counts.borrow() == frame_counts {
+            return Ok(());
+        }
+        self.frame_counts
+            .send(frame_counts)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_frame_stats(&mut self, frame_stats: Option<FrameStats>) -> Result<()> {
+        if *self.frame_stats.borrow() == frame_stats {
+            return Ok(());
+        }
+        self.frame_stats
+            .send(frame_stats)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_scene_state(&mut self, scene_state: String) -> Result<()> {
+        if *self.scene_state.borrow() == scene_state {
+            return Ok(());
+        }
+        self.scene_state
+            .send(scene_state)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_mirror_window(&mut self, visible: bool) -> Result<()> {
+        if *self.mirror_window.borrow() == visible {
+            return Ok(());
+        }
+        self.mirror_window
+            .send(visible)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+    pub fn set_link(&mut self, link: Option<LinkStatus>) -> Result<()> {
+        if *self.link.borrow() == link {
+            return Ok(());
+        }
+        self.link
+            .send(link)
+            .map_err(|_| anyhow!("Failed to send message"))
+    }
+}
+
+#[derive(Clone)]
+pub struct State {
+    pub vr_state: tokio::sync::watch::Receiver<VrState>,
+    pub update: tokio::sync::watch::Receiver<Option<Release>>,
+    pub link: tokio::sync::watch::Receiver<Option<LinkStatus>>,
+    pub mirror_window: tokio::sync::watch::Receiver<bool>,
+    pub scene_state: tokio::sync::watch::Receiver<String>,
+    pub frame_stats: tokio::sync::watch::Receiver<Option<FrameStats>>,
+    pub frame_counts: tokio::sync::watch::Receiver<Option<FrameCounts>>,
+    pub base_stations: tokio::sync::watch::Receiver<BTreeMap<String, bool>>,
+    pub devices: tokio::sync::watch::Receiver<BTreeMap<String, Device>>,
+    pub application_key: tokio::sync::watch::Receiver<String>,
+    pub supersampling: tokio::sync::watch::Receiver<Option<f32>>,
+    /// Headset display brightness in percent, if the headset supports changing it.
+    pub brightness: tokio::sync::watch::Receiver<Option<f32>>,
+    pub standby_timeout: tokio::sync::watch::Receiver<Option<f32>>,
+    pub motion_smoothing: tokio::sync::watch::Receiver<Option<bool>>,
+    pub safe_mode: tokio::sync::watch::Receiver<Option<bool>>,
+    pub reason: tokio::sync::watch::Receiver<Option<Reason>>,
+    pub camera: tokio::sync::watch::Receiver<Option<CameraStatus>>,
+    pub drivers: tokio::sync::watch::Receiver<BTreeMap<String, bool>>,
+    pub display: tokio::sync::watch::Receiver<Option<DisplayStatus>>,
+    pub display_problem: tokio::sync::watch::Receiver<bool>,
+    pub controllers_in_use: tokio::sync::watch::Receiver<bool>,
+    pub ready_to_play: tokio::sync::watch::Receiver<Option<Readiness>>,
+    pub hands_on_controllers: tokio::sync::watch::Receiver<Option<bool>>,
+    pub calibration: tokio::sync::watch::Receiver<Option<Calibration>>,
+    pub process: tokio::sync::watch::Receiver<Option<ProcessInfo>>,
+    /// `None` while no scene application is running.
+    pub process_usage: tokio::sync::watch::Receiver<Option<ProcessUsage>>,
+    pub overlays: tokio::sync::watch::Receiver<OverlayStatus>,
+    /// Times the chaperone bounds appeared since SteamVR started.
+    pub boundary_collisions: tokio::sync::watch::Receiver<u64>,
+    /// Tracked device disconnects within `disconnects.window_secs`.
+    pub disconnects: tokio::sync::watch::Receiver<DisconnectStatus>,
+    pub user: tokio::sync::watch::Receiver<Option<UserInfo>>,
+    pub poll_latency: tokio::sync::watch::Receiver<Option<u64>>,
+    pub progress: Arc<Progress>,
+    /// The most recent error logged by any part of vr-status.
+    pub last_error: tokio::sync::watch::Receiver<Option<String>>,
+    pub locked: tokio::sync::watch::Receiver<bool>,
+    /// Only changes when SteamVR starts vr-status.
+    pub sessions: SessionCounts,
+    /// Cancelled when vr-status is shutting down so sinks can publish their final state.
+    pub shutdown: CancellationToken,
+}
+
+impl State {
+    /// The main state as JSON, for sinks that send all of it at once.
+    pub fn snapshot(&self) -> serde_json::Value {
+        let vr_state = self.vr_state.borrow();
+        serde_json::json!({
+            "power": vr_state.power,
+            "active": vr_state.active,
+            "application": vr_state.application,
+            "scene_state": *self.scene_state.borrow(),
+            "mirror_window": *self.mirror_window.borrow(),
+        })
+    }
+}
+
+/// Published unless the settings define their own derived sensor with this name.
+const RIG_AVAILABLE: &str = "rig_available";
+const RIG_AVAILABLE_EXPRESSION: &str = "power && active && !locked";
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+struct RateWindow {
+    limit: usize,
+    sent: VecDeque<Instant>,
+}
+
+impl RateWindow {
+    fn new(limit: u32) -> Self {
+        Self {
+            limit: limit as usize,
+            sent: VecDeque::new(),
+        }
+    }
+
+    fn next_allowed(&mut self, now: Instant) -> Instant {
+        while matches!(self.sent.front(), Some(sent) if *sent + RATE_WINDOW <= now) {
+            self.sent.pop_front();
+        }
+        if self.sent.len() < self.limit {
+            now
+        } else {
+            self.sent[self.sent.len() - self.limit] + RATE_WINDOW
+        }
+    }
+
+    fn record(&mut self, now: Instant) {
+        self.sent.push_back(now);
+    }
+}
+
+struct PendingMessage {
+    topic: String,
+    payload: String,
+    queued: Instant,
+}
+
+/// Holds state updates until the configured rate limits allow them to be sent.
+///
+/// Only the latest value for each topic is kept, so a burst of changes on one topic
+/// results in a single message carrying the final value.
+struct RateLimiter {
+    coalesce: Duration,
+    global: Option<RateWindow>,
+    topics: HashMap<String, RateWindow>,
+    pending: Vec<PendingMessage>,
+    /// Every topic's most recent value, sent or not, for answering queries.
+    latest: BTreeMap<String, String>,
+    timestamps: bool,
+}
+
+impl RateLimiter {
+    fn new(settings: &RateLimitSettings, topic_prefix: &str, timestamps: bool) -> Self {
+        Self {
+            coalesce: Duration::from_millis(settings.coalesce_ms),
+            global: settings.global.map(RateWindow::new),
+            topics: settings
+                .topics
+                .iter()
+                .map(|(topic, limit)| {
+                    (
+                        format!("{}/{}", topic_prefix, topic),
+                        RateWindow::new(*limit),
+                    )
+                })
+                .collect(),
+            pending: Vec::new(),
+            latest: BTreeMap::new(),
+            timestamps,
+        }
+    }
+
+    fn queue(&mut self, topic: &str, payload: String) {
+        let payload = if self.timestamps {
+            serde_json::json!({
+                "value": payload,
+                "changed_at": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            })
+            .to_string()
+        } else {
+            payload
+        };
+        self.requeue(topic, payload);
+    }
+
+    /// Queues a message taken from the limiter again, without stamping it twice.
+    fn requeue(&mut self, topic: &str, payload: String) {
+        self.latest.insert(topic.to_string(), payload.clone());
+        match self
+            .pending
+            .iter_mut()
+            .find(|pending| pending.topic == topic)
+        {
+            Some(pending) => pending.payload = payload,
+            None => self.pending.push(PendingMessage {
+                topic: topic.to_string(),
+                payload,
+                queued: Instant::now(),
+            }),
+        }
+    }
+
+    fn next_allowed(&mut self, pending: &PendingMessage, now: Instant) -> Instant {
+        let mut allowed = pending.queued + self.coalesce;
+        if let Some(global) = &mut self.global {
+            allowed = allowed.max(global.next_allowed(now));
+        }
+        if let Some(topic) = self.topics.get_mut(&pending.topic) {
+            allowed = allowed.max(topic.next_allowed(now));
+        }
+        allowed
+    }
+
+    /// Returns the earliest time a pending message could be sent, if any are pending.
+    fn next_deadline(&mut self) -> Option<Instant> {
+        let now = Instant::now();
+        let pending = std::mem::take(&mut self.pending);
+        let deadline = pending
+            .iter()
+            .map(|pending| self.next_allowed(pending, now))
+            .min();
+        self.pending = pending;
+        deadline
+    }
+
+    /// The latest value of every topic under `base_topic`, keyed by the rest of the topic.
+    fn snapshot(&self, base_topic: &str) -> serde_json::Map<String, serde_json::Value> {
+        self.latest
+            .iter()
+            .filter_map(|(topic, payload)| {
+                let key = topic.strip_prefix(base_topic)?.strip_prefix('/')?;
+                // Numbers and JSON stay structured so scripts don't have to parse twice.
+                let value = serde_json::from_str(payload)
+                    .unwrap_or_else(|_| serde_json::Value::String(payload.clone()));
+                Some((key.to_string(), value))
+            })
+            .collect()
+    }
+
+    /// Removes and returns every pending message regardless of the limits, for shutdown.
+    fn take_all(&mut self) -> Vec<(String, String)> {
+        std::mem::take(&mut self.pending)
+            .into_iter()
+            .map(|pending| (pending.topic, pending.payload))
+            .collect()
+    }
+
+    /// Removes and returns the messages that may be sent now, oldest first.
+    fn take_ready(&mut self) -> Vec<(String, String)> {
+        let now = Instant::now();
+        let mut ready = Vec::new();
+        for pending in std::mem::take(&mut self.pending) {
+            if self.next_allowed(&pending, now) <= now {
+                if let Some(global) = &mut self.global {
+                    global.record(now);
+                }
+                if let Some(topic) = self.topics.get_mut(&pending.topic) {
+                    topic.record(now);
+                }
+                ready.push((pending.topic, pending.payload));
+            } else {
+                self.pending.push(pending);
+            }
+        }
+        ready
+    }
+}
+
+/// Topics whose values are published together as one JSON snapshot instead of a message
+/// each.
+#[derive(Clone)]
+struct MetricTopics {
+    base_topic: String,
+    topics: Vec<String>,
+    metrics_topic: String,
+}
+
+impl MetricTopics {
+    /// The snapshot key for `topic`, relative to the base topic, if it's batched.
+    fn key<'a>(&self, topic: &'a str) -> Option<&'a str> {
+        let key = topic.strip_prefix(&self.base_topic)?.strip_prefix('/')?;
+        let batched = self.topics.iter().any(|batched| batched == topic)
+            || (key.starts_with("device/") && key.ends_with("/battery"));
+        if batched {
+            Some(key)
+        } else {
+            None
+        }
+    }
+
+    /// Points a discovery config at the snapshot if its state is batched.
+    #[cfg(feature = "discovery")]
+    fn rewrite_discovery(&self, config: &mut serde_json::Value) {
+        let key = match config["state_topic"]
+            .as_str()
+            .and_then(|topic| self.key(topic))
+        {
+            Some(key) => key.to_string(),
+            None => return,
+        };
+        config["state_topic"] = self.metrics_topic.clone().into();
+        config["value_template"] = format!("{{{{ value_json['{}'] }}}}", key).into();
+    }
+}
+
+/// Reads the value out of a payload stamped for `include_timestamps`. The last will can't
+/// be stamped, so bare payloads are passed through.
+#[cfg(feature = "discovery")]
+const STAMPED_VALUE: &str =
+    "(value_json.value if value_json is defined and value_json.value is defined else value)";
+
+/// Points a discovery config at the value inside stamped payloads, except on topics that
+/// don't go through the rate limiter.
+#[cfg(feature = "discovery")]
+fn unstamp_discovery(config: &mut serde_json::Value, unstamped_topics: &[&str]) {
+    let stamped = |topic: &serde_json::Value| {
+        topic
+            .as_str()
+            .map_or(false, |topic| !unstamped_topics.contains(&topic))
+    };
+    let value = format!("{{{{ {} }}}}", STAMPED_VALUE);
+    if stamped(&config["state_topic"]) {
+        config["value_template"] = match config["value_template"].as_str() {
+            Some(template) => {
+                template.replace("value_json", &format!("({} | from_json)", STAMPED_VALUE))
+            }
+            None => value.clone(),
+        }
+        .into();
+    }
+    if stamped(&config["json_attributes_topic"]) {
+        config["json_attributes_template"] = value.clone().into();
+    }
+    if let Some(availability) = config["availability"].as_array_mut() {
+        for availability in availability {
+            availability["value_template"] = value.clone().into();
+        }
+    }
+}
+
+struct MetricsBatch {
+    topics: MetricTopics,
+    values: serde_json::Map<String, serde_json::Value>,
+    changed: bool,
+}
+
+impl MetricsBatch {
+    /// Returns the snapshot if anything changed since it was last taken.
+    fn take(&mut self) -> Option<String> {
+        if !self.changed {
+            return None;
+        }
+        self.changed = false;
+        Some(serde_json::Value::Object(self.values.clone()).to_string())
+    }
+}
+
+/// Queues `payload` for `topic`, or adds it to the metrics snapshot if it's batched.
+fn queue_value(
+    limiter: &mut RateLimiter,
+    metrics: &mut Option<MetricsBatch>,
+    topic: &str,
+    payload: String,
+) {
+    match metrics {
+        Some(metrics) => match metrics.topics.key(topic) {
+            Some(key) => {
+                // Numbers stay numbers so templates can compare them.
+                let value =
+                    serde_json::from_str(&payload).unwrap_or(serde_json::Value::String(payload));
+                if metrics.values.get(key) != Some(&value) {
+                    metrics.values.insert(key.to_string(), value);
+                    metrics.changed = true;
+                }
+            }
+            None => limiter.queue(topic, payload),
+        },
+        None => limiter.queue(topic, payload),
+    }
+}
+
+/// How long a publish may wait for room in the client's queue before the broker is
+/// treated as stalled.
+const PUBLISH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Publishes like `AsyncClient::publish`, but returns `false` instead of waiting
+/// indefinitely when the broker isn't keeping up. The client's queue only holds one
+/// message, so every publish waits for the event loop to send the one before it.
+pub(crate) async fn publish(
+    client: &AsyncClient,
+    topic: &str,
+    retain: bool,
+    payload: impl Into<Vec<u8>>,
+) -> Result<bool> {
+    match tokio::time::timeout(
+        PUBLISH_TIMEOUT,
+        client.publish(topic, QoS::AtLeastOnce, retain, payload),
+    )
+    .await
+    {
+        Ok(result) => {
+            result?;
+            Ok(true)
+        }
+        Err(_) => {
+            warn!("MQTT broker stalled publishing {}", topic);
+            Ok(false)
+        }
+    }
+}
+
+/// MQTT 3.1.1 has no response topic property, so a JSON query can name one instead. Only
+/// topics under `<base>/query/` are accepted, or anyone who can send a query could have
+/// the state published over another device's topics. Anything else gets the answer on
+/// `<base>/query/response`.
+fn response_topic<'a>(request: &'a serde_json::Value, query_topic: &str) -> Option<&'a str> {
+    let topic = request["response_topic"].as_str()?;
+    let name = topic.strip_prefix(query_topic)?.strip_prefix('/')?;
+    if name.is_empty() || topic.contains(&['+', '#'][..]) {
+        return None;
+    }
+    Some(topic)
+}
+
+/// Counts publishes that timed out and queues the count for `<base>/publish_stalls`.
+struct PublishStalls {
+    topic: String,
+    count: u64,
+}
+
+impl PublishStalls {
+    fn stalled(&mut self, limiter: &mut RateLimiter) {
+        self.count += 1;
+        limiter.queue(&self.topic, self.count.to_string());
+    }
+}
+
+/// Publishes everything the rate limiter lets through and returns the topics that were
+/// sent. If the broker stalls, the rest goes back into the limiter for the next pass.
+async fn publish_ready(
+    client: &AsyncClient,
+    limiter: &mut RateLimiter,
+    stalls: &mut PublishStalls,
+) -> Result<Vec<String>> {
+    let mut published = Vec::new();
+    let mut ready = limiter.take_ready().into_iter();
+    while let Some((topic, payload)) = ready.next() {
+        if !publish(client, &topic, true, payload.clone()).await? {
+            // Keep the state for when the broker catches up.
+            for (topic, payload) in std::iter::once((topic, payload)).chain(ready) {
+                limiter.requeue(&topic, payload);
+            }
+            stalls.stalled(limiter);
+            break;
+        }
+        published.push(topic);
+    }
+    Ok(published)
+}
+
+/// Stands in for Home Assistant discovery in builds without it, where there's never
+/// anything to publish.
+#[cfg(not(feature = "discovery"))]
+async fn publish_discovery(
+    _client: &AsyncClient,
+    _registry: &mut TopicRegistry,
+    _settings: &Settings,
+    _unique_id: &str,
+    _name: &str,
+    _components: Vec<(&str, String, serde_json::Value)>,
+) -> Result<bool> {
+    Ok(true)
+}
+
+/// Publishes a one-shot occurrence. Events aren't state so they skip the rate limiter and
+/// aren't retained.
+async fn publish_occurrence(
+    client: &AsyncClient,
+    topic: &str,
+    occurrence: OccurrenceEvent,
+) -> Result<bool> {
+    publish(
+        client,
+        topic,
+        false,
+        serde_json::to_string(&occurrence.to_json()).unwrap(),
+    )
+    .await
+}
+
+/// Connection options for each broker to try, in order, connecting as `client_id`.
+pub(crate) async fn broker_options(
+    settings: &Settings,
+    client_id: &str,
+) -> Result<Vec<((String, u16), MqttOptions)>> {
+    let port = settings
+        .mqtt
+        .port
+        .unwrap_or_else(|| match settings.mqtt.transport {
+            MqttTransport::Tcp => 1883,
+            MqttTransport::Tls => 8883,
+        });
+    let transport = match settings.mqtt.transport {
+        MqttTransport::Tcp => Transport::Tcp,
+        MqttTransport::Tls => {
+            let config =
+                client_config(&settings.mqtt.tls).context("Failed to configure MQTT TLS")?;
+            Transport::Tls(TlsConfiguration::Rustls(Arc::new(config)))
+        }
+    };
+    let mut hosts = Vec::new();
+    #[cfg(feature = "mdns")]
+    if settings.mqtt.discover {
+        match discover_broker(&settings.mqtt.transport).await {
+            Ok(Some(broker)) => hosts.push(broker),
+            Ok(None) => {}
+            Err(error) => error!("Failed to discover MQTT broker: {:?}", error),
+        }
+    }
+    #[cfg(feature = "srv")]
+    if let Some(domain) = &settings.mqtt.srv_domain {
+        match lookup_brokers(domain, &settings.mqtt.transport).await {
+            Ok(brokers) => hosts.extend(brokers),
+            Err(error) => error!("Failed to look up MQTT brokers in DNS: {:?}", error),
+        }
+    }
+    hosts.extend(settings.mqtt.host.iter().map(|host| (host.clone(), port)));
+    if hosts.is_empty() {
+        bail!("No MQTT broker found and no MQTT hosts configured");
+    }
+    if settings.mqtt.ip_family != IpFamily::Auto {
+        let mut resolved = Vec::new();
+        for (host, port) in hosts {
+            match resolve_family(&host, port, settings.mqtt.ip_family).await {
+                Ok(address) => resolved.push((address, port)),
+                Err(error) => error!("Skipping MQTT host {}: {:?}", host, error),
+            }
+        }
+        if resolved.is_empty() {
+            bail!("None of the MQTT hosts have an address in the configured ip_family");
+        }
+        hosts = resolved;
+    }
+    Ok(hosts
+        .into_iter()
+        .map(|(host, port)| {
+            let mut mqtt_options = MqttOptions::new(client_id, &host, port);
+            mqtt_options.set_transport(transport.clone());
+            if let Some(credentials) = &settings.mqtt.credentials {
+                mqtt_options.set_credentials(&credentials.username, &credentials.password);
+            }
+            ((host, port), mqtt_options)
+        })
+        .collect())
+}
+
+/// Resolves `host` to its first address in `family`, so the connection can't fall back to the
+/// other one.
+async fn resolve_family(host: &str, port: u16, family: IpFamily) -> Result<String> {
+    let address = tokio::net::lookup_host((host, port))
+        .await
+        .context("Failed to resolve host")?
+        .find(|address| match family {
+            IpFamily::Auto => true,
+            IpFamily::V4 => address.is_ipv4(),
+            IpFamily::V6 => address.is_ipv6(),
+        })
+        .ok_or_else(|| anyhow!("No address in the configured ip_family"))?;
+    Ok(address.ip().to_string())
+}
+
+async fn probe_broker(host: String, port: u16, healthy: Arc<AtomicBool>) {
+    const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+    if let Ok(Ok(_)) = tokio::time::timeout(
+        PROBE_TIMEOUT,
+        tokio::net::TcpStream::connect((host.as_str(), port)),
+    )
+    .await
+    {
+        healthy.store(true, Ordering::Relaxed);
+    }
+}
+
+pub async fn mqtt_loop(
+    settings: &Settings,
+    unique_id: &str,
+    mut history: History,
+    mut stats: Option<UsageStats>,
+    mut registry: TopicRegistry,
+    mut state: State,
+    mut events: tokio::sync::mpsc::UnboundedReceiver<RawEvent>,
+    mut occurrences: tokio::sync::mpsc::UnboundedReceiver<OccurrenceEvent>,
+    commands: tokio::sync::mpsc::UnboundedSender<Command>,
+) -> Result<()> {
+    let base_topic = settings.base_topic();
+    let power_topic = format!("{}/power", base_topic);
+    let active_topic = format!("{}/active", base_topic);
+    let application_topic = format!("{}/application", base_topic);
+    let application_key_topic = format!("{}/application_key", base_topic);
+    let update_topic = format!("{}/update", base_topic);
+    let link_quality_topic = format!("{}/link_quality", base_topic);
+    let link_problem_topic = format!("{}/link_problem", base_topic);
+    let mirror_window_topic = format!("{}/mirror_window", base_topic);
+    let scene_state_topic = format!("{}/scene_state", base_topic);
+    let application_elapsed_topic = format!("{}/application_elapsed", base_topic);
+    let vr_time_today_topic = format!("{}/vr_time_today", base_topic);
+    let vr_time_week_topic = format!("{}/vr_time_week", base_topic);
+    let supersampling_topic = format!("{}/supersampling", base_topic);
+    let brightness_topic = format!("{}/brightness", base_topic);
+    let standby_timeout_topic = format!("{}/standby_timeout", base_topic);
+    let motion_smoothing_topic = format!("{}/motion_smoothing", base_topic);
+    let reason_topic = format!("{}/reason", base_topic);
+    let process_topic = format!("{}/application/process", base_topic);
+    let application_pid_topic = format!("{}/application/pid", base_topic);
+    let application_cpu_topic = format!("{}/application/cpu", base_topic);
+    let application_memory_topic = format!("{}/application/memory", base_topic);
+    let steamvr_restarts_topic = format!("{}/steamvr_restarts", base_topic);
+    let boundary_collisions_topic = format!("{}/boundary_collisions", base_topic);
+    let user_topic = format!("{}/user", base_topic);
+    let steam_account_topic = format!("{}/steam_account", base_topic);
+    let steamvr_crashes_topic = format!("{}/steamvr_crashes", base_topic);
+    let display_frequency_topic = format!("{}/display_frequency", base_topic);
+    let direct_mode_topic = format!("{}/direct_mode", base_topic);
+    let extended_mode_topic = format!("{}/extended_mode", base_topic);
+    let safe_mode_topic = format!("{}/safe_mode", base_topic);
+    let simulated_topic = format!("{}/simulated", base_topic);
+    let display_problem_topic = format!("{}/display_problem", base_topic);
+    let gpu_topic = format!("{}/gpu", base_topic);
+    let controllers_in_use_topic = format!("{}/controllers_in_use", base_topic);
+    let ready_to_play_topic = format!("{}/ready_to_play", base_topic);
+    let ready_to_play_blockers_topic = format!("{}/ready_to_play/blockers", base_topic);
+    let hands_on_controllers_topic = format!("{}/hands_on_controllers", base_topic);
+    let device_disconnects_topic = format!("{}/device_disconnects", base_topic);
+    let device_problem_topic = format!("{}/device_problem", base_topic);
+    let calibration_topic = format!("{}/calibration", base_topic);
+    let room_setup_needed_topic = format!("{}/room_setup_needed", base_topic);
+    let render_width_topic = format!("{}/render_width", base_topic);
+    let render_height_topic = format!("{}/render_height", base_topic);
+    let drivers_topic = format!("{}/drivers", base_topic);
+    let overlays_topic = format!("{}/overlays", base_topic);
+    let camera_available_topic = format!("{}/camera_available", base_topic);
+    let camera_active_topic = format!("{}/camera_active", base_topic);
+    let locked_topic = format!("{}/locked", base_topic);
+    let raw_events_topic = format!("{}/events/raw", base_topic);
+    let occurrences_topic = format!("{}/events/occurrence", base_topic);
+    let gpu_frame_time_topic = format!("{}/gpu_frame_time", base_topic);
+    let cpu_frame_time_topic = format!("{}/cpu_frame_time", base_topic);
+    let frame_budget_topic = format!("{}/frame_budget", base_topic);
+    let async_reprojection_topic = format!("{}/async_reprojection", base_topic);
+    let interleaved_reprojection_topic = format!("{}/interleaved_reprojection", base_topic);
+    let dropped_frames_topic = format!("{}/dropped_frames", base_topic);
+    let reprojected_frames_topic = format!("{}/reprojected_frames", base_topic);
+    let battery_min_topic = format!("{}/battery_min", base_topic);
+    let battery_min_device_topic = format!("{}/battery_min/device", base_topic);
+    let metrics_topic = format!("{}/metrics", base_topic);
+    let publish_stalls_topic = format!("{}/publish_stalls", base_topic);
+    let mqtt_reconnects_topic = format!("{}/mqtt_reconnects", base_topic);
+    let last_error_topic = format!("{}/last_error", base_topic);
+    let poll_latency_topic = format!("{}/poll_latency", base_topic);
+    let started_topic = format!("{}/started", base_topic);
+    let name_topic = format!("{}/name", base_topic);
+    let query_topic = format!("{}/query", base_topic);
+    let query_response_topic = format!("{}/response", query_topic);
+    let in_game_topic = format!("{}/in_game", base_topic);
+    let fleet_topic = format!("{}/fleet", settings.prefix);
+    let aggregate = settings
+        .arcade
+        .as_ref()
+        .map_or(false, |arcade| arcade.aggregate);
+
+    let power_payload = settings.payloads.get("power");
+    let active_payload = settings.payloads.get("active");
+    let link_problem_payload = settings.payloads.get("link_problem");
+    let mirror_window_payload = settings.payloads.get("mirror_window");
+    let motion_smoothing_payload = settings.payloads.get("motion_smoothing");
+    let base_station_payload = settings.payloads.get("base_station");
+    let camera_available_payload = settings.payloads.get("camera_available");
+    let camera_active_payload = settings.payloads.get("camera_active");
+    let direct_mode_payload = settings.payloads.get("direct_mode");
+    let extended_mode_payload = settings.payloads.get("extended_mode");
+    let safe_mode_payload = settings.payloads.get("safe_mode");
+    let simulated_payload = settings.payloads.get("simulated");
+    let display_problem_payload = settings.payloads.get("display_problem");
+    let controllers_in_use_payload = settings.payloads.get("controllers_in_use");
+    let ready_to_play_payload = settings.payloads.get("ready_to_play");
+    let hands_on_controllers_payload = settings.payloads.get("hands_on_controllers");
+    let formats = Formats::new(settings);
+    let async_reprojection_payload = settings.payloads.get("async_reprojection");
+    let interleaved_reprojection_payload = settings.payloads.get("interleaved_reprojection");
+    let device_problem_payload = settings.payloads.get("device_problem");
+    let room_setup_needed_payload = settings.payloads.get("room_setup_needed");
+    let locked_payload = settings.payloads.get("locked");
+    let in_game_payload = settings.payloads.get("in_game");
+
+    let idle_value = settings
+        .idle_application
+        .as_ref()
+        .map(|idle| idle.value.as_str());
+    // Derived sensors and local sinks still see the real application.
+    let private = |application: &str| match &settings.privacy {
+        Some(privacy) if Some(application) != idle_value => anonymize(privacy, application),
+        _ => application.to_string(),
+    };
+    let reason_payload = |reason: &Reason| {
+        let mut json = reason.to_json();
+        if let Some(application) = json["application"].as_str() {
+            json["application"] = private(application).into();
+        }
+        json.to_string()
+    };
+
+    let mut derived = Vec::new();
+    if !settings.derived.contains_key(RIG_AVAILABLE) {
+        let topic = format!("{}/{}", base_topic, RIG_AVAILABLE);
+        derived.push((
+            RIG_AVAILABLE,
+            topic,
+            Expression::parse(RIG_AVAILABLE_EXPRESSION).unwrap(),
+            settings.payloads.get(RIG_AVAILABLE),
+            None,
+        ));
+    }
+    for (name, source) in &settings.derived {
+        let expression = Expression::parse(source)
+            .with_context(|| format!("Invalid derived sensor {}", name))?;
+        let topic = format!("{}/{}", base_topic, name);
+        derived.push((
+            name.as_str(),
+            topic,
+            expression,
+            settings.payloads.get(name),
+            None,
+        ));
+    }
+
+    let hosts = broker_options(settings, &settings.id).await?;
+    let host_options: Vec<_> = hosts
+        .iter()
+        .map(|(_, mqtt_options)| {
+            let mut mqtt_options = mqtt_options.clone();
+            mqtt_options.set_last_will(LastWill::new(
+                &power_topic,
+                power_payload.off.as_str(),
+                QoS::AtLeastOnce,
+                true,
+            ));
+            mqtt_options
+        })
+        .collect();
+    let (primary_host, primary_port) = hosts[0].0.clone();
+    let primary_healthy = Arc::new(AtomicBool::new(false));
+
+    // Set capacity to 1.
+    // Backpressure is handled more intelligently and for this application it just
+    // doesn't make sense to buffer multiple values for the same topic.
+    let (client, mut event_loop) = AsyncClient::new(host_options[0].clone(), 1);
+
+    let (connect_send, mut connect_receive) = tokio::sync::mpsc::channel(1);
+    let command_base_topic = base_topic.clone();
+    let command_payloads = settings.payloads.clone();
+    let station_prefix = format!("{}/stations/", settings.prefix);
+    let (fleet_send, mut fleet_receive) = tokio::sync::mpsc::unbounded_channel();
+    // Renaming changes discovery, which is published from the main loop rather than the
+    // OpenVR loop that handles other commands.
+    let rename_topic = format!("{}/set", name_topic);
+    let (rename_send, mut rename_receive) = tokio::sync::mpsc::unbounded_channel();
+    let event_query_topic = query_topic.clone();
+    let (query_send, mut query_receive) = tokio::sync::mpsc::unbounded_channel();
+    let failback_client = client.clone();
+    let event_loop = tokio::spawn(async move {
+        // Keep this separate from the `publish(..).await`s.
+        // There's an in-memory queue that holds messages until they are dispatched from
+        // this coroutine. If that queue fills up, `publish(..).await` will pause the
+        // coroutine until this coroutine makes progress emptying the queue. If they're
+        // the same coroutine the code will deadlock as soon as the queue overflows.
+        const MIN_DELAY: Duration = Duration::from_secs(1);
+        const FAILBACK_INTERVAL: Duration = Duration::from_secs(60);
+        let mut start = Instant::now();
+        let mut stop = false;
+        let mut current_host = 0;
+        let mut failing_back = false;
+        let mut last_probe = Instant::now();
+        loop {
+            let result = event_loop.poll().await;
+
+            // While connected to a backup broker, check whether the primary is reachable
+            // again and if so disconnect so the next connection attempt goes there.
+            if current_host != 0 && !failing_back {
+                if primary_healthy.swap(false, Ordering::Relaxed) {
+                    info!("Primary MQTT broker is reachable again");
+                    current_host = 0;
+                    event_loop.options = host_options[0].clone();
+                    failing_back = failback_client.try_disconnect().is_ok();
+                } else if last_probe.elapsed() >= FAILBACK_INTERVAL {
+                    last_probe = Instant::now();
+                    tokio::spawn(probe_broker(
+                        primary_host.clone(),
+                        primary_port,
+                        primary_healthy.clone(),
+                    ));
+                }
+            }
+
+            match result {
+                Ok(Event::Incoming(Packet::ConnAck(ConnAck {
+                    code: rumqttc::ConnectReturnCode::Success,
+                    ..
+                }))) => {
+                    info!("MQTT connected");
+                    // LWT sets power to off on disconnect so we need to set power to on
+                    // after every connect.
+                    // Don't do it from this coroutine or the code can deadlock.
+                    let _ = connect_send.try_send(());
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    let update = if aggregate {
+                        StationUpdate::parse(&station_prefix, &publish.topic, &publish.payload)
+                    } else {
+                        None
+                    };
+                    if let Some(update) = update {
+                        let _ = fleet_send.send(update);
+                    } else if publish.topic == rename_topic {
+                        let name = String::from_utf8_lossy(&publish.payload).trim().to_string();
+                        let _ = rename_send.send(name);
+                    } else if publish.topic == event_query_topic {
+                        let _ = query_send.send(publish.payload);
+                    } else {
+                        match parse_command(
+                            &command_base_topic,
+                            &publish.topic,
+                            &publish.payload,
+                            &command_payloads,
+                        ) {
+                            Some(command) => {
+                                let _ = commands.send(command);
+                            }
+                            None => error!("Unrecognized command on {}", publish.topic),
+                        }
+                    }
+                }
+                Ok(Event::Outgoing(Outgoing::Disconnect)) => {
+                    if !failing_back {
+                        stop = true;
+                    }
+                }
+                Ok(_) => {}
+                Err(error) => {
+                    if stop {
+                        break;
+                    }
+                    if failing_back {
+                        failing_back = false;
+                    } else {
+                        error!("MQTT error: {:?}", error);
+                        if host_options.len() > 1 {
+                            current_host = (current_host + 1) % host_options.len();
+                            info!("Trying MQTT broker {}", current_host + 1);
+                            event_loop.options = host_options[current_host].clone();
+                            last_probe = Instant::now();
+                        }
+                    }
+
+                    // Wait so we don't flood the network with requests and then try again.
+                    let elapsed = start.elapsed();
+                    if elapsed < MIN_DELAY {
+                        tokio::time::sleep(MIN_DELAY - elapsed).await;
+                    }
+                    start = Instant::now();
+                }
+            }
+        }
+    });
+
+    let metric_topics = settings.metrics.as_ref().map(|_| MetricTopics {
+        base_topic: base_topic.clone(),
+        topics: vec![
+            gpu_frame_time_topic.clone(),
+            cpu_frame_time_topic.clone(),
+            frame_budget_topic.clone(),
+            dropped_frames_topic.clone(),
+            reprojected_frames_topic.clone(),
+            battery_min_topic.clone(),
+        ],
+        metrics_topic: metrics_topic.clone(),
+    });
+    #[cfg(feature = "discovery")]
+    let all_components = {
+        let base_topic = base_topic.clone();
+        let base_station_payload = base_station_payload.clone();
+        let occurrences_topic = occurrences_topic.clone();
+        let metric_topics = metric_topics.clone();
+        let availability = serde_json::json!([{
+            "topic": &power_topic,
+            "payload_available": &power_payload.on,
+            "payload_not_available": &power_payload.off,
+        }]);
+        let names = EntityNames::new(settings);
+        let formats = Formats::new(settings);
+        let mut components = vec![
+            (
+                "binary_sensor",
+                "power".to_string(),
+                serde_json::json!({
+                    "name": names.get("power", "Power"),
+                    "unique_id": format!("{}_power", unique_id),
+                    "device_class": "power",
+                    "state_topic": &power_topic,
+                    "json_attributes_topic": &reason_topic,
+                    "payload_on": &power_payload.on,
+                    "payload_off": &power_payload.off,
+                }),
+            ),
+            (
+                "binary_sensor",
+                "active".to_string(),
+                serde_json::json!({
+                    "name": names.get("active", "Active"),
+                    "unique_id": format!("{}_active", unique_id),
+                    "device_class": "moving",
+                    "state_topic": &active_topic,
+                    "json_attributes_topic": &reason_topic,
+                    "payload_on": &active_payload.on,
+                    "payload_off": &active_payload.off,
+                    "availability": &availability,
+                }),
+            ),
+            (
+                "binary_sensor",
+                "in_game".to_string(),
+                serde_json::json!({
+                    "name": names.get("in_game", "In Game"),
+                    "unique_id": format!("{}_in_game", unique_id),
+                    "icon": "mdi:gamepad-variant",
+                    "state_topic": &in_game_topic,
+                    "payload_on": &in_game_payload.on,
+                    "payload_off": &in_game_payload.off,
+                    "availability": &availability,
+                }),
+            ),
+            (
+                "event",
+                "occurrence".to_string(),
+                serde_json::json!({
+                    "name": names.get("occurrence", "Event"),
+                    "unique_id": format!("{}_occurrence", unique_id),
+                    "state_topic": &occurrences_topic,
+                    "event_types": Occurrence::ALL
+                        .iter()
+                        .map(|occurrence| occurrence.name())
+                        .collect::<Vec<_>>(),
+                    "availability": &availability,
+                }),
+            ),
+            (
+                "sensor",
+                "application".to_string(),
+                serde_json::json!({
+                    "name": names.get("application", "Application"),
+                    "unique_id": format!("{}_application", unique_id),
+                    "state_topic": &application_topic,
+                    "json_attributes_topic": &process_topic,
+                    "availability": &availability,
+                }),
+            ),
+            (
+                "sensor",
+                "application_key".to_string(),
+                serde_json::json!({
+                    "name": names.get("application_key", "Application Key"),
+                    "unique_id": format!("{}_application_key", unique_id),
+                    "state_topic": &application_key_topic,
+                    "availability": &availability,
+                }),
+            ),
+            (
+                "sensor",
+                "user".to_string(),
+                serde_json::json!({
+                    "name": names.get("user", "Windows User"),
+                    "unique_id": format!("{}_user", unique_id),
+                    "state_topic": &user_topic,
+                }),
+            ),
+            (
+                "sensor",
+                "steam_account".to_string(),
+                serde_json::json!({
+                    "name": names.get("steam_account", "Steam Account"),
+                    "unique_id": format!("{}_steam_account", unique_id),
+                    "state_topic": &steam_account_topic,
+                }),
+            ),
+            (
+                "text",
+                "name".to_string(),
+                serde_json::json!({
+                    "name": names.get("name", "Name"),
+                    "unique_id": format!("{}_name", unique_id),
+                    "entity_category": "config",
+                    "state_topic": &name_topic,
+                    "command_topic": format!("{}/set", name_topic),
+                    "min": 1,
+                    "max": 255,
+                }),
+            ),
+            (
+                "button",
+                "quit".to_string(),
+                serde_json::json!({
+                    "name": names.get("quit", "Quit"),
+                    "unique_id": format!("{}_quit", unique_id),
+                    "entity_category": "config",
+                    "command_topic": format!("{}/quit/set", base_topic),
+                    "availability": &availability,
+                }),
+            ),
+            (
+                "number",
+                "supersampling".to_string(),
+                serde_json::json!({
+                    "name": names.get("supersampling", "Supersampling"),
+                    "unique_id": format!("{}_supersampling", unique_id),
+                    "state_topic": &supersampling_topic,
+                    "command_topic": format!("{}/set", supersampling_topic),
+                    "min": 0.2,
+                    "max": 5.0,
+                    "step": 0.05,
+                    "availability": &availability,
+                }),
+            ),
+            (
+                "number",
+                "brightness".to_string(),
+                serde_json::json!({
+                    "name": names.get("brightness", "Brightness"),
+                    "unique_id": format!("{}_brightness", unique_id),
+                    "icon": "mdi:brightness-6",
+                    "unit_of_measurement": "%",
+                    "state_topic": &brightness_topic,
+                    "command_topic": format!("{}/set", brightness_topic),
+                    "min": 10,
+                    "max": 160,
+                    "step": 5,
+                    "availability": &availability,
+                }),
+            ),
+            (
+                "number",
+                "standby_timeout".to_string(),
+                serde_json::json!({
+                    "name": names.get("standby_timeout", "Standby Timeout"),
+                    "unique_id": format!("{}_standby_timeout", unique_id),
+                    "entity_category": "config",
+                    "device_class": "duration",
+                    "unit_of_measurement": "s",
+                    "state_topic": &standby_timeout_topic,
+                    "command_topic": format!("{}/set", standby_timeout_topic),
+                    "min": 0,
+                    "max": 3600,
+                    "step": 1,
+                    "availability": &availability,
+                }),
+            ),
+            (
+                "sensor",
+                "scene_state".to_string(),
+                serde_json::json!({
+                    "name": names.get("scene_state", "Scene Application State"),
+                    "unique_id": format!("{}_scene_state", unique_id),
+                    "entity_category": "diagnostic",
+                    "state_topic": &scene_state_topic,
+                    "availability": &availability,
+                }),
+            ),
+            (
+                "sensor",
+                "drivers".to_string(),
+                serde_json::json!({
+                    "name": names.get("drivers", "Enabled Drivers"),
+                    "unique_id": format!("{}_drivers", unique_id),
+                    "entity_category": "diagnostic",
+                    "state_topic": &drivers_topic,
+                    "json_attributes_topic": format!("{}/attributes", drivers_topic),
+                    "availability": &availability,
+                }),
+            ),
+            (
+                "binary_sensor",
+                "direct_mode".to_string(),
+                serde_json::json!({
+                    "name": names.get("direct_mode", "Direct Mode"),
+                    "unique_id": format!("{}_direct_mode", unique_id),
+                    "entity_category": "diagnostic",
+                    "state_topic": &direct_mode_topic,
+                    "payload_on": &direct_mode_payload.on,
+                    "payload_off": &direct_mode_payload.off,
+                    "availability": &availability,
+                }),
+            ),
+            (
+                "binary_sensor",
+                "extended_mode".to_string(),
+                serde_json::json!({
+                    "name": names.get("extended_mode", "Extended Display Mode"),
+                    "unique_id": format!("{}_extended_mode", unique_id),
+                    "device_class": "problem",
+                    "entity_category": "diagnostic",
+                    "state_topic": &extended_mode_topic,
+                    "payload_on": &extended_mode_payload.on,
+                    "payload_off": &extended_mode_payload.off,
+                    "availability": &availability,
+                }),
+            ),
+            (
+                "binary_sensor",
+                "safe_mode".to_string(),
+                serde_json::json!({
+                    "name": names.get("safe_mode", "Safe Mode"),
+                    "unique_id": format!("{}_safe_mode", unique_id),
+                    "device_class": "problem",
+                    "entity_category": "diagnostic",
+                    "state_topic": &safe_mode_topic,
+                    "payload_on": &safe_mode_payload.on,
+                    "payload_off": &safe_mode_payload.off,
+                    "availability": &availability,
+                }),
+            ),
+            (
+                "binary_sensor",
+                "simulated".to_string(),
+                serde_json::json!({
+                    "name": names.get("simulated", "Simulated Headset"),
+                    "unique_id": format!("{}_simulated", unique_id),
+                    "entity_category": "diagnostic",
+                    "state_topic": &simulated_topic,
+                    "payload_on": &simulated_payload.on,
+                    "payload_off": &simulated_payload.off,
+                    "availability": &availability,
+                }),
+            ),
+            (
+                "binary_sensor",
+                "display_problem".to_string(),
+                serde_json::json!({
+                    "name": names.get("display_problem", "Display Problem"),
+                    "unique_id": format!("{}_display_problem", unique_id),
+                    "device_class": "problem",
+                    "state_topic": &display_problem_topic,
+                    "payload_on": &display_problem_payload.on,
+                    "payload_off": &display_problem_payload.off,
+                    "availability": &availability,
+                }),
+            ),
+            (
+                "binary_sensor",
+                "device_problem".to_string(),
+                serde_json::json!({
+                    "name": names.get("device_problem", "Device Problem"),
+                    "unique_id": format!("{}_device_problem", unique_id),
+                    "device_class": "problem",
+                    "state_topic": &device_problem_topic,
+                    "payload_on": &device_problem_payload.on,
+                    "payload_off": &device_problem_payload.off,
+                    "availability": &availability,
+                }),
+            ),
+            (
+                "sensor",
+                "calibration".to_string(),
+                serde_json::json!({
+                    "name": names.get("calibration", "Room Setup"),
+                    "unique_id": format!("{}_calibration", unique_id),
+                    "device_class": "enum",
+                    "options": Calibration::ALL.iter().map(Calibration::name).collect::<Vec<_>>(),
+                    "entity_category": "diagnostic",
+                    "state_topic": &calibration_topic,
+                    "availability": &availability,
+                }),
+            ),
+            (
+                "binary_sensor",
+                "room_setup_needed".to_string(),
+                serde_json::json!({
+                    "name": names.get("room_setup_needed", "Room Setup Needed"),
+                    "unique_id": format!("{}_room_setup_needed", unique_id),
+                    "device_class": "problem",
+                    "state_topic": &room_setup_needed_topic,
+                    "payload_on": &room_setup_needed_payload.on,
+                    "payload_off": &room_setup_needed_payload.off,
+                    "availability": &availability,
+                }),
+            ),
+            (
+                "binary_sensor",
+                "camera_available".to_string(),
+                serde_json::json!({
+                    "name": names.get("camera_available", "Camera Available"),
+                    "unique_id": format!("{}_camera_available", unique_id),
+                    "entity_category": "diagnostic",
+                    "state_topic": &camera_available_topic,
+                    "payload_on": &camera_available_payload.on,
+                    "payload_off": &camera_available_payload.off,
+                    "availability": &availability,
+                }),
+            ),
+            (
+                "binary_sensor",
+                "camera_active".to_string(),
+                serde_json::json!({
+                    "name": names.get("camera_active", "Camera Active"),
+                    "unique_id": format!("{}_camera_active", unique_id),
+                    "state_topic": &camera_active_topic,
+                    "payload_on": &camera_active_payload.on,
+                    "payload_off": &camera_active_payload.off,
+                    "availability": &availability,
+                }),
+            ),
+            (
+                "binary_sensor",
+                "locked".to_string(),
+                serde_json::json!({
+                    "name": names.get("locked", "Locked"),
+                    "unique_id": format!("{}_locked", unique_id),
+                    "state_topic": &locked_topic,
+                    "payload_on": &locked_payload.on,
+                    "payload_off": &locked_payload.off,
+                }),
+            ),
+            (
+                "switch",
+                "motion_smoothing".to_string(),
+                serde_json::json!({
+                    "name": names.get("motion_smoothing", "Motion Smoothing"),
+                    "unique_id": format!("{}_motion_smoothing", unique_id),
+                    "state_topic": &motion_smoothing_topic,
+                    "command_topic": format!("{}/set", motion_smoothing_topic),
+                    "payload_on": &motion_smoothing_payload.on,
+                    "payload_off": &motion_smoothing_payload.off,
+                    "state_on": &motion_smoothing_payload.on,
+                    "state_off": &motion_smoothing_payload.off,
+                    "availability": &availability,
+                }),
+            ),
+            (
+                "switch",
+                "mirror_window".to_string(),
+                serde_json::json!({
+                    "name": names.get("mirror_window", "Mirror Window"),
+                    "unique_id": format!("{}_mirror_window", unique_id),
+                    "state_topic": &mirror_window_topic,
+                    "command_topic": format!("{}/set", mirror_window_topic),
+                    "payload_on": &mirror_window_payload.on,
+                    "payload_off": &mirror_window_payload.off,
+                    "state_on": &mirror_window_payload.on,
+                    "state_off": &mirror_window_payload.off,
+                    "availability": &availability,
+                }),
+            ),
+        ];
+        if settings.devices.is_some() {
+            components.push(
+                DiscoveryEntity::sensor(
+                    "battery_min",
+                    names.get("battery_min", "Lowest Battery"),
+                    unique_id,
+                    &battery_min_topic,
+                    StateClass::Measurement,
+                )
+                .device_class("battery")
+                .unit("%", 0)
+                .formatted(&formats, "battery_min")
+                .attributes_topic(&battery_min_device_topic)
+                .availability(&availability)
+                .build(),
+            );
+        }
+        for (role, name) in &[("left", "Left"), ("right", "Right")] {
+            components.push((
+                "button",
+                format!("identify_{}", role),
+                serde_json::json!({
+                    "name": names.get(
+                        &format!("identify_{}", role),
+                        &format!("Identify {} Controller", name),
+                    ),
+                    "unique_id": format!("{}_identify_{}", unique_id, role),
+                    "device_class": "identify",
+                    "command_topic": format!("{}/identify/{}/set", base_topic, role),
+                    "availability": &availability,
+                }),
+            ));
+        }
+        if settings.updater.is_some() {
+            components.push((
+                "update",
+                "update".to_string(),
+                serde_json::json!({
+                    "name": names.get("update", "Update"),
+                    "unique_id": format!("{}_update", unique_id),
+                    "state_topic": &update_topic,
+                }),
+            ));
+        }
+        if settings.wireless.is_some() {
+            components.push(
+                DiscoveryEntity::sensor(
+                    "link_quality",
+                    names.get("link_quality", "Link Quality"),
+                    unique_id,
+                    &link_quality_topic,
+                    StateClass::Measurement,
+                )
+                .formatted(&formats, "link_quality")
+                .availability(&availability)
+                .build(),
+            );
+            components.push((
+                "binary_sensor",
+                "link_problem".to_string(),
+                serde_json::json!({
+                    "name": names.get("link_problem", "Link Problem"),
+                    "unique_id": format!("{}_link_problem", unique_id),
+                    "device_class": "problem",
+                    "state_topic": &link_problem_topic,
+                    "payload_on": &link_problem_payload.on,
+                    "payload_off": &link_problem_payload.off,
+                    "availability": &availability,
+                }),
+            ));
+        }
+        components.extend(Occurrence::TRIGGERS.iter().map(
+            |(occurrence, trigger_type, subtype)| {
+                (
+                    "device_automation",
+                    format!("trigger_{}", occurrence.name()),
+                    serde_json::json!({
+                        "automation_type": "trigger",
+                        "topic": &occurrences_topic,
+                        "type": trigger_type,
+                        "subtype": subtype,
+                        "value_template": "{{ value_json.event_type }}",
+                        "payload": occurrence.name(),
+                    }),
+                )
+            },
+        ));
+        if settings.application_elapsed.is_some() {
+            components.push(
+                DiscoveryEntity::sensor(
+                    "application_elapsed",
+                    names.get("application_elapsed", "Application Elapsed"),
+                    unique_id,
+                    &application_elapsed_topic,
+                    StateClass::Measurement,
+                )
+                .device_class("duration")
+                .unit("s", 0)
+                .formatted(&formats, "application_elapsed")
+                .availability(&availability)
+                .build(),
+            );
+        }
+        if settings.stats.is_some() {
+            for (topic, object_id, name) in &[
+                (&vr_time_today_topic, "vr_time_today", "VR Time Today"),
+                (&vr_time_week_topic, "vr_time_week", "VR Time This Week"),
+            ] {
+                // Unlike the headset sensors these stay meaningful while it's off.
+                components.push(
+                    DiscoveryEntity::sensor(
+                        *object_id,
+                        names.get(object_id, name),
+                        unique_id,
+                        topic.as_str(),
+                        StateClass::TotalIncreasing,
+                    )
+                    .device_class("duration")
+                    .unit("min", 0)
+                    .formatted(&formats, object_id)
+                    .build(),
+                );
+            }
+        }
+        if settings.ready_to_play.is_some() {
+            components.push((
+                "binary_sensor",
+                "ready_to_play".to_string(),
+                serde_json::json!({
+                    "name": names.get("ready_to_play", "Ready To Play"),
+                    "unique_id": format!("{}_ready_to_play", unique_id),
+                    "icon": "mdi:controller",
+                    "state_topic": &ready_to_play_topic,
+                    "json_attributes_topic": &ready_to_play_blockers_topic,
+                    "payload_on": &ready_to_play_payload.on,
+                    "payload_off": &ready_to_play_payload.off,
+                    "availability": &availability,
+                }),
+            ));
+        }
+        if settings.controllers_in_use.is_some() {
+            components.push((
+                "binary_sensor",
+                "controllers_in_use".to_string(),
+                serde_json::json!({
+                    "name": names.get("controllers_in_use", "Controllers In Use"),
+                    "unique_id": format!("{}_controllers_in_use", unique_id),
+                    "state_topic": &controllers_in_use_topic,
+                    "payload_on": &controllers_in_use_payload.on,
+                    "payload_off": &controllers_in_use_payload.off,
+                    "availability": &availability,
+                }),
+            ));
+        }
+        components.push((
+            "binary_sensor",
+            "hands_on_controllers".to_string(),
+            serde_json::json!({
+                "name": names.get("hands_on_controllers", "Hands On Controllers"),
+                "unique_id": format!("{}_hands_on_controllers", unique_id),
+                "icon": "mdi:hand-back-right",
+                "state_topic": &hands_on_controllers_topic,
+                "payload_on": &hands_on_controllers_payload.on,
+                "payload_off": &hands_on_controllers_payload.off,
+                "availability": &availability,
+            }),
+        ));
+        if settings.overlays.is_some() {
+            components.push((
+                "sensor",
+                "overlays".to_string(),
+                serde_json::json!({
+                    "name": names.get("overlays", "Visible Overlays"),
+                    "unique_id": format!("{}_overlays", unique_id),
+                    "state_topic": &overlays_topic,
+                    "json_attributes_topic": format!("{}/attributes", overlays_topic),
+                    "availability": &availability,
+                }),
+            ));
+        }
+        if settings.process_usage.is_some() {
+            components.push((
+                "sensor",
+                "application_pid".to_string(),
+                serde_json::json!({
+                    "name": names.get("application_pid", "Application PID"),
+                    "unique_id": format!("{}_application_pid", unique_id),
+                    "entity_category": "diagnostic",
+                    "state_topic": &application_pid_topic,
+                    "availability": &availability,
+                }),
+            ));
+            components.push(
+                DiscoveryEntity::sensor(
+                    "application_cpu",
+                    names.get("application_cpu", "Application CPU"),
+                    unique_id,
+                    &application_cpu_topic,
+                    StateClass::Measurement,
+                )
+                .unit("%", 1)
+                .formatted(&formats, "application_cpu")
+                .availability(&availability)
+                .build(),
+            );
+            components.push(
+                DiscoveryEntity::sensor(
+                    "application_memory",
+                    names.get("application_memory", "Application Memory"),
+                    unique_id,
+                    &application_memory_topic,
+                    StateClass::Measurement,
+                )
+                .device_class("data_size")
+                .unit("MiB", 0)
+                .formatted(&formats, "application_memory")
+                .availability(&availability)
+                .build(),
+            );
+        }
+        if settings.frame_timing.is_some() {
+            for (topic, object_id, name) in &[
+                (&gpu_frame_time_topic, "gpu_frame_time", "GPU Frame Time"),
+                (&cpu_frame_time_topic, "cpu_frame_time", "CPU Frame Time"),
+                (&frame_budget_topic, "frame_budget", "Frame Budget Used"),
+            ] {
+                components.push(
+                    DiscoveryEntity::sensor(
+                        *object_id,
+                        names.get(object_id, name),
+                        unique_id,
+                        *topic,
+                        StateClass::Measurement,
+                    )
+                    .formatted(&formats, object_id)
+                    .availability(&availability)
+                    .build(),
+                );
+            }
+            for (topic, object_id, name, payload) in &[
+                (
+                    &async_reprojection_topic,
+                    "async_reprojection",
+                    "Async Reprojection",
+                    &async_reprojection_payload,
+                ),
+                (
+                    &interleaved_reprojection_topic,
+                    "interleaved_reprojection",
+                    "Interleaved Reprojection",
+                    &interleaved_reprojection_payload,
+                ),
+            ] {
+                components.push((
+                    "binary_sensor",
+                    object_id.to_string(),
+                    serde_json::json!({
+                        "name": names.get(object_id, name),
+                        "unique_id": format!("{}_{}", unique_id, object_id),
+                        "entity_category": "diagnostic",
+                        "state_topic": topic,
+                        "payload_on": &payload.on,
+                        "payload_off": &payload.off,
+                        "availability": &availability,
+                    }),
+                ));
+            }
+            for (topic, object_id, name) in &[
+                (&dropped_frames_topic, "dropped_frames", "Dropped Frames"),
+                (
+                    &reprojected_frames_topic,
+                    "reprojected_frames",
+                    "Reprojected Frames",
+                ),
+            ] {
+                components.push(
+                    DiscoveryEntity::sensor(
+                        *object_id,
+                        names.get(object_id, name),
+                        unique_id,
+                        *topic,
+                        StateClass::TotalIncreasing,
+                    )
+                    .precision(0)
+                    .availability(&availability)
+                    .build(),
+                );
+            }
+        }
+        if aggregate {
+            for (object_id, name) in &[
+                ("in_use", "In Use"),
+                ("idle", "Idle"),
+                ("offline", "Offline"),
+                ("problems", "Problems"),
+            ] {
+                components.push(
+                    DiscoveryEntity::sensor(
+                        format!("fleet_{}", object_id),
+                        names.get(&format!("fleet_{}", object_id), &format!("Fleet {}", name)),
+                        unique_id,
+                        format!("{}/{}", fleet_topic, object_id),
+                        StateClass::Measurement,
+                    )
+                    .precision(0)
+                    .attributes_topic(format!("{}/summary", fleet_topic))
+                    .availability(&availability)
+                    .build(),
+                );
+            }
+        }
+        components.push(
+            DiscoveryEntity::sensor(
+                "boundary_collisions",
+                names.get("boundary_collisions", "Boundary Collisions"),
+                unique_id,
+                &boundary_collisions_topic,
+                StateClass::TotalIncreasing,
+            )
+            .precision(0)
+            .availability(&availability)
+            .build(),
+        );
+        components.push(
+            DiscoveryEntity::sensor(
+                "device_disconnects",
+                names.get("device_disconnects", "Device Disconnects"),
+                unique_id,
+                &device_disconnects_topic,
+                StateClass::Measurement,
+            )
+            .precision(0)
+            .diagnostic()
+            .availability(&availability)
+            .build(),
+        );
+        for (topic, object_id, name) in &[
+            (
+                &steamvr_restarts_topic,
+                "steamvr_restarts",
+                "SteamVR Restarts",
+            ),
+            (&steamvr_crashes_topic, "steamvr_crashes", "SteamVR Crashes"),
+            (
+                &publish_stalls_topic,
+                "publish_stalls",
+                "MQTT Publish Stalls",
+            ),
+            (&mqtt_reconnects_topic, "mqtt_reconnects", "MQTT Reconnects"),
+        ] {
+            components.push(
+                DiscoveryEntity::sensor(
+                    *object_id,
+                    names.get(object_id, name),
+                    unique_id,
+                    *topic,
+                    StateClass::TotalIncreasing,
+                )
+                .precision(0)
+                .diagnostic()
+                .build(),
+            );
+        }
+        components.push(
+            DiscoveryEntity::sensor(
+                "poll_latency",
+                names.get("poll_latency", "OpenVR Poll Latency"),
+                unique_id,
+                &poll_latency_topic,
+                StateClass::Measurement,
+            )
+            .device_class("duration")
+            .unit("ms", 0)
+            .formatted(&formats, "poll_latency")
+            .diagnostic()
+            .availability(&availability)
+            .build(),
+        );
+        components.push((
+            "sensor",
+            "gpu".to_string(),
+            serde_json::json!({
+                "name": names.get("gpu", "GPU"),
+                "unique_id": format!("{}_gpu", unique_id),
+                "entity_category": "diagnostic",
+                "state_topic": &gpu_topic,
+                "availability": &availability,
+            }),
+        ));
+        components.push((
+            "sensor",
+            "last_error".to_string(),
+            serde_json::json!({
+                "name": names.get("last_error", "Last Error"),
+                "unique_id": format!("{}_last_error", unique_id),
+                "entity_category": "diagnostic",
+                "state_topic": &last_error_topic,
+            }),
+        ));
+        components.push((
+            "sensor",
+            "started".to_string(),
+            serde_json::json!({
+                "name": names.get("started", "Started"),
+                "unique_id": format!("{}_started", unique_id),
+                "device_class": "timestamp",
+                "entity_category": "diagnostic",
+                "state_topic": &started_topic,
+            }),
+        ));
+        for (topic, object_id, name, unit) in &[
+            (
+                &display_frequency_topic,
+                "display_frequency",
+                "Display Frequency",
+                "Hz",
+            ),
+            (&render_width_topic, "render_width", "Render Width", "px"),
+            (&render_height_topic, "render_height", "Render Height", "px"),
+        ] {
+            components.push(
+                DiscoveryEntity::sensor(
+                    *object_id,
+                    names.get(object_id, name),
+                    unique_id,
+                    *topic,
+                    StateClass::Measurement,
+                )
+                .unit(*unit, 0)
+                .formatted(&formats, object_id)
+                .availability(&availability)
+                .build(),
+            );
+        }
+        for (sensor, name) in &[
+            ("power", "Power"),
+            ("active", "Active"),
+            ("application", "Application"),
+        ] {
+            components.push((
+                "sensor",
+                format!("{}_last_changed", sensor),
+                serde_json::json!({
+                    "name": names.get(
+                        &format!("{}_last_changed", sensor),
+                        &format!("{} Last Changed", name),
+                    ),
+                    "unique_id": format!("{}_{}_last_changed", unique_id, sensor),
+                    "device_class": "timestamp",
+                    "state_topic": format!("{}/{}/last_changed", base_topic, sensor),
+                }),
+            ));
+        }
+        for (name, topic, _, payload, _) in &derived {
+            components.push((
+                "binary_sensor",
+                name.to_string(),
+                serde_json::json!({
+                    "name": names.get(name, name),
+                    "unique_id": format!("{}_{}", unique_id, name),
+                    "state_topic": topic,
+                    "payload_on": &payload.on,
+                    "payload_off": &payload.off,
+                    "availability": &availability,
+                }),
+            ));
+        }
+        // Base stations and devices come and go, so discovery is republished with the
+        // static components whenever the set changes.
+        move |base_stations: &[String], devices: &[(String, String)]| {
+            let base_station_component = |serial: &str| {
+                (
+                    "binary_sensor",
+                    format!("base_station_{}", serial),
+                    serde_json::json!({
+                        "name": names.get_with("base_station", "Base Station {}", serial),
+                        "unique_id": format!("{}_base_station_{}", unique_id, serial),
+                        "state_topic": format!("{}/base_station/{}", base_topic, serial),
+                        "payload_on": &base_station_payload.on,
+                        "payload_off": &base_station_payload.off,
+                        "availability": &availability,
+                    }),
+                )
+            };
+
+            let device_component = |serial: &str, label: &str| {
+                DiscoveryEntity::sensor(
+                    format!("device_{}_battery", serial),
+                    names.get_with("device_battery", "{} Battery", label),
+                    unique_id,
+                    format!("{}/device/{}/battery", base_topic, serial),
+                    StateClass::Measurement,
+                )
+                .device_class("battery")
+                .unit("%", 0)
+                .formatted(&formats, "device_battery")
+                .availability(&availability)
+                .build()
+            };
+            let mut all_components = components.clone();
+            all_components.extend(
+                base_stations
+                    .iter()
+                    .map(|serial| base_station_component(serial)),
+            );
+            all_components.extend(
+                devices
+                    .iter()
+                    .map(|(serial, label)| device_component(serial, label)),
+            );
+            if let Some(metric_topics) = &metric_topics {
+                for (_, _, config) in &mut all_components {
+                    metric_topics.rewrite_discovery(config);
+                }
+            }
+            if settings.include_timestamps {
+                for (_, _, config) in &mut all_components {
+                    unstamp_discovery(config, &[&occurrences_topic]);
+                }
+            }
+            all_components
+        }
+    };
+    #[cfg(not(feature = "discovery"))]
+    let all_components = |_: &[String], _: &[(String, String)]| Vec::new();
+    let device_labels = |devices: &BTreeMap<String, Device>| -> Vec<(String, String)> {
+        devices
+            .iter()
+            .map(|(serial, device)| (serial.clone(), device.label.clone()))
+            .collect()
+    };
+
+    let mut known_base_stations: Vec<String> =
+        state.base_stations.borrow().keys().cloned().collect();
+    let mut known_devices = device_labels(&state.devices.borrow());
+
+    let mut device_name = settings.name.clone();
+    // Discovery that was interrupted by a stalled broker is retried on the next connect.
+    let mut discovery_pending = !settings.hass_prefix.is_empty()
+        && !publish_discovery(
+            &client,
+            &mut registry,
+            settings,
+            unique_id,
+            &device_name,
+            all_components(&known_base_stations, &known_devices),
+        )
+        .await?;
+
+    let mut limiter = RateLimiter::new(
+        &settings.mqtt.rate_limit,
+        &base_topic,
+        settings.include_timestamps,
+    );
+    let mut metrics = metric_topics.clone().map(|topics| MetricsBatch {
+        topics,
+        values: Default::default(),
+        changed: false,
+    });
+
+    for (name, time) in history.last_changed() {
+        limiter.queue(&format!("{}/{}/last_changed", base_topic, name), time);
+    }
+    limiter.queue(&steamvr_restarts_topic, state.sessions.restarts.to_string());
+    limiter.queue(&steamvr_crashes_topic, state.sessions.crashes.to_string());
+    limiter.queue(&name_topic, device_name.clone());
+    limiter.queue(
+        &started_topic,
+        Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+    );
+    // Power was off before this process started.
+    let mut published = state.vr_state.borrow().clone();
+    let mut last_values: HashMap<&str, String> = [
+        ("power", false.to_string()),
+        ("active", published.active.to_string()),
+        ("application", published.application.clone()),
+    ]
+    .iter()
+    .cloned()
+    .collect();
+    // The key arrives before the name, so both are needed to tell SteamVR Home from a game.
+    let mut application_key = state.application_key.borrow().clone();
+    let hooks = Hooks::new(&settings.hooks);
+    let mut in_game = settings
+        .in_game
+        .is_game(&application_key, &published.application);
+    // What's published as active, which may be limited to some applications.
+    let active_for = |active: bool, key: &str, application: &str| {
+        active && settings.active.allows(key, application)
+    };
+    let mut active = active_for(published.active, &application_key, &published.application);
+    limiter.queue(&in_game_topic, in_game_payload.format(in_game).to_string());
+
+    let mut fleet = Fleet::default();
+    let mut application_started = Instant::now();
+    let mut next_application_elapsed = Instant::now();
+    let mut next_stats = Instant::now();
+    let mut next_metrics = Instant::now();
+    let mut schedule = Schedule::new(&settings.standby);
+    let mut publish_stalls = PublishStalls {
+        topic: publish_stalls_topic.clone(),
+        count: 0,
+    };
+    let mut connections: u64 = 0;
+    limiter.queue(&mqtt_reconnects_topic, 0.to_string());
+
+    let _progress = state.progress.start("mqtt");
+    let mut progress_interval = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        let deadline = limiter.next_deadline();
+        tokio::select! {
+            _ = progress_interval.tick() => state.progress.beat("mqtt"),
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now).into()), if deadline.is_some() => {}
+            _ = tokio::time::sleep_until(next_application_elapsed.into()), if settings.application_elapsed.is_some() && !schedule.is_suspended("application_elapsed") => {
+                if let Some(application_elapsed) = &settings.application_elapsed {
+                    next_application_elapsed =
+                        schedule.next("application_elapsed", application_elapsed.interval_secs);
+                    limiter.queue(
+                        &application_elapsed_topic,
+                        formats.format("application_elapsed", application_started.elapsed().as_secs_f64()),
+                    );
+                }
+            }
+            _ = tokio::time::sleep_until(next_stats.into()), if stats.is_some() && !schedule.is_suspended("stats") => {
+                if let (Some(stats_settings), Some(stats)) = (&settings.stats, &mut stats) {
+                    next_stats = schedule.next("stats", stats_settings.interval_secs);
+                    if let Err(error) = stats.update(published.active).await {
+                        error!("Failed to update stats: {:?}", error);
+                    }
+                    limiter.queue(&vr_time_today_topic, formats.format("vr_time_today", stats.today_mins() as f64));
+                    limiter.queue(&vr_time_week_topic, formats.format("vr_time_week", stats.week_mins() as f64));
+                }
+            }
+            _ = tokio::time::sleep_until(next_metrics.into()), if settings.metrics.is_some() && !schedule.is_suspended("metrics") => {
+                if let (Some(metrics_settings), Some(metrics)) = (&settings.metrics, &mut metrics) {
+                    next_metrics = schedule.next("metrics", metrics_settings.interval_secs);
+                    if let Some(payload) = metrics.take() {
+                        limiter.queue(&metrics_topic, payload);
+                    }
+                }
+            }
+            _ = state.shutdown.cancelled() => break,
+            recv = connect_receive.recv() => {
+                if recv.is_some() {
+                    // Subscriptions don't outlive the session so renew them on every connect.
+                    client
+                        .subscribe(format!("{}/+/set", base_topic), QoS::AtLeastOnce)
+                        .await?;
+                    client
+                        .subscribe(format!("{}/identify/+/set", base_topic), QoS::AtLeastOnce)
+                        .await?;
+                    client.subscribe(&query_topic, QoS::AtLeastOnce).await?;
+                    if aggregate {
+                        client
+                            .subscribe(format!("{}/stations/+/+", settings.prefix), QoS::AtLeastOnce)
+                            .await?;
+                    }
+                    let power = state.vr_state.borrow().power;
+                    limiter.queue(&power_topic, power_payload.format(power).to_string());
+                    let locked = *state.locked.borrow();
+                    limiter.queue(&locked_topic, locked_payload.format(locked).to_string());
+                    connections += 1;
+                    limiter.queue(&mqtt_reconnects_topic, (connections - 1).to_string());
+                    if discovery_pending {
+                        discovery_pending = !publish_discovery(
+                            &client,
+                            &mut registry,
+                            settings,
+                            unique_id,
+                            &device_name,
+                            all_components(&known_base_stations, &known_devices),
+                        )
+                        .await?;
+                    }
+                } else {
+                    break;
+                }
+            }
+            Some(request) = query_receive.recv() => {
+                let request: serde_json::Value =
+                    serde_json::from_slice(&request).unwrap_or(serde_json::Value::Null);
+                let response_topic =
+                    response_topic(&request, &query_topic).unwrap_or(query_response_topic.as_str());
+                let mut snapshot = limiter.snapshot(&base_topic);
+                // Batched values may not have been published yet.
+                if let Some(metrics) = &metrics {
+                    snapshot.extend(metrics.values.clone());
+                }
+                let response = serde_json::json!({
+                    "id": request["id"],
+                    "state": snapshot,
+                });
+                if !publish(&client, response_topic, false, response.to_string()).await? {
+                    publish_stalls.stalled(&mut limiter);
+                }
+            }
+            Some(name) = rename_receive.recv() => {
+                if !name.is_empty() && name != device_name {
+                    info!("Renamed to {}", name);
+                    device_name = name;
+                    if let Err(error) = save_name(&device_name).await {
+                        error!("Failed to save name: {:?}", error);
+                    }
+                    if !settings.hass_prefix.is_empty() {
+                        discovery_pending = !publish_discovery(
+                            &client,
+                            &mut registry,
+                            settings,
+                            unique_id,
+                            &device_name,
+                            all_components(&known_base_stations, &known_devices),
+                        )
+                        .await?;
+                    }
+                }
+                limiter.queue(&name_topic, device_name.clone());
+            }
+            Some(event) = events.recv() => {
+                // Events aren't state so they skip the rate limiter and aren't retained, and
+                // are dropped if the broker stalls.
+                let published = publish(
+                    &client,
+                    &raw_events_topic,
+                    false,
+                    serde_json::to_string(&serde_json::json!({
+                        "type": event.name,
+                        "device_index": event.device_index,
+                        "age_seconds": event.age_seconds,
+                    }))
+                    .unwrap(),
+                )
+                .await?;
+                if !published {
+                    publish_stalls.stalled(&mut limiter);
+                }
+            }
+            Some(occurrence) = occurrences.recv() => {
+                hooks.run(&occurrence, &application_key, &published);
+                if !publish_occurrence(&client, &occurrences_topic, occurrence).await? {
+                    publish_stalls.stalled(&mut limiter);
+                }
+            }
+            Some(update) = fleet_receive.recv() => {
+                fleet.update(update, &settings.payloads);
+                let summary = fleet.summary();
+                for (name, stations) in &[
+                    ("in_use", &summary.in_use),
+                    ("idle", &summary.idle),
+                    ("offline", &summary.offline),
+                    ("problems", &summary.problems),
+                ] {
+                    limiter.queue(&format!("{}/{}", fleet_topic, name), stations.len().to_string());
+                }
+                limiter.queue(
+                    &format!("{}/summary", fleet_topic),
+                    serde_json::to_string(&serde_json::json!({
+                        "in_use": summary.in_use,
+                        "idle": summary.idle,
+                        "offline": summary.offline,
+                        "problems": summary.problems,
+                    }))
+                    .unwrap(),
+                );
+            }
+            recv = state.vr_state.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let vr_state = state.vr_state.borrow_and_update().clone();
+                if schedule.set_active(vr_state.active) {
+                    next_application_elapsed = Instant::now();
+                    next_metrics = Instant::now();
+                }
+                if let Some(stats) = &mut stats {
+                    if vr_state.active != published.active {
+                        if let Err(error) = stats.update(vr_state.active).await {
+                            error!("Failed to update stats: {:?}", error);
+                        }
+                        limiter.queue(&vr_time_today_topic, formats.format("vr_time_today", stats.today_mins() as f64));
+                        limiter.queue(&vr_time_week_topic, formats.format("vr_time_week", stats.week_mins() as f64));
+                    }
+                }
+                if vr_state.power != published.power {
+                    limiter.queue(&power_topic, power_payload.format(vr_state.power).to_string());
+                }
+                let now_active = active_for(vr_state.active, &application_key, &vr_state.application);
+                if now_active != active {
+                    active = now_active;
+                    limiter.queue(&active_topic, active_payload.format(active).to_string());
+                }
+                if vr_state.application != published.application {
+                    limiter.queue(&application_topic, private(&vr_state.application));
+                    // Restart the count and publish the reset right away rather than waiting
+                    // for the next interval.
+                    application_started = Instant::now();
+                    next_application_elapsed = Instant::now();
+                }
+                let game = settings.in_game.is_game(&application_key, &vr_state.application);
+                if game != in_game {
+                    in_game = game;
+                    limiter.queue(&in_game_topic, in_game_payload.format(in_game).to_string());
+                    let occurrence = OccurrenceEvent::new(if in_game { Occurrence::GameStarted } else { Occurrence::GameEnded }, None);
+                    hooks.run(&occurrence, &application_key, &vr_state);
+                    if !publish_occurrence(&client, &occurrences_topic, occurrence).await? {
+                        publish_stalls.stalled(&mut limiter);
+                    }
+                }
+                published = vr_state;
+            }
+            recv = state.base_stations.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let base_stations = state.base_stations.borrow_and_update().clone();
+                let serials: Vec<String> = base_stations.keys().cloned().collect();
+                if !settings.hass_prefix.is_empty() && serials != known_base_stations {
+                    discovery_pending = !publish_discovery(
+                        &client,
+                        &mut registry,
+                        settings,
+                        unique_id,
+                        &device_name,
+                        all_components(&serials, &known_devices),
+                    )
+                    .await?;
+                }
+                known_base_stations = serials;
+                for (serial, awake) in &base_stations {
+                    limiter.queue(
+                        &format!("{}/base_station/{}", base_topic, serial),
+                        base_station_payload.format(*awake).to_string(),
+                    );
+                }
+            }
+            recv = state.devices.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let devices = state.devices.borrow_and_update().clone();
+                let labels = device_labels(&devices);
+                if !settings.hass_prefix.is_empty() && labels != known_devices {
+                    discovery_pending = !publish_discovery(
+                        &client,
+                        &mut registry,
+                        settings,
+                        unique_id,
+                        &device_name,
+                        all_components(&known_base_stations, &labels),
+                    )
+                    .await?;
+                }
+                known_devices = labels;
+                for (serial, device) in &devices {
+                    if let Some(battery) = device.battery {
+                        queue_value(
+                            &mut limiter,
+                            &mut metrics,
+                            &format!("{}/device/{}/battery", base_topic, serial),
+                            formats.format("device_battery", f64::from(battery) * 100.0),
+                        );
+                    }
+                }
+                // Home Assistant shows None as unknown rather than failing to parse it.
+                let (battery_min, battery_min_device) = match lowest_battery(&devices) {
+                    Some((serial, device, battery)) => (
+                        formats.format("battery_min", f64::from(battery) * 100.0),
+                        serde_json::json!({ "serial": serial, "label": &device.label }),
+                    ),
+                    None => ("None".to_string(), serde_json::json!({})),
+                };
+                queue_value(&mut limiter, &mut metrics, &battery_min_topic, battery_min);
+                limiter.queue(&battery_min_device_topic, battery_min_device.to_string());
+            }
+            recv = state.frame_stats.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let frame_stats = state.frame_stats.borrow_and_update().clone();
+                if let Some(frame_stats) = frame_stats {
+                    queue_value(&mut limiter, &mut metrics, &gpu_frame_time_topic, formats.format("gpu_frame_time", f64::from(frame_stats.gpu_ms)));
+                    queue_value(&mut limiter, &mut metrics, &cpu_frame_time_topic, formats.format("cpu_frame_time", f64::from(frame_stats.cpu_ms)));
+                    queue_value(&mut limiter, &mut metrics, &frame_budget_topic, formats.format("frame_budget", f64::from(frame_stats.budget_percent)));
+                    limiter.queue(&async_reprojection_topic, async_reprojection_payload.format(frame_stats.async_reprojection).to_string());
+                    limiter.queue(&interleaved_reprojection_topic, interleaved_reprojection_payload.format(frame_stats.interleaved_reprojection).to_string());
+                }
+            }
+            recv = state.frame_counts.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let frame_counts = state.frame_counts.borrow_and_update().clone();
+                if let Some(frame_counts) = frame_counts {
+                    queue_value(&mut limiter, &mut metrics, &dropped_frames_topic, frame_counts.dropped.to_string());
+                    queue_value(&mut limiter, &mut metrics, &reprojected_frames_topic, frame_counts.reprojected.to_string());
+                }
+            }
+            recv = state.motion_smoothing.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let enabled = *state.motion_smoothing.borrow_and_update();
+                if let Some(enabled) = enabled {
+                    limiter.queue(&motion_smoothing_topic, motion_smoothing_payload.format(enabled).to_string());
+                }
+            }
+            recv = state.safe_mode.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let enabled = *state.safe_mode.borrow_and_update();
+                if let Some(enabled) = enabled {
+                    limiter.queue(&safe_mode_topic, safe_mode_payload.format(enabled).to_string());
+                }
+            }
+            recv = state.application_key.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                application_key = state.application_key.borrow_and_update().clone();
+                limiter.queue(&application_key_topic, private(&application_key));
+                let now_active = active_for(published.active, &application_key, &published.application);
+                if now_active != active {
+                    active = now_active;
+                    limiter.queue(&active_topic, active_payload.format(active).to_string());
+                }
+                let game = settings.in_game.is_game(&application_key, &published.application);
+                if game != in_game {
+                    in_game = game;
+                    limiter.queue(&in_game_topic, in_game_payload.format(in_game).to_string());
+                    let occurrence = OccurrenceEvent::new(if in_game { Occurrence::GameStarted } else { Occurrence::GameEnded }, None);
+                    hooks.run(&occurrence, &application_key, &published);
+                    if !publish_occurrence(&client, &occurrences_topic, occurrence).await? {
+                        publish_stalls.stalled(&mut limiter);
+                    }
+                }
+            }
+            recv = state.supersampling.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let scale = *state.supersampling.borrow_and_update();
+                if let Some(scale) = scale {
+                    limiter.queue(&supersampling_topic, format!("{:.2}", scale));
+                }
+            }
+            recv = state.brightness.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let percent = *state.brightness.borrow_and_update();
+                if let Some(percent) = percent {
+                    limiter.queue(&brightness_topic, format!("{:.0}", percent));
+                }
+            }
+            recv = state.standby_timeout.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let seconds = *state.standby_timeout.borrow_and_update();
+                if let Some(seconds) = seconds {
+                    limiter.queue(&standby_timeout_topic, format!("{:.0}", seconds));
+                }
+            }
+            recv = state.ready_to_play.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let readiness = state.ready_to_play.borrow_and_update().clone();
+                if let Some(readiness) = readiness {
+                    limiter.queue(&ready_to_play_topic, ready_to_play_payload.format(readiness.ready).to_string());
+                    limiter.queue(&ready_to_play_blockers_topic, serde_json::json!({ "blockers": readiness.blockers }).to_string());
+                }
+            }
+            recv = state.controllers_in_use.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let in_use = *state.controllers_in_use.borrow_and_update();
+                limiter.queue(&controllers_in_use_topic, controllers_in_use_payload.format(in_use).to_string());
+            }
+            recv = state.hands_on_controllers.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let held = *state.hands_on_controllers.borrow_and_update();
+                if let Some(held) = held {
+                    limiter.queue(&hands_on_controllers_topic, hands_on_controllers_payload.format(held).to_string());
+                }
+            }
+            recv = state.display_problem.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let problem = *state.display_problem.borrow_and_update();
+                limiter.queue(&display_problem_topic, display_problem_payload.format(problem).to_string());
+            }
+            recv = state.disconnects.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let status = *state.disconnects.borrow_and_update();
+                limiter.queue(&device_disconnects_topic, status.recent.to_string());
+                limiter.queue(&device_problem_topic, device_problem_payload.format(status.flapping).to_string());
+            }
+            recv = state.boundary_collisions.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let count = *state.boundary_collisions.borrow_and_update();
+                limiter.queue(&boundary_collisions_topic, count.to_string());
+            }
+            recv = state.poll_latency.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let millis = *state.poll_latency.borrow_and_update();
+                if let Some(millis) = millis {
+                    limiter.queue(&poll_latency_topic, formats.format("poll_latency", millis as f64));
+                }
+            }
+            recv = state.last_error.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let error = state.last_error.borrow_and_update().clone();
+                if let Some(error) = error {
+                    // Home Assistant rejects states longer than 255 characters.
+                    limiter.queue(&last_error_topic, error.chars().take(255).collect());
+                }
+            }
+            recv = state.user.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let user = state.user.borrow_and_update().clone();
+                if let Some(user) = user {
+                    limiter.queue(&user_topic, user.windows_user);
+                    // Empty while nobody is logged in to Steam.
+                    limiter.queue(&steam_account_topic, user.steam_account.unwrap_or_default());
+                }
+            }
+            recv = state.process.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let process = state.process.borrow_and_update().clone();
+                // Paths and arguments usually name the application, so they're left out when
+                // it's private.
+                let process = match (&settings.privacy, process) {
+                    (None, Some(process)) => serde_json::to_value(process).unwrap(),
+                    (Some(_), Some(process)) => {
+                        serde_json::json!({ "launched_by": process.launched_by })
+                    }
+                    (_, None) => serde_json::json!({}),
+                };
+                limiter.queue(&process_topic, process.to_string());
+            }
+            recv = state.process_usage.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let usage = state.process_usage.borrow_and_update().clone();
+                match usage {
+                    Some(usage) => {
+                        limiter.queue(&application_pid_topic, usage.pid.to_string());
+                        limiter.queue(&application_cpu_topic, formats.format("application_cpu", f64::from(usage.cpu_percent)));
+                        limiter.queue(&application_memory_topic, formats.format("application_memory", usage.working_set_mb));
+                    }
+                    None => {
+                        limiter.queue(&application_pid_topic, String::new());
+                        limiter.queue(&application_cpu_topic, "0".to_string());
+                        limiter.queue(&application_memory_topic, "0".to_string());
+                    }
+                }
+            }
+            recv = state.overlays.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let overlays = state.overlays.borrow_and_update().clone();
+                limiter.queue(&overlays_topic, overlays.visible.len().to_string());
+                limiter.queue(
+                    &format!("{}/attributes", overlays_topic),
+                    serde_json::to_string(&overlays).unwrap(),
+                );
+            }
+            recv = state.calibration.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let calibration = *state.calibration.borrow_and_update();
+                if let Some(calibration) = calibration {
+                    limiter.queue(&calibration_topic, calibration.name().to_string());
+                    limiter.queue(&room_setup_needed_topic, room_setup_needed_payload.format(calibration.needs_setup()).to_string());
+                }
+            }
+            recv = state.display.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let display = state.display.borrow_and_update().clone();
+                if let Some(display) = display {
+                    limiter.queue(&display_frequency_topic, formats.format("display_frequency", f64::from(display.frequency)));
+                    limiter.queue(&render_width_topic, formats.format("render_width", f64::from(display.render_width)));
+                    limiter.queue(&render_height_topic, formats.format("render_height", f64::from(display.render_height)));
+                    limiter.queue(&direct_mode_topic, direct_mode_payload.format(display.direct_mode).to_string());
+                    // The simulated headset never has a display to drive directly.
+                    let extended_mode = !display.direct_mode && !display.simulated;
+                    limiter.queue(&extended_mode_topic, extended_mode_payload.format(extended_mode).to_string());
+                    limiter.queue(&simulated_topic, simulated_payload.format(display.simulated).to_string());
+                    if let Some(gpu) = display.gpu {
+                        limiter.queue(&gpu_topic, gpu);
+                    }
+                }
+            }
+            recv = state.drivers.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let drivers = state.drivers.borrow_and_update().clone();
+                let enabled = drivers.values().filter(|enabled| **enabled).count();
+                limiter.queue(&drivers_topic, enabled.to_string());
+                limiter.queue(
+                    &format!("{}/attributes", drivers_topic),
+                    serde_json::to_string(&drivers).unwrap(),
+                );
+            }
+            recv = state.camera.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let camera = state.camera.borrow_and_update().clone();
+                if let Some(camera) = camera {
+                    limiter.queue(&camera_available_topic, camera_available_payload.format(camera.available).to_string());
+                    limiter.queue(&camera_active_topic, camera_active_payload.format(camera.active).to_string());
+                }
+            }
+            recv = state.reason.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let reason = state.reason.borrow_and_update().clone();
+                if let Some(reason) = reason {
+                    limiter.queue(&reason_topic, reason_payload(&reason));
+                }
+            }
+            recv = state.scene_state.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let scene_state = state.scene_state.borrow_and_update().clone();
+                limiter.queue(&scene_state_topic, scene_state);
+            }
+            recv = state.mirror_window.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let visible = *state.mirror_window.borrow_and_update();
+                limiter.queue(&mirror_window_topic, mirror_window_payload.format(visible).to_string());
+            }
+            recv = state.link.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let link = state.link.borrow_and_update().clone();
+                if let Some(link) = link {
+                    limiter.queue(&link_quality_topic, formats.format("link_quality", f64::from(link.quality)));
+                    limiter.queue(&link_problem_topic, link_problem_payload.format(link.problem).to_string());
+                }
+            }
+            recv = state.locked.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let locked = *state.locked.borrow_and_update();
+                limiter.queue(&locked_topic, locked_payload.format(locked).to_string());
+            }
+            recv = state.update.changed() => {
+                if recv.is_err() {
+                    break;
+                }
+                let release = state.update.borrow_and_update().clone();
+                if let Some(release) = release {
+                    limiter.queue(
+                        &update_topic,
+                        serde_json::to_string(&serde_json::json!({
+                            "installed_version": INSTALLED_VERSION,
+                            "latest_version": release.version,
+                            "release_url": release.url,
+                            "release_summary": release.summary,
+                        }))
+                        .unwrap(),
+                    );
+                }
+            }
+        }
+
+        let vr_state = state.vr_state.borrow().clone();
+        let current = [
+            ("power", vr_state.power.to_string()),
+            ("active", vr_state.active.to_string()),
+            ("application", vr_state.application.clone()),
+        ];
+        for (name, value) in current.iter() {
+            if last_values.get(name) != Some(value) {
+                last_values.insert(*name, value.clone());
+                match history.record(name).await {
+                    Ok(time) => {
+                        limiter.queue(&format!("{}/{}/last_changed", base_topic, name), time)
+                    }
+                    Err(error) => error!("Failed to record change: {:?}", error),
+                }
+            }
+        }
+
+        let locked = *state.locked.borrow();
+        let simulated = state
+            .display
+            .borrow()
+            .as_ref()
+            .map_or(false, |display| display.simulated);
+        let controllers_in_use = *state.controllers_in_use.borrow();
+        let lookup = |name: &str| match name {
+            "locked" => Some(Value::Bool(locked)),
+            "controllers_in_use" => Some(Value::Bool(controllers_in_use)),
+            "simulated" => Some(Value::Bool(simulated)),
+            "power" => Some(Value::Bool(vr_state.power)),
+            "active" => Some(Value::Bool(vr_state.active)),
+            "application" => Some(Value::String(vr_state.application.clone())),
+            _ => None,
+        };
+        for (name, topic, expression, payload, last) in &mut derived {
+            let value = match expression.evaluate(&lookup) {
+                Ok(value) => value.truthy(),
+                Err(error) => {
+                    error!("Failed to evaluate derived sensor {}: {:?}", name, error);
+                    continue;
+                }
+            };
+            if *last != Some(value) {
+                *last = Some(value);
+                limiter.queue(topic, payload.format(value).to_string());
+            }
+        }
+
+        for topic in publish_ready(&client, &mut limiter, &mut publish_stalls).await? {
+            registry.record(&topic);
+        }
+        registry.save().await?;
+    }
+
+    // The reason is usually set just before shutting down, so don't rely on the loop
+    // having seen it.
+    if let Some(reason) = state.reason.borrow().clone() {
+        limiter.queue(&reason_topic, reason_payload(&reason));
+    }
+    if let Some(payload) = metrics.as_mut().and_then(MetricsBatch::take) {
+        limiter.queue(&metrics_topic, payload);
+    }
+    limiter.queue(&active_topic, active_payload.format(false).to_string());
+    limiter.queue(&power_topic, power_payload.format(false).to_string());
+    for (topic, payload) in limiter.take_all() {
+        registry.record(&topic);
+        if !publish(&client, &topic, true, payload).await? {
+            break;
+        }
+    }
+    registry.save().await?;
+    // Publishes are sent in order so this waits for everything above to go out.
+    client.disconnect().await?;
+
+    event_loop.await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_window_allows_up_to_the_limit() {
+        let start = Instant::now();
+        let mut window = RateWindow::new(2);
+        assert_eq!(window.next_allowed(start), start);
+        window.record(start);
+        assert_eq!(window.next_allowed(start), start);
+        window.record(start + Duration::from_secs(1));
+        assert_eq!(
+            window.next_allowed(start + Duration::from_secs(2)),
+            start + RATE_WINDOW
+        );
+    }
+
+    #[test]
+    fn rate_window_frees_slots_as_they_age_out() {
+        let start = Instant::now();
+        let mut window = RateWindow::new(2);
+        window.record(start);
+        window.record(start + Duration::from_secs(10));
+        // The first send has left the window, so there's room again.
+        let later = start + RATE_WINDOW;
+        assert_eq!(window.next_allowed(later), later);
+        window.record(later);
+        assert_eq!(
+            window.next_allowed(later),
+            start + Duration::from_secs(10) + RATE_WINDOW
+        );
+    }
+
+    fn rate_limiter(global: Option<u32>, topics: &[(&str, u32)], coalesce_ms: u64) -> RateLimiter {
+        let settings = RateLimitSettings {
+            global,
+            topics: topics
+                .iter()
+                .map(|(topic, limit)| (topic.to_string(), *limit))
+                .collect(),
+            coalesce_ms,
+        };
+        RateLimiter::new(&settings, "vr/test", false)
+    }
+
+    #[test]
+    fn rate_limiter_keeps_only_the_latest_value() {
+        let mut limiter = rate_limiter(None, &[], 0);
+        limiter.queue("vr/test/application", "first".into());
+        limiter.queue("vr/test/application", "second".into());
+        assert_eq!(
+            limiter.take_ready(),
+            vec![("vr/test/application".to_string(), "second".to_string())]
+        );
+        assert!(limiter.take_ready().is_empty());
+    }
+
+    #[test]
+    fn rate_limiter_holds_changes_while_coalescing() {
+        let mut limiter = rate_limiter(None, &[], 60_000);
+        limiter.queue("vr/test/application", "first".into());
+        limiter.queue("vr/test/application", "second".into());
+        assert!(limiter.take_ready().is_empty());
+        assert!(limiter.next_deadline().unwrap() > Instant::now());
+        assert_eq!(
+            limiter.take_all(),
+            vec![("vr/test/application".to_string(), "second".to_string())]
+        );
+    }
+
+    #[test]
+    fn rate_limiter_applies_global_and_topic_limits() {
+        let mut limiter = rate_limiter(Some(2), &[("application", 1)], 0);
+        limiter.queue("vr/test/application", "a".into());
+        limiter.queue("vr/test/power", "ON".into());
+        assert_eq!(limiter.take_ready().len(), 2);
+
+        // The topic's limit is used up even though the global one isn't yet.
+        let mut limiter = rate_limiter(Some(10), &[("application", 1)], 0);
+        limiter.queue("vr/test/application", "a".into());
+        assert_eq!(limiter.take_ready().len(), 1);
+        limiter.queue("vr/test/application", "b".into());
+        limiter.queue("vr/test/power", "ON".into());
+        assert_eq!(
+            limiter.take_ready(),
+            vec![("vr/test/power".to_string(), "ON".to_string())]
+        );
+        assert!(limiter.next_deadline().unwrap() > Instant::now() + Duration::from_secs(59));
+    }
+
+    #[test]
+    fn rate_limiter_remembers_values_for_snapshots() {
+        let mut limiter = rate_limiter(None, &[], 0);
+        limiter.queue("vr/test/application", "Beat Saber".into());
+        limiter.queue("vr/test/display_frequency", "90".into());
+        limiter.take_ready();
+        let snapshot = limiter.snapshot("vr/test");
+        assert_eq!(snapshot["application"], "Beat Saber");
+        assert_eq!(snapshot["display_frequency"], 90);
+    }
+
+    #[test]
+    fn response_topics_stay_under_the_query_topic() {
+        let response = |topic: &str| {
+            let request = serde_json::json!({ "response_topic": topic, "id": 1 });
+            response_topic(&request, "vr/test/query").map(str::to_string)
+        };
+        assert_eq!(
+            response("vr/test/query/script").as_deref(),
+            Some("vr/test/query/script")
+        );
+        assert_eq!(
+            response("vr/test/query/script/1").as_deref(),
+            Some("vr/test/query/script/1")
+        );
+        for refused in &[
+            "",
+            "vr/test/query",
+            "vr/test/query/",
+            "vr/test/queryx/script",
+            "vr/test/power",
+            "vr/other/query/script",
+            "homeassistant/binary_sensor/vr/config",
+            "vr/test/query/+",
+            "vr/test/query/#",
+        ] {
+            assert_eq!(response(refused), None, "{}", refused);
+        }
+        assert_eq!(
+            response_topic(&serde_json::Value::Null, "vr/test/query"),
+            None
+        );
+        assert_eq!(
+            response_topic(&serde_json::json!({ "response_topic": 1 }), "vr/test/query"),
+            None
+        );
+    }
+
+    /// A client with room for one message whose event loop is never polled, like one
+    /// connected to a broker that stopped responding. The event loop has to be kept or
+    /// publishing fails instead of waiting.
+    fn stalled_client() -> (AsyncClient, rumqttc::EventLoop) {
+        AsyncClient::new(MqttOptions::new("test", "localhost", 1883), 1)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn publish_gives_up_when_the_queue_stays_full() {
+        let (client, _event_loop) = stalled_client();
+        assert!(publish(&client, "vr/test/power", true, "ON").await.unwrap());
+        let started = tokio::time::Instant::now();
+        assert!(!publish(&client, "vr/test/active", true, "ON")
+            .await
+            .unwrap());
+        assert_eq!(started.elapsed(), PUBLISH_TIMEOUT);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stalled_messages_are_kept_and_counted() {
+        let (client, _event_loop) = stalled_client();
+        let mut limiter = rate_limiter(None, &[], 0);
+        let mut stalls = PublishStalls {
+            topic: "vr/test/publish_stalls".to_string(),
+            count: 0,
+        };
+        limiter.queue("vr/test/power", "ON".into());
+        limiter.queue("vr/test/active", "ON".into());
+        limiter.queue("vr/test/application", "Beat Saber".into());
+
+        let published = publish_ready(&client, &mut limiter, &mut stalls)
+            .await
+            .unwrap();
+        assert_eq!(published, vec!["vr/test/power".to_string()]);
+        assert_eq!(stalls.count, 1);
+        assert_eq!(
+            limiter.take_all(),
+            vec![
+                ("vr/test/active".to_string(), "ON".to_string()),
+                ("vr/test/application".to_string(), "Beat Saber".to_string()),
+                ("vr/test/publish_stalls".to_string(), "1".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "notifications")]
+use anyhow::{Context, Result};
+use bindings::openvr::{
+    k_unMaxTrackedDeviceCount, k_unTrackedDeviceIndex_Hmd,
+    ETrackedDeviceClass_ETrackedDeviceClass_TrackedDeviceClass_Controller,
+    ETrackedDeviceProperty_ETrackedDeviceProperty_Prop_DeviceBatteryPercentage_Float,
+    ETrackingResult_ETrackingResult_TrackingResult_Running_OK,
+    ETrackingUniverseOrigin_ETrackingUniverseOrigin_TrackingUniverseStanding, TrackedDeviceIndex_t,
+};
+#[cfg(feature = "notifications")]
+use log::{error, info};
+#[cfg(feature = "notifications")]
+use tokio::sync::mpsc::UnboundedReceiver;
+
+#[cfg(feature = "notifications")]
+use crate::settings::NotificationService;
+use crate::{openvr::VrSystem, settings::NotificationSettings};
+
+pub struct Notification {
+    pub title: String,
+    pub message: String,
+}
+
+/// Tracks conditions that are worth a push notification so each one is only sent once
+/// per occurrence.
+#[derive(Default)]
+pub struct CriticalConditions {
+    low_battery: HashSet<TrackedDeviceIndex_t>,
+    tracking_lost: bool,
+    tracking_losses: VecDeque<Instant>,
+}
+
+impl CriticalConditions {
+    pub fn check(
+        &mut self,
+        system: &VrSystem,
+        settings: &NotificationSettings,
+    ) -> Vec<Notification> {
+        let mut notifications = Vec::new();
+
+        for index in 0..k_unMaxTrackedDeviceCount {
+            if system.get_tracked_device_class(index)
+                != ETrackedDeviceClass_ETrackedDeviceClass_TrackedDeviceClass_Controller
+                || !system.is_tracked_device_connected(index)
+            {
+                self.low_battery.remove(&index);
+                continue;
+            }
+            let battery = match system.get_float_tracked_device_property(
+                index,
+                ETrackedDeviceProperty_ETrackedDeviceProperty_Prop_DeviceBatteryPercentage_Float,
+            ) {
+                Ok(battery) => battery,
+                Err(_) => continue,
+            };
+            if battery >= settings.battery_below {
+                self.low_battery.remove(&index);
+            } else if self.low_battery.insert(index) {
+                notifications.push(Notification {
+                    title: "Controller battery critical".into(),
+                    message: format!("Controller {} is at {:.0}% battery", index, battery * 100.0),
+                });
+            }
+        }
+
+        let poses = system.get_device_to_absolute_tracking_pose(
+            ETrackingUniverseOrigin_ETrackingUniverseOrigin_TrackingUniverseStanding,
+        );
+        let hmd = &poses[k_unTrackedDeviceIndex_Hmd as usize];
+        let tracking = hmd.bDeviceIsConnected
+            && hmd.eTrackingResult == ETrackingResult_ETrackingResult_TrackingResult_Running_OK;
+        if hmd.bDeviceIsConnected && !tracking && !self.tracking_lost {
+            self.tracking_losses.push_back(Instant::now());
+        }
+        self.tracking_lost = hmd.bDeviceIsConnected && !tracking;
+        let window = Duration::from_secs(settings.tracking_lost_window_secs);
+        while let Some(lost) = self.tracking_losses.front() {
+            if lost.elapsed() <= window {
+                break;
+            }
+            self.tracking_losses.pop_front();
+        }
+        if self.tracking_losses.len() >= settings.tracking_lost_count {
+            self.tracking_losses.clear();
+            notifications.push(Notification {
+                title: "Tracking lost repeatedly".into(),
+                message: format!(
+                    "Headset tracking was lost {} times in {} minutes",
+                    settings.tracking_lost_count,
+                    settings.tracking_lost_window_secs / 60
+                ),
+            });
+        }
+
+        notifications
+    }
+}
+
+#[cfg(feature = "notifications")]
+async fn send(
+    client: &reqwest::Client,
+    service: &NotificationService,
+    notification: &Notification,
+) -> Result<()> {
+    let request = match service {
+        NotificationService::Ntfy {
+            server,
+            topic,
+            token,
+        } => {
+            let request = client
+                .post(format!("{}/{}", server.trim_end_matches('/'), topic))
+                .header("Title", &notification.title)
+                .header("Priority", "urgent")
+                .body(notification.message.clone());
+            match token {
+                Some(token) => request.bearer_auth(token),
+                None => request,
+            }
+        }
+        NotificationService::Pushover { token, user } => client
+            .post("https://api.pushover.net/1/messages.json")
+            .form(&[
+                ("token", token.as_str()),
+                ("user", user.as_str()),
+                ("title", notification.title.as_str()),
+                ("message", notification.message.as_str()),
+                ("priority", "1"),
+            ]),
+    };
+    request
+        .send()
+        .await
+        .context("Failed to send notification")?
+        .error_for_status()
+        .context("Failed to send notification")?;
+    Ok(())
+}
+
+#[cfg(feature = "notifications")]
+pub async fn notify_loop(
+    settings: &NotificationSettings,
+    mut receiver: UnboundedReceiver<Notification>,
+) -> Result<()> {
+    let client = reqwest::Client::builder()
+        .user_agent(concat!("vr-status/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .context("Failed to create HTTP client")?;
+
+    while let Some(notification) = receiver.recv().await {
+        info!("Notifying: {}", notification.message);
+        if let Err(error) = send(&client, &settings.service, &notification).await {
+            error!("{:?}", error);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::settings::{NumberFormat, Settings};
+
+/// A unit numeric sensors can be converted between. Fractions and percentages convert to
+/// each other, as do the units of time.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum NumberUnit {
+    Fraction,
+    Percent,
+    Milliseconds,
+    Seconds,
+    Minutes,
+    Hours,
+}
+
+impl NumberUnit {
+    /// The unit Home Assistant shows, or `None` for a plain fraction.
+    pub fn symbol(self) -> Option<&'static str> {
+        match self {
+            NumberUnit::Fraction => None,
+            NumberUnit::Percent => Some("%"),
+            NumberUnit::Milliseconds => Some("ms"),
+            NumberUnit::Seconds => Some("s"),
+            NumberUnit::Minutes => Some("min"),
+            NumberUnit::Hours => Some("h"),
+        }
+    }
+
+    /// How many of the base unit (a fraction or a second) one of this unit is.
+    fn scale(self) -> f64 {
+        match self {
+            NumberUnit::Fraction => 1.0,
+            NumberUnit::Percent => 0.01,
+            NumberUnit::Milliseconds => 0.001,
+            NumberUnit::Seconds => 1.0,
+            NumberUnit::Minutes => 60.0,
+            NumberUnit::Hours => 3600.0,
+        }
+    }
+
+    fn is_time(self) -> bool {
+        !matches!(self, NumberUnit::Fraction | NumberUnit::Percent)
+    }
+
+    pub fn converts_to(self, other: NumberUnit) -> bool {
+        self.is_time() == other.is_time()
+    }
+}
+
+/// Numeric sensors `formats` can change, keyed like `hass_names`, with the unit they're
+/// measured in, if it can be converted, and the decimal places they're published with.
+pub const NUMERIC: &[(&str, Option<NumberUnit>, u32)] = &[
+    ("application_cpu", Some(NumberUnit::Percent), 1),
+    ("application_elapsed", Some(NumberUnit::Seconds), 0),
+    ("application_memory", None, 0),
+    ("battery_min", Some(NumberUnit::Percent), 0),
+    ("cpu_frame_time", Some(NumberUnit::Milliseconds), 2),
+    ("device_battery", Some(NumberUnit::Percent), 0),
+    ("display_frequency", None, 0),
+    ("frame_budget", Some(NumberUnit::Percent), 0),
+    ("gpu_frame_time", Some(NumberUnit::Milliseconds), 2),
+    ("link_quality", Some(NumberUnit::Fraction), 2),
+    ("poll_latency", Some(NumberUnit::Milliseconds), 0),
+    ("render_height", None, 0),
+    ("render_width", None, 0),
+    ("vr_time_today", Some(NumberUnit::Minutes), 0),
+    ("vr_time_week", Some(NumberUnit::Minutes), 0),
+];
+
+fn numeric(key: &str) -> (Option<NumberUnit>, u32) {
+    NUMERIC
+        .iter()
+        .find(|(name, _, _)| *name == key)
+        .map(|(_, unit, decimals)| (*unit, *decimals))
+        .unwrap_or_else(|| panic!("{} isn't a numeric sensor", key))
+}
+
+/// Converts and rounds numeric sensors as `formats` asks before they're published, and
+/// tells discovery the unit and precision to expect.
+pub struct Formats<'a> {
+    formats: &'a HashMap<String, NumberFormat>,
+}
+
+impl<'a> Formats<'a> {
+    pub fn new(settings: &'a Settings) -> Self {
+        Self {
+            formats: &settings.formats,
+        }
+    }
+
+    /// The unit and decimal places `key` is published with.
+    pub fn get(&self, key: &str) -> (Option<NumberUnit>, u32) {
+        let (unit, decimals) = numeric(key);
+        match self.formats.get(key) {
+            Some(format) => (format.unit.or(unit), format.decimals.unwrap_or(decimals)),
+            None => (unit, decimals),
+        }
+    }
+
+    /// Formats `value`, given in the unit listed in [`NUMERIC`], for publishing as `key`.
+    pub fn format(&self, key: &str, value: f64) -> String {
+        let (from, _) = numeric(key);
+        let (to, decimals) = self.get(key);
+        let value = match (from, to) {
+            (Some(from), Some(to)) => value * from.scale() / to.scale(),
+            _ => value,
+        };
+        format!("{:.*}", decimals as usize, value)
+    }
+}
@@ -0,0 +1,987 @@
+use std::{
+    ffi::{c_void, CStr, CString},
+    fmt,
+    mem::MaybeUninit,
+};
+
+use bindings::openvr::{
+    k_unMaxApplicationKeyLength, k_unMaxTrackedDeviceCount, ChaperoneCalibrationState,
+    Compositor_CumulativeStats, Compositor_FrameTiming, EDeviceActivityLevel, ETextureType,
+    ETrackedControllerRole, ETrackedDeviceClass, ETrackedDeviceProperty, ETrackedPropertyError,
+    ETrackedPropertyError_ETrackedPropertyError_TrackedProp_BufferTooSmall,
+    ETrackingUniverseOrigin, EVRApplicationError, EVRApplicationProperty, EVRApplicationType,
+    EVREventType, EVRInitError, EVROverlayError, EVRSceneApplicationState, EVRSettingsError,
+    EVRTrackedCameraError, IVRApplications_Version, IVRChaperone_Version, IVRCompositor_Version,
+    IVRDriverManager_Version, IVROverlay_Version, IVRSettings_Version, IVRSystem_Version,
+    IVRTrackedCamera_Version, TrackedDeviceIndex_t, TrackedDevicePose_t, VRControllerState_t,
+    VREvent_t, VROverlayHandle_t, VR_IVRApplications_FnTable, VR_IVRChaperone_FnTable,
+    VR_IVRCompositor_FnTable, VR_IVRDriverManager_FnTable, VR_IVROverlay_FnTable,
+    VR_IVRSettings_FnTable, VR_IVRSystem_FnTable, VR_IVRTrackedCamera_FnTable,
+};
+use cstr::cstr;
+use libloading::Library;
+
+/// `VRInitError_Init_InterfaceNotFound`, reported when the runtime doesn't provide the
+/// requested version of an interface.
+const INIT_INTERFACE_NOT_FOUND: EVRInitError = 105;
+
+/// `TextureType_DirectX`, for which `GetOutputDevice` reports the adapter LUID.
+const TEXTURE_TYPE_DIRECTX: ETextureType = 0;
+
+/// `VROverlayError_UnknownOverlay`, reported when nothing has created an overlay with the
+/// requested key.
+const OVERLAY_UNKNOWN: EVROverlayError = 21;
+
+/// Errors from the OpenVR runtime, so callers can tell a runtime that isn't there from a
+/// call that failed.
+#[derive(Debug)]
+pub enum VrError {
+    /// openvr_api couldn't be loaded or doesn't export what's needed.
+    Library(String),
+    /// The runtime refused to start, usually because SteamVR isn't running.
+    InitError {
+        code: EVRInitError,
+        description: String,
+    },
+    /// The installed SteamVR doesn't provide the interface version these bindings use.
+    InterfaceVersionMismatch {
+        interface: String,
+        description: String,
+    },
+    ApplicationError {
+        function: &'static str,
+        code: EVRApplicationError,
+        name: String,
+    },
+    PropertyError {
+        function: &'static str,
+        code: ETrackedPropertyError,
+        name: String,
+    },
+    SettingsError {
+        function: &'static str,
+        code: EVRSettingsError,
+        name: String,
+    },
+    CameraError {
+        function: &'static str,
+        code: EVRTrackedCameraError,
+        name: String,
+    },
+    OverlayError {
+        function: &'static str,
+        code: EVROverlayError,
+        name: String,
+    },
+    /// A driver index that has no name.
+    NoDriverName(u32),
+    /// The runtime returned a string that isn't UTF-8.
+    InvalidString,
+}
+
+impl fmt::Display for VrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VrError::Library(message) => write!(f, "{}", message),
+            VrError::InitError { code, description } => {
+                write!(f, "VR init error {}: {}", code, description)
+            }
+            VrError::InterfaceVersionMismatch {
+                interface,
+                description,
+            } => write!(f, "SteamVR doesn't provide {}: {}", interface, description),
+            VrError::ApplicationError {
+                function,
+                code,
+                name,
+            } => write!(f, "{} error {}: {}", function, code, name),
+            VrError::PropertyError {
+                function,
+                code,
+                name,
+            } => write!(f, "{} error {}: {}", function, code, name),
+            VrError::SettingsError {
+                function,
+                code,
+                name,
+            } => write!(f, "{} error {}: {}", function, code, name),
+            VrError::CameraError {
+                function,
+                code,
+                name,
+            } => write!(f, "{} error {}: {}", function, code, name),
+            VrError::OverlayError {
+                function,
+                code,
+                name,
+            } => write!(f, "{} error {}: {}", function, code, name),
+            VrError::NoDriverName(driver) => write!(f, "Driver {} has no name", driver),
+            VrError::InvalidString => write!(f, "Invalid characters in string"),
+        }
+    }
+}
+
+impl std::error::Error for VrError {}
+
+pub struct OpenVr {
+    library: Library,
+}
+
+fn fntable(version: &'static [u8]) -> CString {
+    const PREFIX: &[u8; 8] = b"FnTable:";
+
+    assert!(version.len() > 1);
+    assert!(!version[0..version.len() - 2].contains(&0));
+    assert!(version[version.len() - 1] == 0);
+
+    let mut result = Vec::with_capacity(PREFIX.len() + version.len());
+    result.extend_from_slice(PREFIX);
+    result.extend_from_slice(version);
+    result.pop();
+
+    unsafe { CString::from_vec_unchecked(result) }
+}
+
+impl OpenVr {
+    pub fn new(application_type: EVRApplicationType) -> Result<Self, VrError> {
+        unsafe {
+            let library = Library::new("openvr_api").map_err(|error| {
+                VrError::Library(format!("Failed to load openvr_api: {}", error))
+            })?;
+
+            let mut error = MaybeUninit::uninit();
+
+            let init2 = library.get::<unsafe extern "C" fn(
+                *mut EVRInitError,
+                EVRApplicationType,
+                *const i8,
+            ) -> *mut c_void>(
+                cstr!("VR_InitInternal2").to_bytes_with_nul()
+            );
+
+            match init2 {
+                Ok(init2) => init2(error.as_mut_ptr(), application_type, cstr!("").as_ptr()),
+                Err(load_error2) => {
+                    let init = library.get::<unsafe extern "C" fn(
+                        *mut EVRInitError,
+                        EVRApplicationType,
+                    ) -> *mut c_void>(
+                        cstr!("VR_InitInternal").to_bytes_with_nul()
+                    );
+                    match init {
+                        Ok(init) => init(error.as_mut_ptr(), application_type),
+                        Err(load_error) => {
+                            return Err(VrError::Library(format!("Neither VR_InitInternal2 nor VR_InitInternal were found.\n{:?}\n{:?}", load_error2, load_error)));
+                        }
+                    }
+                }
+            };
+
+            let error = error.assume_init();
+            if error != 0 {
+                return Err(VrError::InitError {
+                    code: error,
+                    description: OpenVr::describe_init_error(&library, error),
+                });
+            }
+
+            Ok(Self { library })
+        }
+    }
+
+    fn describe_init_error(library: &Library, error: EVRInitError) -> String {
+        unsafe {
+            match library.get::<unsafe extern "C" fn(EVRInitError) -> *const i8>(
+                cstr!("VR_GetVRInitErrorAsEnglishDescription").to_bytes_with_nul(),
+            ) {
+                Ok(describe) => CStr::from_ptr(describe(error))
+                    .to_string_lossy()
+                    .into_owned(),
+                Err(_) => String::new(),
+            }
+        }
+    }
+
+    unsafe fn get_generic_interface<'a, T>(&'a self, name: &CStr) -> Result<&'a T, VrError> {
+        let get = self
+            .library
+            .get::<unsafe extern "C" fn(*const i8, *mut EVRInitError) -> *const T>(
+                b"VR_GetGenericInterface",
+            )
+            .map_err(|error| {
+                VrError::Library(format!("VR_GetGenericInterface not found: {}", error))
+            })?;
+        let mut error = MaybeUninit::uninit();
+        let table = get(name.as_ptr(), error.as_mut_ptr());
+
+        let error = error.assume_init();
+        if error == INIT_INTERFACE_NOT_FOUND {
+            return Err(VrError::InterfaceVersionMismatch {
+                interface: name.to_string_lossy().into_owned(),
+                description: OpenVr::describe_init_error(&self.library, error),
+            });
+        }
+        if error != 0 {
+            return Err(VrError::InitError {
+                code: error,
+                description: OpenVr::describe_init_error(&self.library, error),
+            });
+        }
+
+        Ok(table.as_ref().unwrap())
+    }
+
+    pub fn applications(&self) -> Result<VrApplications, VrError> {
+        unsafe {
+            self.get_generic_interface(&fntable(IVRApplications_Version))
+                .map(VrApplications)
+        }
+    }
+
+    pub fn compositor(&self) -> Result<VrCompositor, VrError> {
+        unsafe {
+            self.get_generic_interface(&fntable(IVRCompositor_Version))
+                .map(VrCompositor)
+        }
+    }
+
+    pub fn chaperone(&self) -> Result<VrChaperone, VrError> {
+        unsafe {
+            self.get_generic_interface(&fntable(IVRChaperone_Version))
+                .map(VrChaperone)
+        }
+    }
+
+    pub fn driver_manager(&self) -> Result<VrDriverManager, VrError> {
+        unsafe {
+            self.get_generic_interface(&fntable(IVRDriverManager_Version))
+                .map(VrDriverManager)
+        }
+    }
+
+    pub fn overlay(&self) -> Result<VrOverlay, VrError> {
+        unsafe {
+            self.get_generic_interface(&fntable(IVROverlay_Version))
+                .map(VrOverlay)
+        }
+    }
+
+    pub fn settings(&self) -> Result<VrSettings, VrError> {
+        unsafe {
+            self.get_generic_interface(&fntable(IVRSettings_Version))
+                .map(VrSettings)
+        }
+    }
+
+    pub fn tracked_camera(&self) -> Result<VrTrackedCamera, VrError> {
+        unsafe {
+            self.get_generic_interface(&fntable(IVRTrackedCamera_Version))
+                .map(VrTrackedCamera)
+        }
+    }
+
+    pub fn system(&self) -> Result<VrSystem, VrError> {
+        unsafe {
+            self.get_generic_interface(&fntable(IVRSystem_Version))
+                .map(VrSystem)
+        }
+    }
+}
+
+impl Drop for OpenVr {
+    fn drop(&mut self) {
+        unsafe {
+            self.library
+                .get::<unsafe extern "C" fn()>(cstr!("VR_ShutdownInternal").to_bytes_with_nul())
+                .unwrap()()
+        }
+    }
+}
+
+pub struct VrApplications<'a>(&'a VR_IVRApplications_FnTable);
+
+impl<'a> VrApplications<'a> {
+    pub fn add_application_manifest(&self, path: &CStr, temporary: bool) -> Result<(), VrError> {
+        unsafe {
+            match (self.0.AddApplicationManifest.unwrap())(path.as_ptr() as _, temporary) {
+                0 => Ok(()),
+                error => Err(self.error("AddApplicationManifest", error)),
+            }
+        }
+    }
+
+    pub fn get_applications_err_name_from_enum(&self, error: EVRApplicationError) -> &'a CStr {
+        unsafe { CStr::from_ptr((self.0.GetApplicationsErrorNameFromEnum.unwrap())(error)) }
+    }
+
+    fn error(&self, function: &'static str, code: EVRApplicationError) -> VrError {
+        VrError::ApplicationError {
+            function,
+            code,
+            name: self
+                .get_applications_err_name_from_enum(code)
+                .to_string_lossy()
+                .into_owned(),
+        }
+    }
+
+    pub fn get_application_auto_launch(&self, id: &CStr) -> bool {
+        unsafe { (self.0.GetApplicationAutoLaunch.unwrap())(id.as_ptr() as _) }
+    }
+
+    pub fn set_application_auto_launch(&self, id: &CStr, autolaunch: bool) -> Result<(), VrError> {
+        unsafe {
+            match (self.0.SetApplicationAutoLaunch.unwrap())(id.as_ptr() as _, autolaunch) {
+                0 => Ok(()),
+                error => Err(self.error("SetApplicationAutoLaunch", error)),
+            }
+        }
+    }
+
+    pub fn launch_application(&self, app_key: &CStr) -> Result<(), VrError> {
+        unsafe {
+            match (self.0.LaunchApplication.unwrap())(app_key.as_ptr() as _) {
+                0 => Ok(()),
+                error => Err(self.error("LaunchApplication", error)),
+            }
+        }
+    }
+
+    /// Returns the keys of applications that can open `mime_type`.
+    pub fn get_applications_that_support_mime_type(
+        &self,
+        mime_type: &CStr,
+    ) -> Result<Vec<String>, VrError> {
+        unsafe {
+            let mut result = Vec::new();
+            loop {
+                let len = result.capacity() as u32;
+                let needed = (self.0.GetApplicationsThatSupportMimeType.unwrap())(
+                    mime_type.as_ptr() as _,
+                    result.as_mut_ptr() as _,
+                    len,
+                );
+                if needed == 0 {
+                    return Ok(Vec::new());
+                }
+                if needed > len {
+                    result.reserve_exact(needed as usize);
+                } else {
+                    // Ignore null terminator.
+                    result.set_len(needed as usize - 1);
+                    let keys = String::from_utf8(result).map_err(|_| VrError::InvalidString)?;
+                    return Ok(keys
+                        .split(',')
+                        .filter(|key| !key.is_empty())
+                        .map(|key| key.to_string())
+                        .collect());
+                }
+            }
+        }
+    }
+
+    pub fn launch_application_from_mime_type(
+        &self,
+        mime_type: &CStr,
+        args: &CStr,
+    ) -> Result<(), VrError> {
+        unsafe {
+            match (self.0.LaunchApplicationFromMimeType.unwrap())(
+                mime_type.as_ptr() as _,
+                args.as_ptr() as _,
+            ) {
+                0 => Ok(()),
+                error => Err(self.error("LaunchApplicationFromMimeType", error)),
+            }
+        }
+    }
+
+    pub fn get_scene_application_state(&self) -> EVRSceneApplicationState {
+        unsafe { (self.0.GetSceneApplicationState.unwrap())() }
+    }
+
+    /// Gets SteamVR ready to launch `app_key`. This quits the current scene application if
+    /// it's a different one, so it's only for paths that are about to launch something.
+    pub fn perform_application_prelaunch_check(&self, app_key: &CStr) -> Result<(), VrError> {
+        unsafe {
+            match (self.0.PerformApplicationPrelaunchCheck.unwrap())(app_key.as_ptr() as _) {
+                0 => Ok(()),
+                error => Err(self.error("PerformApplicationPrelaunchCheck", error)),
+            }
+        }
+    }
+
+    /// Returns the arguments SteamVR launched an application with, given the handle from a
+    /// `VREvent_ApplicationLaunch_t`.
+    pub fn get_application_launch_arguments(&self, handle: u32) -> Result<String, VrError> {
+        unsafe {
+            let mut result = Vec::new();
+            loop {
+                let len = result.capacity() as u32;
+                let needed = (self.0.GetApplicationLaunchArguments.unwrap())(
+                    handle,
+                    result.as_mut_ptr() as _,
+                    len,
+                );
+                if needed == 0 {
+                    return Ok(String::new());
+                }
+                if needed > len {
+                    result.reserve_exact(needed as usize);
+                } else {
+                    // Ignore null terminator.
+                    result.set_len(needed as usize - 1);
+                    return String::from_utf8(result).map_err(|_| VrError::InvalidString);
+                }
+            }
+        }
+    }
+
+    pub fn get_current_scene_process_id(&self) -> u32 {
+        unsafe { (self.0.GetCurrentSceneProcessId.unwrap())() }
+    }
+
+    pub fn get_application_key_by_process_id(&self, process_id: u32) -> Result<CString, VrError> {
+        unsafe {
+            let mut app_key_buffer: [MaybeUninit<_>; k_unMaxApplicationKeyLength as usize] =
+                MaybeUninit::uninit().assume_init();
+            match (self.0.GetApplicationKeyByProcessId.unwrap())(
+                process_id,
+                app_key_buffer[0].as_mut_ptr(),
+                k_unMaxApplicationKeyLength,
+            ) {
+                0 => Ok(app_key_from_buffer(&app_key_buffer)),
+                error => Err(self.error("GetApplicationKeyByProcessId", error)),
+            }
+        }
+    }
+
+    pub fn get_application_count(&self) -> u32 {
+        unsafe { (self.0.GetApplicationCount.unwrap())() }
+    }
+
+    pub fn get_application_key_by_index(&self, index: u32) -> Result<CString, VrError> {
+        unsafe {
+            let mut app_key_buffer: [MaybeUninit<_>; k_unMaxApplicationKeyLength as usize] =
+                MaybeUninit::uninit().assume_init();
+            match (self.0.GetApplicationKeyByIndex.unwrap())(
+                index,
+                app_key_buffer[0].as_mut_ptr(),
+                k_unMaxApplicationKeyLength,
+            ) {
+                0 => Ok(app_key_from_buffer(&app_key_buffer)),
+                error => Err(self.error("GetApplicationKeyByIndex", error)),
+            }
+        }
+    }
+
+    /// Returns the process ID of a running application, or 0 if it isn't running.
+    pub fn get_application_process_id(&self, app_key: &CStr) -> u32 {
+        unsafe { (self.0.GetApplicationProcessId.unwrap())(app_key.as_ptr() as _) }
+    }
+
+    pub fn get_application_property_bool(
+        &self,
+        app_key: &CStr,
+        property: EVRApplicationProperty,
+    ) -> Result<bool, VrError> {
+        let mut error = MaybeUninit::uninit();
+        unsafe {
+            let value = (self.0.GetApplicationPropertyBool.unwrap())(
+                app_key.as_ptr() as _,
+                property,
+                error.as_mut_ptr(),
+            );
+            match error.assume_init() {
+                0 => Ok(value),
+                error => Err(self.error("GetApplicationPropertyBool", error)),
+            }
+        }
+    }
+
+    pub fn get_application_property_string(
+        &self,
+        app_key: &CStr,
+        property: EVRApplicationProperty,
+    ) -> Result<String, VrError> {
+        unsafe {
+            let mut result = Vec::new();
+            loop {
+                let mut error = MaybeUninit::uninit();
+                let len = result.capacity() as u32;
+                let needed = (self.0.GetApplicationPropertyString.unwrap())(
+                    app_key.as_ptr() as _,
+                    property,
+                    result.as_mut_ptr() as _,
+                    len,
+                    error.as_mut_ptr(),
+                );
+                let error = error.assume_init();
+                if error != 0 {
+                    return Err(self.error("GetApplicationPropertyString", error));
+                }
+                if needed > len {
+                    result.reserve_exact(needed as usize);
+                } else {
+                    // Ignore null terminator.
+                    result.set_len(needed as usize - 1);
+                    return String::from_utf8(result).map_err(|_| VrError::InvalidString);
+                }
+            }
+        }
+    }
+}
+
+/// Copies an application key out of a buffer OpenVR filled in, up to the null terminator.
+unsafe fn app_key_from_buffer(
+    app_key_buffer: &[MaybeUninit<i8>; k_unMaxApplicationKeyLength as usize],
+) -> CString {
+    let mut len = 0;
+    loop {
+        if len < k_unMaxApplicationKeyLength as usize && app_key_buffer[len].assume_init() == 0 {
+            break;
+        }
+        len += 1;
+    }
+    let initialized: &[MaybeUninit<i8>] = &app_key_buffer[0..len];
+    let initialized: &[u8] = &*(initialized as *const _ as *const _);
+    let mut vec = Vec::with_capacity(len + 1);
+    vec.extend_from_slice(initialized);
+    CString::from_vec_unchecked(vec)
+}
+
+pub struct VrSystem<'a>(&'a VR_IVRSystem_FnTable);
+
+impl<'a> VrSystem<'a> {
+    pub fn poll_next_event(&self) -> Option<VREvent_t> {
+        unsafe {
+            let mut event = MaybeUninit::uninit();
+            if (self.0.PollNextEvent.unwrap())(
+                event.as_mut_ptr(),
+                std::mem::size_of_val(&event) as _,
+            ) {
+                Some(event.assume_init())
+            } else {
+                None
+            }
+        }
+    }
+
+    pub fn get_event_type_name_from_enum(&self, event_type: EVREventType) -> &'a CStr {
+        unsafe { CStr::from_ptr((self.0.GetEventTypeNameFromEnum.unwrap())(event_type)) }
+    }
+
+    /// Returns true when the headset is an extended desktop display rather than in
+    /// direct mode.
+    pub fn is_display_on_desktop(&self) -> bool {
+        unsafe { (self.0.IsDisplayOnDesktop.unwrap())() }
+    }
+
+    /// Returns the LUID of the graphics adapter the headset is attached to, or `None` if
+    /// SteamVR doesn't know.
+    pub fn get_output_device_luid(&self) -> Option<u64> {
+        let mut device = 0;
+        unsafe {
+            (self.0.GetOutputDevice.unwrap())(
+                &mut device,
+                TEXTURE_TYPE_DIRECTX,
+                std::ptr::null_mut(),
+            )
+        };
+        if device == 0 {
+            None
+        } else {
+            Some(device)
+        }
+    }
+
+    pub fn get_recommended_render_target_size(&self) -> (u32, u32) {
+        let mut width = 0;
+        let mut height = 0;
+        unsafe { (self.0.GetRecommendedRenderTargetSize.unwrap())(&mut width, &mut height) };
+        (width, height)
+    }
+
+    pub fn acknowledge_quit_exiting(&self) {
+        unsafe { (self.0.AcknowledgeQuit_Exiting.unwrap())() }
+    }
+
+    /// Returns the current pose of every tracked device slot.
+    pub fn get_device_to_absolute_tracking_pose(
+        &self,
+        origin: ETrackingUniverseOrigin,
+    ) -> Vec<TrackedDevicePose_t> {
+        unsafe {
+            let mut poses =
+                vec![std::mem::zeroed::<TrackedDevicePose_t>(); k_unMaxTrackedDeviceCount as usize];
+            (self.0.GetDeviceToAbsoluteTrackingPose.unwrap())(
+                origin,
+                0.0,
+                poses.as_mut_ptr(),
+                k_unMaxTrackedDeviceCount,
+            );
+            poses
+        }
+    }
+
+    pub fn get_tracked_device_class(
+        &self,
+        device_index: TrackedDeviceIndex_t,
+    ) -> ETrackedDeviceClass {
+        unsafe { (self.0.GetTrackedDeviceClass.unwrap())(device_index) }
+    }
+
+    pub fn is_tracked_device_connected(&self, device_index: TrackedDeviceIndex_t) -> bool {
+        unsafe { (self.0.IsTrackedDeviceConnected.unwrap())(device_index) }
+    }
+
+    pub fn get_tracked_device_activity_level(
+        &self,
+        device_index: TrackedDeviceIndex_t,
+    ) -> EDeviceActivityLevel {
+        unsafe { (self.0.GetTrackedDeviceActivityLevel.unwrap())(device_index) }
+    }
+
+    pub fn get_controller_role_for_tracked_device_index(
+        &self,
+        device_index: TrackedDeviceIndex_t,
+    ) -> ETrackedControllerRole {
+        unsafe { (self.0.GetControllerRoleForTrackedDeviceIndex.unwrap())(device_index) }
+    }
+
+    pub fn get_tracked_device_index_for_controller_role(
+        &self,
+        role: ETrackedControllerRole,
+    ) -> TrackedDeviceIndex_t {
+        unsafe { (self.0.GetTrackedDeviceIndexForControllerRole.unwrap())(role) }
+    }
+
+    /// Returns `None` when `device_index` isn't a connected controller.
+    pub fn get_controller_state(
+        &self,
+        device_index: TrackedDeviceIndex_t,
+    ) -> Option<VRControllerState_t> {
+        unsafe {
+            let mut state = MaybeUninit::<VRControllerState_t>::uninit();
+            if (self.0.GetControllerState.unwrap())(
+                device_index,
+                state.as_mut_ptr(),
+                std::mem::size_of::<VRControllerState_t>() as _,
+            ) {
+                Some(state.assume_init())
+            } else {
+                None
+            }
+        }
+    }
+
+    pub fn trigger_haptic_pulse(
+        &self,
+        device_index: TrackedDeviceIndex_t,
+        axis_id: u32,
+        duration_micro_sec: u16,
+    ) {
+        unsafe { (self.0.TriggerHapticPulse.unwrap())(device_index, axis_id, duration_micro_sec) }
+    }
+    pub fn get_prop_error_name_from_enum(&self, error: ETrackedPropertyError) -> &'a CStr {
+        unsafe { CStr::from_ptr((self.0.GetPropErrorNameFromEnum.unwrap())(error)) }
+    }
+
+    fn error(&self, function: &'static str, code: ETrackedPropertyError) -> VrError {
+        VrError::PropertyError {
+            function,
+            code,
+            name: self
+                .get_prop_error_name_from_enum(code)
+                .to_string_lossy()
+                .into_owned(),
+        }
+    }
+
+    pub fn get_float_tracked_device_property(
+        &self,
+        device_index: TrackedDeviceIndex_t,
+        property: ETrackedDeviceProperty,
+    ) -> Result<f32, VrError> {
+        unsafe {
+            let mut error = MaybeUninit::uninit();
+            let value = (self.0.GetFloatTrackedDeviceProperty.unwrap())(
+                device_index,
+                property,
+                error.as_mut_ptr(),
+            );
+            match error.assume_init() {
+                0 => Ok(value),
+                error => Err(self.error("GetFloatTrackedDeviceProperty", error)),
+            }
+        }
+    }
+
+    pub fn get_string_tracked_device_property(
+        &self,
+        device_index: TrackedDeviceIndex_t,
+        property: ETrackedDeviceProperty,
+    ) -> Result<String, VrError> {
+        unsafe {
+            let mut result = Vec::new();
+            loop {
+                let mut error = MaybeUninit::uninit();
+                let len = result.capacity() as u32;
+                let needed = (self.0.GetStringTrackedDeviceProperty.unwrap())(
+                    device_index,
+                    property,
+                    result.as_mut_ptr() as _,
+                    len,
+                    error.as_mut_ptr(),
+                );
+                let error = error.assume_init();
+                if error != 0
+                    && error
+                        != ETrackedPropertyError_ETrackedPropertyError_TrackedProp_BufferTooSmall
+                {
+                    return Err(self.error("GetStringTrackedDeviceProperty", error));
+                }
+                if needed > len {
+                    result.reserve_exact(needed as usize);
+                } else {
+                    // Ignore null terminator.
+                    result.set_len(needed as usize - 1);
+                    return String::from_utf8(result).map_err(|_| VrError::InvalidString);
+                }
+            }
+        }
+    }
+}
+
+pub struct VrChaperone<'a>(&'a VR_IVRChaperone_FnTable);
+
+impl<'a> VrChaperone<'a> {
+    pub fn get_calibration_state(&self) -> ChaperoneCalibrationState {
+        unsafe { (self.0.GetCalibrationState.unwrap())() }
+    }
+
+    pub fn are_bounds_visible(&self) -> bool {
+        unsafe { (self.0.AreBoundsVisible.unwrap())() }
+    }
+}
+
+pub struct VrCompositor<'a>(&'a VR_IVRCompositor_FnTable);
+
+impl<'a> VrCompositor<'a> {
+    pub fn show_mirror_window(&self) {
+        unsafe { (self.0.ShowMirrorWindow.unwrap())() }
+    }
+
+    pub fn hide_mirror_window(&self) {
+        unsafe { (self.0.HideMirrorWindow.unwrap())() }
+    }
+
+    pub fn is_mirror_window_visible(&self) -> bool {
+        unsafe { (self.0.IsMirrorWindowVisible.unwrap())() }
+    }
+
+    /// Returns frame totals for the current scene application.
+    pub fn get_cumulative_stats(&self) -> Compositor_CumulativeStats {
+        unsafe {
+            let mut stats = std::mem::zeroed::<Compositor_CumulativeStats>();
+            (self.0.GetCumulativeStats.unwrap())(
+                &mut stats,
+                std::mem::size_of::<Compositor_CumulativeStats>() as u32,
+            );
+            stats
+        }
+    }
+
+    /// Returns timing for up to `frames` recent frames.
+    pub fn get_frame_timings(&self, frames: u32) -> Vec<Compositor_FrameTiming> {
+        unsafe {
+            let mut timings = vec![std::mem::zeroed::<Compositor_FrameTiming>(); frames as usize];
+            for timing in &mut timings {
+                timing.m_nSize = std::mem::size_of::<Compositor_FrameTiming>() as u32;
+            }
+            let count = (self.0.GetFrameTimings.unwrap())(timings.as_mut_ptr(), frames);
+            timings.truncate(count as usize);
+            timings
+        }
+    }
+}
+
+pub struct VrDriverManager<'a>(&'a VR_IVRDriverManager_FnTable);
+
+impl<'a> VrDriverManager<'a> {
+    pub fn get_driver_count(&self) -> u32 {
+        unsafe { (self.0.GetDriverCount.unwrap())() }
+    }
+
+    pub fn get_driver_name(&self, driver: u32) -> Result<String, VrError> {
+        unsafe {
+            let mut result = Vec::new();
+            loop {
+                let len = result.capacity() as u32;
+                let needed = (self.0.GetDriverName.unwrap())(driver, result.as_mut_ptr() as _, len);
+                if needed == 0 {
+                    return Err(VrError::NoDriverName(driver));
+                }
+                if needed > len {
+                    result.reserve_exact(needed as usize);
+                } else {
+                    // Ignore null terminator.
+                    result.set_len(needed as usize - 1);
+                    return String::from_utf8(result).map_err(|_| VrError::InvalidString);
+                }
+            }
+        }
+    }
+
+    pub fn is_enabled(&self, driver: u32) -> bool {
+        unsafe { (self.0.IsEnabled.unwrap())(driver) }
+    }
+}
+
+pub struct VrOverlay<'a>(&'a VR_IVROverlay_FnTable);
+
+impl<'a> VrOverlay<'a> {
+    pub fn get_overlay_error_name_from_enum(&self, error: EVROverlayError) -> &'a CStr {
+        unsafe { CStr::from_ptr((self.0.GetOverlayErrorNameFromEnum.unwrap())(error)) }
+    }
+
+    /// Returns the handle of the overlay with this key, or `None` if no application has
+    /// created it.
+    pub fn find_overlay(&self, overlay_key: &CStr) -> Result<Option<VROverlayHandle_t>, VrError> {
+        let mut handle = MaybeUninit::uninit();
+        unsafe {
+            match (self.0.FindOverlay.unwrap())(overlay_key.as_ptr() as _, handle.as_mut_ptr()) {
+                0 => Ok(Some(handle.assume_init())),
+                OVERLAY_UNKNOWN => Ok(None),
+                code => Err(VrError::OverlayError {
+                    function: "FindOverlay",
+                    code,
+                    name: self
+                        .get_overlay_error_name_from_enum(code)
+                        .to_string_lossy()
+                        .into_owned(),
+                }),
+            }
+        }
+    }
+
+    pub fn is_overlay_visible(&self, handle: VROverlayHandle_t) -> bool {
+        unsafe { (self.0.IsOverlayVisible.unwrap())(handle) }
+    }
+
+    pub fn is_dashboard_visible(&self) -> bool {
+        unsafe { (self.0.IsDashboardVisible.unwrap())() }
+    }
+}
+
+pub struct VrSettings<'a>(&'a VR_IVRSettings_FnTable);
+
+impl<'a> VrSettings<'a> {
+    pub fn get_settings_error_name_from_enum(&self, error: EVRSettingsError) -> &'a CStr {
+        unsafe { CStr::from_ptr((self.0.GetSettingsErrorNameFromEnum.unwrap())(error)) }
+    }
+
+    fn check(
+        &self,
+        function: &'static str,
+        error: MaybeUninit<EVRSettingsError>,
+    ) -> Result<(), VrError> {
+        match unsafe { error.assume_init() } {
+            0 => Ok(()),
+            code => Err(VrError::SettingsError {
+                function,
+                code,
+                name: self
+                    .get_settings_error_name_from_enum(code)
+                    .to_string_lossy()
+                    .into_owned(),
+            }),
+        }
+    }
+
+    pub fn get_bool(&self, section: &CStr, key: &CStr) -> Result<bool, VrError> {
+        let mut error = MaybeUninit::uninit();
+        let value = unsafe {
+            (self.0.GetBool.unwrap())(section.as_ptr() as _, key.as_ptr() as _, error.as_mut_ptr())
+        };
+        self.check("GetBool", error).map(|()| value)
+    }
+
+    pub fn set_bool(&self, section: &CStr, key: &CStr, value: bool) -> Result<(), VrError> {
+        let mut error = MaybeUninit::uninit();
+        unsafe {
+            (self.0.SetBool.unwrap())(
+                section.as_ptr() as _,
+                key.as_ptr() as _,
+                value,
+                error.as_mut_ptr(),
+            )
+        };
+        self.check("SetBool", error)
+    }
+
+    pub fn get_float(&self, section: &CStr, key: &CStr) -> Result<f32, VrError> {
+        let mut error = MaybeUninit::uninit();
+        let value = unsafe {
+            (self.0.GetFloat.unwrap())(section.as_ptr() as _, key.as_ptr() as _, error.as_mut_ptr())
+        };
+        self.check("GetFloat", error).map(|()| value)
+    }
+
+    pub fn set_float(&self, section: &CStr, key: &CStr, value: f32) -> Result<(), VrError> {
+        let mut error = MaybeUninit::uninit();
+        unsafe {
+            (self.0.SetFloat.unwrap())(
+                section.as_ptr() as _,
+                key.as_ptr() as _,
+                value,
+                error.as_mut_ptr(),
+            )
+        };
+        self.check("SetFloat", error)
+    }
+
+    pub fn get_int32(&self, section: &CStr, key: &CStr) -> Result<i32, VrError> {
+        let mut error = MaybeUninit::uninit();
+        let value = unsafe {
+            (self.0.GetInt32.unwrap())(section.as_ptr() as _, key.as_ptr() as _, error.as_mut_ptr())
+        };
+        self.check("GetInt32", error).map(|()| value)
+    }
+
+    pub fn set_int32(&self, section: &CStr, key: &CStr, value: i32) -> Result<(), VrError> {
+        let mut error = MaybeUninit::uninit();
+        unsafe {
+            (self.0.SetInt32.unwrap())(
+                section.as_ptr() as _,
+                key.as_ptr() as _,
+                value,
+                error.as_mut_ptr(),
+            )
+        };
+        self.check("SetInt32", error)
+    }
+}
+
+pub struct VrTrackedCamera<'a>(&'a VR_IVRTrackedCamera_FnTable);
+
+impl<'a> VrTrackedCamera<'a> {
+    pub fn get_camera_error_name_from_enum(&self, error: EVRTrackedCameraError) -> &'a CStr {
+        unsafe { CStr::from_ptr((self.0.GetCameraErrorNameFromEnum.unwrap())(error)) }
+    }
+
+    pub fn has_camera(&self, device_index: TrackedDeviceIndex_t) -> Result<bool, VrError> {
+        let mut has_camera = false;
+        match unsafe { (self.0.HasCamera.unwrap())(device_index, &mut has_camera) } {
+            0 => Ok(has_camera),
+            code => Err(VrError::CameraError {
+                function: "HasCamera",
+                code,
+                name: self
+                    .get_camera_error_name_from_enum(code)
+                    .to_string_lossy()
+                    .into_owned(),
+            }),
+        }
+    }
+}
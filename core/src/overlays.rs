@@ -0,0 +1,77 @@
+use std::{collections::BTreeSet, ffi::CString};
+
+use bindings::openvr::EVRApplicationProperty_EVRApplicationProperty_VRApplicationProperty_IsDashboardOverlay_Bool;
+use log::debug;
+use serde::Serialize;
+
+use crate::{
+    openvr::{VrApplications, VrOverlay},
+    settings::OverlaySettings,
+};
+
+/// Dashboard overlay applications such as fpsVR or OVR Advanced Settings, and which
+/// overlays are on screen.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct OverlayStatus {
+    /// Keys of the running applications whose manifests mark them as dashboard overlays.
+    pub running: BTreeSet<String>,
+    /// Keys of the overlays that are currently visible.
+    pub visible: BTreeSet<String>,
+    pub dashboard_visible: bool,
+}
+
+/// Whether the application's manifest marks it as a dashboard overlay rather than a game.
+pub fn is_overlay_application(applications: &VrApplications, key: &CString) -> bool {
+    applications
+        .get_application_property_bool(
+            key,
+            EVRApplicationProperty_EVRApplicationProperty_VRApplicationProperty_IsDashboardOverlay_Bool,
+        )
+        .unwrap_or(false)
+}
+
+/// OpenVR can't list overlays directly, so this checks the overlays of running overlay
+/// applications, which usually use their application key as the overlay key, along with
+/// any configured overlay keys.
+pub fn read_overlays(
+    applications: &VrApplications,
+    overlay: &VrOverlay,
+    settings: &OverlaySettings,
+) -> OverlayStatus {
+    let mut running = BTreeSet::new();
+    for index in 0..applications.get_application_count() {
+        match applications.get_application_key_by_index(index) {
+            Ok(key) => {
+                if is_overlay_application(applications, &key)
+                    && applications.get_application_process_id(&key) != 0
+                {
+                    running.insert(key.to_string_lossy().into_owned());
+                }
+            }
+            Err(error) => debug!("Failed to read application {} key: {:?}", index, error),
+        }
+    }
+
+    let mut visible = BTreeSet::new();
+    for key in running.iter().chain(settings.keys.iter()) {
+        let overlay_key = match CString::new(key.as_str()) {
+            Ok(overlay_key) => overlay_key,
+            Err(_) => continue,
+        };
+        match overlay.find_overlay(&overlay_key) {
+            Ok(Some(handle)) => {
+                if overlay.is_overlay_visible(handle) {
+                    visible.insert(key.clone());
+                }
+            }
+            Ok(None) => {}
+            Err(error) => debug!("Failed to find overlay {}: {:?}", key, error),
+        }
+    }
+
+    OverlayStatus {
+        running,
+        visible,
+        dashboard_visible: overlay.is_dashboard_visible(),
+    }
+}
@@ -0,0 +1,73 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use log::info;
+
+/// Extensions of files in the installation directory that hold settings or state rather
+/// than the program, and move to the data directory on first run.
+const DATA_EXTENSIONS: &[&str] = &["yaml", "yml", "json", "txt", "pem", "crt", "key"];
+
+/// The directory holding the executable and the files shipped with it, which may be
+/// read-only when installed to Program Files.
+pub fn install_dir() -> Result<PathBuf> {
+    let mut path = ::std::env::current_exe().context("Could not find installation directory")?;
+    path.pop();
+    Ok(path)
+}
+
+/// `%APPDATA%\vr-status`, where settings and state are kept.
+pub fn data_dir() -> Result<PathBuf> {
+    let mut path: PathBuf = ::std::env::var_os("APPDATA")
+        .context("APPDATA is not set")?
+        .into();
+    path.push("vr-status");
+    Ok(path)
+}
+
+/// The path of a writable settings or state file.
+pub fn data_file(name: &str) -> Result<PathBuf> {
+    Ok(data_dir()?.join(name))
+}
+
+/// Creates the data directory, and the first time, copies settings and state from
+/// beside the executable, where older versions kept them. Files that are already in the
+/// data directory are left alone.
+pub async fn migrate() -> Result<()> {
+    let data_dir = data_dir()?;
+    if tokio::fs::metadata(data_dir.join("vr-status.yaml"))
+        .await
+        .is_ok()
+    {
+        return Ok(());
+    }
+    tokio::fs::create_dir_all(&data_dir)
+        .await
+        .with_context(|| format!("Failed to create {}", data_dir.display()))?;
+    let install_dir = install_dir()?;
+    let mut entries = tokio::fs::read_dir(&install_dir)
+        .await
+        .with_context(|| format!("Failed to list {}", install_dir.display()))?;
+    while let Some(entry) = entries.next_entry().await? {
+        let from = entry.path();
+        let is_data = from
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map_or(false, |extension| {
+                DATA_EXTENSIONS
+                    .iter()
+                    .any(|data| data.eq_ignore_ascii_case(extension))
+            });
+        if !is_data || !entry.file_type().await?.is_file() {
+            continue;
+        }
+        let to = data_dir.join(entry.file_name());
+        if tokio::fs::metadata(&to).await.is_ok() {
+            continue;
+        }
+        info!("Copying {} to {}", from.display(), to.display());
+        tokio::fs::copy(&from, &to)
+            .await
+            .with_context(|| format!("Failed to copy {} to {}", from.display(), to.display()))?;
+    }
+    Ok(())
+}
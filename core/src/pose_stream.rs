@@ -0,0 +1,192 @@
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use bindings::openvr::{
+    k_unTrackedDeviceIndexInvalid, k_unTrackedDeviceIndex_Hmd,
+    ETrackedControllerRole_ETrackedControllerRole_TrackedControllerRole_LeftHand,
+    ETrackedControllerRole_ETrackedControllerRole_TrackedControllerRole_RightHand,
+    ETrackingResult_ETrackingResult_TrackingResult_Running_OK, ETrackingUniverseOrigin,
+    ETrackingUniverseOrigin_ETrackingUniverseOrigin_TrackingUniverseSeated,
+    ETrackingUniverseOrigin_ETrackingUniverseOrigin_TrackingUniverseStanding, HmdMatrix34_t,
+    TrackedDevicePose_t,
+};
+use log::{debug, warn};
+use serde::Serialize;
+
+use crate::{
+    openvr::VrSystem,
+    settings::{PoseOrigin, PoseStreamSettings},
+};
+
+#[derive(Serialize)]
+struct DevicePose {
+    device: &'static str,
+    tracking: bool,
+    /// Meters.
+    position: [f32; 3],
+    /// Quaternion as `[w, x, y, z]`.
+    rotation: [f32; 4],
+    /// Meters per second.
+    velocity: [f32; 3],
+    /// Radians per second.
+    angular_velocity: [f32; 3],
+}
+
+impl DevicePose {
+    fn new(device: &'static str, pose: &TrackedDevicePose_t) -> Self {
+        let m = &pose.mDeviceToAbsoluteTracking;
+        Self {
+            device,
+            tracking: pose.bPoseIsValid
+                && pose.eTrackingResult
+                    == ETrackingResult_ETrackingResult_TrackingResult_Running_OK,
+            position: [m.m[0][3], m.m[1][3], m.m[2][3]],
+            rotation: rotation(m),
+            velocity: pose.vVelocity.v,
+            angular_velocity: pose.vAngularVelocity.v,
+        }
+    }
+}
+
+/// Converts the rotation part of a pose matrix to a quaternion.
+fn rotation(m: &HmdMatrix34_t) -> [f32; 4] {
+    let m = &m.m;
+    let trace = m[0][0] + m[1][1] + m[2][2];
+    let (w, x, y, z) = if trace > 0.0 {
+        let s = (trace + 1.0).sqrt() * 2.0;
+        (
+            s / 4.0,
+            (m[2][1] - m[1][2]) / s,
+            (m[0][2] - m[2][0]) / s,
+            (m[1][0] - m[0][1]) / s,
+        )
+    } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+        let s = (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt() * 2.0;
+        (
+            (m[2][1] - m[1][2]) / s,
+            s / 4.0,
+            (m[0][1] + m[1][0]) / s,
+            (m[0][2] + m[2][0]) / s,
+        )
+    } else if m[1][1] > m[2][2] {
+        let s = (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt() * 2.0;
+        (
+            (m[0][2] - m[2][0]) / s,
+            (m[0][1] + m[1][0]) / s,
+            s / 4.0,
+            (m[1][2] + m[2][1]) / s,
+        )
+    } else {
+        let s = (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt() * 2.0;
+        (
+            (m[1][0] - m[0][1]) / s,
+            (m[0][2] + m[2][0]) / s,
+            (m[1][2] + m[2][1]) / s,
+            s / 4.0,
+        )
+    };
+    [w, x, y, z]
+}
+
+/// Sends headset and controller poses as JSON datagrams to a local tool such as a
+/// head-tracking bridge. Poses are never published to MQTT.
+pub struct PoseStream {
+    socket: UdpSocket,
+    origin: ETrackingUniverseOrigin,
+    interval: Duration,
+    started: Instant,
+    next: Instant,
+}
+
+impl PoseStream {
+    pub fn new(settings: &PoseStreamSettings) -> Result<Self> {
+        if !settings.address.ip().is_loopback() {
+            warn!(
+                "Streaming poses to {}, which is not this computer",
+                settings.address
+            );
+        }
+        let local: SocketAddr = if settings.address.is_ipv4() {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        } else {
+            (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let socket = UdpSocket::bind(local).context("Failed to open pose stream socket")?;
+        socket
+            .connect(settings.address)
+            .with_context(|| format!("Failed to address pose stream to {}", settings.address))?;
+        // Poses are sent from the OpenVR loop, which can't wait on the network.
+        socket
+            .set_nonblocking(true)
+            .context("Failed to make pose stream socket non-blocking")?;
+        Ok(Self {
+            socket,
+            origin: match settings.origin {
+                PoseOrigin::Seated => {
+                    ETrackingUniverseOrigin_ETrackingUniverseOrigin_TrackingUniverseSeated
+                }
+                PoseOrigin::Standing => {
+                    ETrackingUniverseOrigin_ETrackingUniverseOrigin_TrackingUniverseStanding
+                }
+            },
+            interval: Duration::from_secs_f64(1.0 / settings.rate_hz as f64),
+            started: Instant::now(),
+            next: Instant::now(),
+        })
+    }
+
+    /// How often poses are sent.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Sends the current poses if one interval has passed since the last ones.
+    pub fn update(&mut self, system: &VrSystem) {
+        let now = Instant::now();
+        if now < self.next {
+            return;
+        }
+        self.next = now + self.interval;
+
+        let poses = system.get_device_to_absolute_tracking_pose(self.origin);
+        let mut devices = Vec::new();
+        for (device, index) in [
+            ("hmd", k_unTrackedDeviceIndex_Hmd),
+            (
+                "left",
+                system.get_tracked_device_index_for_controller_role(
+                    ETrackedControllerRole_ETrackedControllerRole_TrackedControllerRole_LeftHand,
+                ),
+            ),
+            (
+                "right",
+                system.get_tracked_device_index_for_controller_role(
+                    ETrackedControllerRole_ETrackedControllerRole_TrackedControllerRole_RightHand,
+                ),
+            ),
+        ]
+        .iter()
+        {
+            if *index == k_unTrackedDeviceIndexInvalid {
+                continue;
+            }
+            match poses.get(*index as usize) {
+                Some(pose) if pose.bDeviceIsConnected => {
+                    devices.push(DevicePose::new(*device, pose))
+                }
+                _ => {}
+            }
+        }
+        let payload = serde_json::json!({
+            "seconds": now.duration_since(self.started).as_secs_f64(),
+            "devices": devices,
+        });
+        // Nothing may be listening, and a late pose is no use, so failures are dropped.
+        if let Err(error) = self.socket.send(payload.to_string().as_bytes()) {
+            debug!("Failed to send poses: {}", error);
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use sha2::{Digest, Sha256};
+
+use crate::settings::{PrivacyMode, PrivacySettings};
+
+/// Replaces an application key or name with something that doesn't reveal it. Hashes
+/// stay distinct per application so automations can still tell them apart.
+pub fn anonymize(settings: &PrivacySettings, application: &str) -> String {
+    // Nothing running isn't private.
+    if application.is_empty() {
+        return String::new();
+    }
+    match settings.mode {
+        PrivacyMode::Hash => {
+            let mut hasher = Sha256::new();
+            hasher.update(settings.salt.as_bytes());
+            hasher.update(application.as_bytes());
+            hasher.finalize()[..6]
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect()
+        }
+        PrivacyMode::Category => settings
+            .categories
+            .get(application)
+            .unwrap_or(&settings.default_category)
+            .clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(mode: PrivacyMode, salt: &str) -> PrivacySettings {
+        PrivacySettings {
+            mode,
+            salt: salt.to_string(),
+            categories: vec![("steam.app.620".to_string(), "Puzzle".to_string())]
+                .into_iter()
+                .collect(),
+            default_category: "Game".to_string(),
+        }
+    }
+
+    #[test]
+    fn hashes_are_short_stable_and_salted() {
+        let plain = settings(PrivacyMode::Hash, "");
+        let hash = anonymize(&plain, "steam.app.620");
+        assert_eq!(hash.len(), 12);
+        assert!(hash.bytes().all(|byte| byte.is_ascii_hexdigit()));
+        assert_eq!(anonymize(&plain, "steam.app.620"), hash);
+        assert_ne!(anonymize(&plain, "steam.app.546560"), hash);
+        assert_ne!(
+            anonymize(&settings(PrivacyMode::Hash, "salt"), "steam.app.620"),
+            hash
+        );
+    }
+
+    #[test]
+    fn categories_fall_back_to_the_default() {
+        let settings = settings(PrivacyMode::Category, "");
+        assert_eq!(anonymize(&settings, "steam.app.620"), "Puzzle");
+        assert_eq!(anonymize(&settings, "steam.app.546560"), "Game");
+    }
+
+    #[test]
+    fn nothing_running_stays_empty() {
+        assert_eq!(anonymize(&settings(PrivacyMode::Hash, "salt"), ""), "");
+        assert_eq!(anonymize(&settings(PrivacyMode::Category, ""), ""), "");
+    }
+}
@@ -0,0 +1,119 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+/// Where the scene application was started from, so different builds of the same game
+/// can be told apart.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ProcessInfo {
+    pub executable: String,
+    pub working_directory: String,
+    pub launched_by: LaunchSource,
+    /// The arguments SteamVR passed when it launched the application, such as a URL.
+    pub launch_arguments: String,
+}
+
+/// How the scene application was started.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LaunchSource {
+    /// Launched through vr-status over MQTT or IPC.
+    VrStatus,
+    /// Started by SteamVR because its manifest asks to be launched automatically.
+    Auto,
+    /// Started while the SteamVR dashboard was open, usually from its library.
+    Dashboard,
+    /// Started some other way, usually from Steam or the desktop.
+    Desktop,
+}
+
+impl Default for LaunchSource {
+    fn default() -> Self {
+        LaunchSource::Desktop
+    }
+}
+
+/// How long after vr-status asks SteamVR to launch something the next application to
+/// start counts as launched by vr-status.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Remembers what vr-status asked SteamVR to launch and the launch arguments SteamVR
+/// reported, to tell how each scene application was started.
+#[derive(Default)]
+pub struct LaunchTracker {
+    /// The application keys vr-status last asked SteamVR to launch, and when.
+    requested: Option<(Vec<String>, Instant)>,
+    /// Launch arguments by process id.
+    arguments: HashMap<u32, String>,
+    /// The process id of the current scene application and how it was started.
+    current: Option<(u32, LaunchSource)>,
+}
+
+impl LaunchTracker {
+    /// Records that vr-status asked SteamVR to launch one of `keys`.
+    pub fn requested(&mut self, keys: Vec<String>) {
+        self.requested = Some((keys, Instant::now()));
+    }
+
+    /// Records the launch arguments SteamVR reported for `pid`.
+    pub fn arguments(&mut self, pid: u32, arguments: String) {
+        self.arguments.insert(pid, arguments);
+    }
+
+    /// Decides how the scene application `key` was started the first time `pid` is seen,
+    /// and returns that along with its launch arguments.
+    pub fn started(
+        &mut self,
+        key: &str,
+        pid: u32,
+        auto_launch: bool,
+        dashboard_visible: bool,
+    ) -> (LaunchSource, String) {
+        let source = match self.current {
+            Some((current, source)) if current == pid => source,
+            _ => {
+                let requested = match self.requested.take() {
+                    Some((keys, at)) if at.elapsed() < REQUEST_TIMEOUT => {
+                        if keys.iter().any(|requested| requested == key) {
+                            true
+                        } else {
+                            self.requested = Some((keys, at));
+                            false
+                        }
+                    }
+                    _ => false,
+                };
+                let source = if requested {
+                    LaunchSource::VrStatus
+                } else if auto_launch {
+                    LaunchSource::Auto
+                } else if dashboard_visible {
+                    LaunchSource::Dashboard
+                } else {
+                    LaunchSource::Desktop
+                };
+                // Arguments for processes that have since exited are no longer needed.
+                self.arguments
+                    .retain(|arguments_pid, _| *arguments_pid == pid);
+                self.current = Some((pid, source));
+                source
+            }
+        };
+        (
+            source,
+            self.arguments.get(&pid).cloned().unwrap_or_default(),
+        )
+    }
+}
+
+/// How hard the scene application is working the CPU and memory.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ProcessUsage {
+    pub pid: u32,
+    /// Of all cores together, like Task Manager.
+    pub cpu_percent: f32,
+    pub working_set_mb: f64,
+}
@@ -0,0 +1,104 @@
+use std::ffi::CString;
+
+use log::{error, info};
+
+use crate::{
+    openvr::{VrError, VrSettings},
+    settings::{ApplicationProfile, SettingChange, SettingValue},
+};
+
+/// Changes SteamVR settings while the applications named in `on_application` are
+/// running, and puts them back afterwards.
+pub struct Profiles<'a> {
+    profiles: &'a [ApplicationProfile],
+    application_key: String,
+    /// The values the current profile replaced, to restore when the application stops.
+    replaced: Vec<SettingChange>,
+}
+
+impl<'a> Profiles<'a> {
+    pub fn new(profiles: &'a [ApplicationProfile]) -> Self {
+        Self {
+            profiles,
+            application_key: String::new(),
+            replaced: Vec::new(),
+        }
+    }
+
+    /// Applies the profile for `application_key` if it's different from last time,
+    /// restoring whatever the previous one changed first. Returns whether any settings
+    /// changed.
+    pub fn update(&mut self, application_key: &str, vr_settings: &VrSettings) -> bool {
+        if application_key == self.application_key {
+            return false;
+        }
+        self.application_key = application_key.to_string();
+        let mut changed = false;
+        for change in self.replaced.drain(..).rev() {
+            if let Err(error) = apply(vr_settings, &change) {
+                error!(
+                    "Failed to restore {}.{}: {:?}",
+                    change.section, change.key, error
+                );
+            }
+            changed = true;
+        }
+        let profile = self
+            .profiles
+            .iter()
+            .find(|profile| profile.key.iter().any(|key| key == application_key));
+        if let Some(profile) = profile {
+            info!("Applying settings for {}", application_key);
+            for change in &profile.set {
+                if profile.restore {
+                    match read(vr_settings, change) {
+                        Ok(previous) => self.replaced.push(previous),
+                        Err(error) => error!(
+                            "Failed to read {}.{}, so it won't be restored: {:?}",
+                            change.section, change.key, error
+                        ),
+                    }
+                }
+                if let Err(error) = apply(vr_settings, change) {
+                    error!(
+                        "Failed to set {}.{}: {:?}",
+                        change.section, change.key, error
+                    );
+                }
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+fn names(change: &SettingChange) -> (CString, CString) {
+    // Settings validation rejects names with null characters.
+    (
+        CString::new(change.section.as_str()).unwrap(),
+        CString::new(change.key.as_str()).unwrap(),
+    )
+}
+
+/// Reads the current value of the setting `change` would change.
+fn read(vr_settings: &VrSettings, change: &SettingChange) -> Result<SettingChange, VrError> {
+    let (section, key) = names(change);
+    let value = match change.value {
+        SettingValue::Bool(_) => SettingValue::Bool(vr_settings.get_bool(&section, &key)?),
+        SettingValue::Int(_) => SettingValue::Int(vr_settings.get_int32(&section, &key)?),
+        SettingValue::Float(_) => SettingValue::Float(vr_settings.get_float(&section, &key)?),
+    };
+    Ok(SettingChange {
+        value,
+        ..change.clone()
+    })
+}
+
+fn apply(vr_settings: &VrSettings, change: &SettingChange) -> Result<(), VrError> {
+    let (section, key) = names(change);
+    match change.value {
+        SettingValue::Bool(value) => vr_settings.set_bool(&section, &key, value),
+        SettingValue::Int(value) => vr_settings.set_int32(&section, &key, value),
+        SettingValue::Float(value) => vr_settings.set_float(&section, &key, value),
+    }
+}
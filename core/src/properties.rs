@@ -0,0 +1,81 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use bindings::openvr::{ETrackedDeviceProperty, TrackedDeviceIndex_t};
+
+use crate::openvr::VrSystem;
+
+/// How long a property is trusted without a `VREvent_PropertyChanged` for it. Drivers
+/// don't send the event for every change, so values are still read again eventually.
+const TTL: Duration = Duration::from_secs(30);
+
+type Key = (TrackedDeviceIndex_t, ETrackedDeviceProperty);
+
+/// Remembers tracked device properties between polls, so reading the same labels and
+/// batteries every cycle doesn't cost a runtime call each, or two for every string.
+/// Properties a device doesn't have are remembered as `None` too.
+#[derive(Default)]
+pub struct PropertyCache {
+    strings: HashMap<Key, (Instant, Option<String>)>,
+    floats: HashMap<Key, (Instant, Option<f32>)>,
+}
+
+impl PropertyCache {
+    pub fn string(
+        &mut self,
+        system: &VrSystem,
+        device_index: TrackedDeviceIndex_t,
+        property: ETrackedDeviceProperty,
+    ) -> Option<String> {
+        let now = Instant::now();
+        match self.strings.get(&(device_index, property)) {
+            Some((read_at, value)) if now.duration_since(*read_at) < TTL => value.clone(),
+            _ => {
+                let value = system
+                    .get_string_tracked_device_property(device_index, property)
+                    .ok();
+                self.strings
+                    .insert((device_index, property), (now, value.clone()));
+                value
+            }
+        }
+    }
+
+    pub fn float(
+        &mut self,
+        system: &VrSystem,
+        device_index: TrackedDeviceIndex_t,
+        property: ETrackedDeviceProperty,
+    ) -> Option<f32> {
+        let now = Instant::now();
+        match self.floats.get(&(device_index, property)) {
+            Some((read_at, value)) if now.duration_since(*read_at) < TTL => *value,
+            _ => {
+                let value = system
+                    .get_float_tracked_device_property(device_index, property)
+                    .ok();
+                self.floats.insert((device_index, property), (now, value));
+                value
+            }
+        }
+    }
+
+    /// Forgets one property, for `VREvent_PropertyChanged`.
+    pub fn invalidate(
+        &mut self,
+        device_index: TrackedDeviceIndex_t,
+        property: ETrackedDeviceProperty,
+    ) {
+        self.strings.remove(&(device_index, property));
+        self.floats.remove(&(device_index, property));
+    }
+
+    /// Forgets everything about a device that connected or disconnected, since its index
+    /// can be given to a different device.
+    pub fn forget_device(&mut self, device_index: TrackedDeviceIndex_t) {
+        self.strings.retain(|(index, _), _| *index != device_index);
+        self.floats.retain(|(index, _), _| *index != device_index);
+    }
+}
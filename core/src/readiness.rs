@@ -0,0 +1,78 @@
+use bindings::openvr::{
+    k_unTrackedDeviceIndexInvalid, k_unTrackedDeviceIndex_Hmd,
+    ETrackedControllerRole_ETrackedControllerRole_TrackedControllerRole_LeftHand,
+    ETrackedControllerRole_ETrackedControllerRole_TrackedControllerRole_RightHand,
+    ETrackedDeviceProperty_ETrackedDeviceProperty_Prop_DeviceBatteryPercentage_Float,
+};
+
+use crate::{
+    base_stations::read_base_stations, openvr::VrSystem, properties::PropertyCache,
+    settings::ReadySettings,
+};
+
+/// Whether someone could put the headset on and play right now, and if not, why.
+#[derive(Clone, PartialEq)]
+pub struct Readiness {
+    pub ready: bool,
+    /// Something for a person to fix for each thing in the way, e.g. `Charge the left
+    /// controller (12%)`.
+    pub blockers: Vec<String>,
+}
+
+impl Readiness {
+    pub fn check(
+        system: &VrSystem,
+        properties: &mut PropertyCache,
+        settings: &ReadySettings,
+    ) -> Self {
+        let mut blockers = Vec::new();
+        if !system.is_tracked_device_connected(k_unTrackedDeviceIndex_Hmd) {
+            blockers.push("Connect the headset".to_string());
+        }
+        if settings.base_stations {
+            let base_stations = read_base_stations(system);
+            if base_stations.is_empty() {
+                blockers.push("No base stations found".to_string());
+            }
+            for (serial, _) in base_stations.iter().filter(|(_, awake)| !**awake) {
+                blockers.push(format!("Wake base station {}", serial));
+            }
+        }
+        if settings.controllers {
+            for (hand, role) in &[
+                (
+                    "left",
+                    ETrackedControllerRole_ETrackedControllerRole_TrackedControllerRole_LeftHand,
+                ),
+                (
+                    "right",
+                    ETrackedControllerRole_ETrackedControllerRole_TrackedControllerRole_RightHand,
+                ),
+            ] {
+                let index = system.get_tracked_device_index_for_controller_role(*role);
+                if index == k_unTrackedDeviceIndexInvalid
+                    || !system.is_tracked_device_connected(index)
+                {
+                    blockers.push(format!("Turn on the {} controller", hand));
+                    continue;
+                }
+                let battery = properties.float(
+                    system,
+                    index,
+                    ETrackedDeviceProperty_ETrackedDeviceProperty_Prop_DeviceBatteryPercentage_Float,
+                );
+                if let Some(battery) = battery.filter(|&battery| battery < settings.battery_below) {
+                    blockers.push(format!(
+                        "Charge the {} controller ({:.0}%)",
+                        hand,
+                        battery * 100.0
+                    ));
+                }
+            }
+        }
+        Readiness {
+            ready: blockers.is_empty(),
+            blockers,
+        }
+    }
+}
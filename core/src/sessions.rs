@@ -0,0 +1,68 @@
+use std::{io::ErrorKind, path::PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::paths::data_file;
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct SessionCounts {
+    /// SteamVR sessions started after the first one.
+    pub restarts: u64,
+    /// Sessions that ended without SteamVR asking vr-status to quit.
+    pub crashes: u64,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+struct SessionFile {
+    #[serde(flatten)]
+    counts: SessionCounts,
+    running: bool,
+}
+
+/// Counts SteamVR sessions, persisted in the data directory. SteamVR starts vr-status
+/// with every session, so a session that was still running when the next one starts
+/// must have ended without a quit event.
+pub struct SessionLog {
+    path: PathBuf,
+    file: SessionFile,
+}
+
+impl SessionLog {
+    pub async fn start() -> Result<Self> {
+        let path = data_file("vr-status.sessions.json")?;
+        let mut file = match tokio::fs::read(&path).await {
+            Ok(contents) => {
+                let mut file: SessionFile =
+                    serde_json::from_slice(&contents).context("Failed to parse sessions")?;
+                file.counts.restarts += 1;
+                if file.running {
+                    file.counts.crashes += 1;
+                }
+                file
+            }
+            Err(error) if error.kind() == ErrorKind::NotFound => SessionFile::default(),
+            Err(error) => return Err(error).context("Failed to read sessions"),
+        };
+        file.running = true;
+        let log = Self { path, file };
+        log.save().await?;
+        Ok(log)
+    }
+
+    pub fn counts(&self) -> &SessionCounts {
+        &self.file.counts
+    }
+
+    /// Records that SteamVR asked vr-status to quit.
+    pub async fn quit(&mut self) -> Result<()> {
+        self.file.running = false;
+        self.save().await
+    }
+
+    async fn save(&self) -> Result<()> {
+        tokio::fs::write(&self.path, serde_json::to_vec(&self.file).unwrap())
+            .await
+            .context("Failed to write sessions")
+    }
+}
@@ -0,0 +1,1588 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    pin::Pin,
+};
+
+use anyhow::{bail, Context, Result};
+use bindings::openvr::ETrackedDeviceProperty;
+use chrono::{NaiveTime, Weekday};
+use serde::{Deserialize, Deserializer};
+use serde_yaml::Value;
+use tokio::{fs::File, io::AsyncReadExt};
+
+use crate::{
+    derived::Expression,
+    events::Occurrence,
+    numbers::{NumberUnit, NUMERIC},
+    paths::{data_file, migrate},
+    telemetry::PERIODIC,
+};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    pub id: String,
+    pub name: String,
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+    #[serde(default = "default_hass_prefix")]
+    pub hass_prefix: String,
+    #[serde(default)]
+    pub hass_unique_id: UniqueIdSource,
+    #[serde(default)]
+    pub hass_discovery: DiscoveryFormat,
+    #[serde(default = "default_hass_name_template")]
+    pub hass_name_template: String,
+    #[serde(default)]
+    pub hass_names: HashMap<String, String>,
+    pub mqtt: MqttSettings,
+    #[serde(default)]
+    pub file: Option<FileSettings>,
+    #[serde(default)]
+    pub updater: Option<UpdaterSettings>,
+    #[serde(default)]
+    pub wireless: Option<WirelessSettings>,
+    #[serde(default)]
+    pub derived: BTreeMap<String, String>,
+    #[serde(default)]
+    pub formats: HashMap<String, NumberFormat>,
+    #[serde(default)]
+    pub on_application: Vec<ApplicationProfile>,
+    #[serde(default)]
+    pub hooks: Vec<HookSettings>,
+    #[serde(default)]
+    pub payloads: Payloads,
+    /// Publish state as `{"value": ..., "changed_at": ...}` instead of the bare value.
+    #[serde(default)]
+    pub include_timestamps: bool,
+    #[serde(default)]
+    pub frame_timing: Option<FrameTimingSettings>,
+    #[serde(default)]
+    pub base_stations: Option<BaseStationSettings>,
+    #[serde(default)]
+    pub devices: Option<DeviceSettings>,
+    #[serde(default)]
+    pub application_elapsed: Option<ApplicationElapsedSettings>,
+    #[serde(default)]
+    pub stats: Option<StatsSettings>,
+    #[serde(default)]
+    pub process_usage: Option<ProcessUsageSettings>,
+    #[serde(default)]
+    pub overlays: Option<OverlaySettings>,
+    #[serde(default)]
+    pub metrics: Option<MetricsSettings>,
+    #[serde(default)]
+    pub raw_events: Option<RawEventSettings>,
+    #[serde(default)]
+    pub notifications: Option<NotificationSettings>,
+    #[serde(default)]
+    pub idle_application: Option<IdleApplicationSettings>,
+    #[serde(default)]
+    pub application_lookup: ApplicationLookupSettings,
+    #[serde(default)]
+    pub panic_gesture: Option<PanicGestureSettings>,
+    #[serde(default)]
+    pub controllers_in_use: Option<ControllerActivitySettings>,
+    #[serde(default)]
+    pub ready_to_play: Option<ReadySettings>,
+    #[serde(default)]
+    pub pose_stream: Option<PoseStreamSettings>,
+    #[serde(default)]
+    pub privacy: Option<PrivacySettings>,
+    #[serde(default)]
+    pub arcade: Option<ArcadeSettings>,
+    #[serde(default)]
+    pub ipc: Option<IpcSettings>,
+    #[serde(default)]
+    pub web: Option<WebSettings>,
+    #[serde(default)]
+    pub steamvr: SteamVrSettings,
+    #[serde(default)]
+    pub ignore: IgnoreSettings,
+    #[serde(default)]
+    pub in_game: InGameSettings,
+    #[serde(default)]
+    pub active: ActiveSettings,
+    #[serde(default)]
+    pub disconnects: DisconnectSettings,
+    #[serde(default)]
+    pub watchdog: WatchdogSettings,
+    #[serde(default)]
+    pub standby: StandbySettings,
+}
+
+impl Settings {
+    /// Checks values that parse but can't work, naming the offending key.
+    pub fn validate(&self) -> Result<()> {
+        check_topic_level("id", &self.id)?;
+        check_topic("prefix", &self.prefix)?;
+        check_topic("hass_prefix", &self.hass_prefix)?;
+        if !self.hass_name_template.contains("{sensor}") {
+            bail!(
+                "hass_name_template must contain {{sensor}} or every entity gets the same name; try {:?}",
+                "{name} {sensor}"
+            );
+        }
+        if (self.mqtt.host.is_empty() && !self.mqtt.discover && self.mqtt.srv_domain.is_none())
+            || self.mqtt.host.iter().any(|host| host.is_empty())
+        {
+            bail!(
+                "mqtt.host must name at least one broker, e.g. `host: mqtt.example.com`, unless mqtt.discover is true or mqtt.srv_domain is set"
+            );
+        }
+        if self.mqtt.srv_domain.as_deref() == Some("") {
+            bail!("mqtt.srv_domain can't be empty");
+        }
+        if let Some(credentials) = &self.mqtt.credentials {
+            if credentials.password_file.is_some() && !credentials.password.is_empty() {
+                bail!("mqtt.credentials can have a password or a password_file but not both");
+            }
+        }
+        if self.mqtt.port == Some(0) {
+            bail!("mqtt.port can't be 0; leave it out to use 1883 for tcp or 8883 for tls");
+        }
+        let manifest = &self.steamvr.manifest;
+        if !manifest.is_default() && !self.steamvr.generate_manifest {
+            bail!("steamvr.manifest only changes the generated manifest; set steamvr.generate_manifest: true");
+        }
+        if manifest.name.as_deref() == Some("") {
+            bail!("steamvr.manifest.name can't be empty");
+        }
+        match (&manifest.launch_type, &manifest.url) {
+            (ManifestLaunchType::Url, None) => {
+                bail!("steamvr.manifest.launch_type Url needs steamvr.manifest.url")
+            }
+            (ManifestLaunchType::Binary, Some(_)) => {
+                bail!("steamvr.manifest.url is only used with launch_type: Url")
+            }
+            _ => {}
+        }
+        // A limit of 0 would hold every message forever; leaving the limit out is how to
+        // turn it off.
+        if self.mqtt.rate_limit.global == Some(0) {
+            bail!("mqtt.rate_limit.global can't be 0; leave it out for no limit");
+        }
+        for (topic, limit) in &self.mqtt.rate_limit.topics {
+            check_topic(&format!("mqtt.rate_limit.topics.{}", topic), topic)?;
+            if *limit == 0 {
+                bail!(
+                    "mqtt.rate_limit.topics.{} can't be 0; leave it out for no limit",
+                    topic
+                );
+            }
+        }
+        for (name, source) in &self.derived {
+            check_topic_level(&format!("derived.{}", name), name)?;
+            Expression::parse(source)
+                .and_then(|expression| expression.check_variables())
+                .with_context(|| format!("derived.{} isn't a valid expression", name))?;
+        }
+        check_feature("file", cfg!(feature = "file"), self.file.is_some())?;
+        check_feature("ipc", cfg!(feature = "ipc"), self.ipc.is_some())?;
+        check_feature("mqtt.discover", cfg!(feature = "mdns"), self.mqtt.discover)?;
+        check_feature(
+            "mqtt.srv_domain",
+            cfg!(feature = "srv"),
+            self.mqtt.srv_domain.is_some(),
+        )?;
+        check_feature(
+            "notifications",
+            cfg!(feature = "notifications"),
+            self.notifications.is_some(),
+        )?;
+        check_feature("updater", cfg!(feature = "updater"), self.updater.is_some())?;
+        check_feature("web", cfg!(feature = "web"), self.web.is_some())?;
+        for (name, interval_secs) in &[
+            (
+                "application_elapsed",
+                self.application_elapsed.as_ref().map(|s| s.interval_secs),
+            ),
+            ("stats", self.stats.as_ref().map(|s| s.interval_secs)),
+            ("metrics", self.metrics.as_ref().map(|s| s.interval_secs)),
+        ] {
+            if *interval_secs == Some(0) {
+                bail!(
+                    "{}.interval_secs must be more than 0 or it's published nonstop",
+                    name
+                );
+            }
+        }
+        if let Some(updater) = &self.updater {
+            if updater.interval_secs == 0 {
+                bail!("updater.interval_secs must be more than 0 or GitHub is asked for releases nonstop");
+            }
+        }
+        if let Some(panic_gesture) = &self.panic_gesture {
+            if panic_gesture.buttons.is_empty() {
+                bail!("panic_gesture.buttons must list at least one button, e.g. [Menu]");
+            }
+            if panic_gesture.hold_secs <= 0.0 {
+                bail!("panic_gesture.hold_secs must be more than 0 or brushing a button would call for help");
+            }
+        }
+        if let Some(pose_stream) = &self.pose_stream {
+            if pose_stream.rate_hz == 0 || pose_stream.rate_hz > 1000 {
+                bail!("pose_stream.rate_hz must be between 1 and 1000");
+            }
+        }
+        if self.watchdog.timeout_secs < 10 {
+            bail!("watchdog.timeout_secs must be at least 10 or vr-status restarts between heartbeats");
+        }
+        if self.disconnects.window_secs == 0 || self.disconnects.threshold == 0 {
+            bail!("disconnects.window_secs and disconnects.threshold must be more than 0");
+        }
+        for name in self.standby.intervals.keys() {
+            if !PERIODIC.contains(&name.as_str()) {
+                bail!(
+                    "standby.intervals.{} isn't a periodic publish; use one of {}",
+                    name,
+                    PERIODIC.join(", ")
+                );
+            }
+        }
+        for (name, format) in &self.formats {
+            let unit = match NUMERIC.iter().find(|(numeric, _, _)| numeric == name) {
+                Some((_, unit, _)) => *unit,
+                None => bail!(
+                    "formats.{} isn't a numeric sensor; use one of {}",
+                    name,
+                    NUMERIC
+                        .iter()
+                        .map(|(numeric, _, _)| *numeric)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            };
+            if let Some(to) = format.unit {
+                match unit {
+                    Some(from) if from.converts_to(to) => {}
+                    Some(from) => bail!(
+                        "formats.{} is measured in {:?} and can't be published in {:?}",
+                        name,
+                        from,
+                        to
+                    ),
+                    None => bail!("formats.{} can't be converted to another unit", name),
+                }
+            }
+        }
+        for (index, profile) in self.on_application.iter().enumerate() {
+            if profile.key.is_empty() || profile.set.is_empty() {
+                bail!(
+                    "on_application[{}] needs at least one key and one setting to set",
+                    index
+                );
+            }
+            for change in &profile.set {
+                if change.section.is_empty()
+                    || change.key.is_empty()
+                    || change.section.contains('\0')
+                    || change.key.contains('\0')
+                {
+                    bail!(
+                        "on_application[{}] has a setting without a valid section and key",
+                        index
+                    );
+                }
+            }
+        }
+        if let Some(ready) = &self.ready_to_play {
+            if !(0.0..=1.0).contains(&ready.battery_below) {
+                bail!(
+                    "ready_to_play.battery_below is a fraction of a full charge, e.g. 0.2 for 20%"
+                );
+            }
+        }
+        for (index, hook) in self.hooks.iter().enumerate() {
+            if hook.command.is_empty() {
+                bail!("hooks[{}] needs a command", index);
+            }
+            if hook.on.is_empty() {
+                bail!("hooks[{}] needs at least one event in on", index);
+            }
+            if let Some(on) = hook.on.iter().find(|on| {
+                !Occurrence::ALL
+                    .iter()
+                    .any(|occurrence| occurrence.name() == on.as_str())
+            }) {
+                bail!(
+                    "hooks[{}] runs on {:?}, which isn't an event; try one of {}",
+                    index,
+                    on,
+                    Occurrence::ALL
+                        .iter()
+                        .map(|occurrence| occurrence.name())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+        }
+        for (topic, payload) in &self.payloads.0 {
+            if payload.on == payload.off {
+                bail!(
+                    "payloads.{} uses {:?} for both on and off, so the state can't be told apart",
+                    topic,
+                    payload.on
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// The topic this instance's state is published under.
+    pub fn base_topic(&self) -> String {
+        match self.arcade {
+            Some(_) => format!("{}/stations/{}", self.prefix, self.id),
+            None => format!("{}/{}", self.prefix, self.id),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MqttSettings {
+    #[serde(default, deserialize_with = "one_or_many")]
+    pub host: Vec<String>,
+    /// Look for a broker on the local network with mDNS before trying `host`.
+    #[serde(default)]
+    pub discover: bool,
+    /// Look up brokers in this domain's SRV records before trying `host`.
+    #[serde(default)]
+    pub srv_domain: Option<String>,
+    #[serde(default)]
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub transport: MqttTransport,
+    #[serde(default)]
+    pub ip_family: IpFamily,
+    #[serde(default)]
+    pub credentials: Option<MqttCredential>,
+    #[serde(default)]
+    pub rate_limit: RateLimitSettings,
+    #[serde(default)]
+    pub tls: TlsSettings,
+}
+
+fn check_topic(key: &str, value: &str) -> Result<()> {
+    if value.is_empty() {
+        bail!("{} can't be empty", key);
+    }
+    if let Some(c) = value.chars().find(|&c| c == '+' || c == '#' || c == '\0') {
+        bail!(
+            "{} contains {:?}, which isn't allowed in MQTT topics; try {:?}",
+            key,
+            c,
+            value.replace(&['+', '#', '\0'][..], "-")
+        );
+    }
+    Ok(())
+}
+
+/// Like `check_topic`, but for values that must stay within a single topic level.
+fn check_topic_level(key: &str, value: &str) -> Result<()> {
+    check_topic(key, value)?;
+    if value.contains('/') {
+        bail!(
+            "{} contains '/', which would split it across topic levels; try {:?}",
+            key,
+            value.replace('/', "-")
+        );
+    }
+    Ok(())
+}
+
+/// Fails if `key` is used but this build left out the feature that handles it, rather than
+/// silently ignoring it.
+fn check_feature(key: &str, built: bool, used: bool) -> Result<()> {
+    if used && !built {
+        bail!(
+            "{} isn't available because vr-status was built without it; remove it or rebuild with the default features",
+            key
+        );
+    }
+    Ok(())
+}
+
+fn one_or_many<'de, D>(deserializer: D) -> std::result::Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(host) => vec![host],
+        OneOrMany::Many(hosts) => hosts,
+    })
+}
+
+fn default_prefix() -> String {
+    "vr-status".to_string()
+}
+
+fn default_hass_prefix() -> String {
+    "homeassistant".into()
+}
+
+fn default_hass_name_template() -> String {
+    "{name} {sensor}".into()
+}
+
+#[derive(Deserialize, PartialEq)]
+pub enum UniqueIdSource {
+    Id,
+    Serial,
+}
+
+impl Default for UniqueIdSource {
+    fn default() -> Self {
+        UniqueIdSource::Id
+    }
+}
+
+#[derive(Deserialize)]
+pub enum DiscoveryFormat {
+    Entity,
+    Device,
+}
+
+impl Default for DiscoveryFormat {
+    fn default() -> Self {
+        DiscoveryFormat::Entity
+    }
+}
+
+#[derive(Deserialize)]
+pub enum MqttTransport {
+    Tcp,
+    Tls,
+}
+
+impl Default for MqttTransport {
+    fn default() -> Self {
+        MqttTransport::Tls
+    }
+}
+
+/// Which addresses of the broker to connect to when its name resolves to both.
+#[derive(Clone, Copy, Deserialize, PartialEq)]
+pub enum IpFamily {
+    /// Whatever the resolver returns, in its order.
+    Auto,
+    V4,
+    V6,
+}
+
+impl Default for IpFamily {
+    fn default() -> Self {
+        IpFamily::Auto
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MqttCredential {
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+    /// File containing the password, relative to `vr-status.yaml`. Read into `password`
+    /// by `load_settings`.
+    #[serde(default)]
+    pub password_file: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct TlsSettings {
+    /// Name the broker's certificate must match instead of the host connected to.
+    #[serde(default)]
+    pub sni: Option<String>,
+    /// Protocols to offer during ALPN negotiation.
+    #[serde(default)]
+    pub alpn: Vec<String>,
+    /// Skip certificate verification entirely.
+    #[serde(default)]
+    pub danger_accept_invalid_certs: bool,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitSettings {
+    /// Maximum messages per minute across all state topics.
+    #[serde(default)]
+    pub global: Option<u32>,
+    /// Maximum messages per minute keyed by topic relative to `<prefix>/<id>/`.
+    #[serde(default)]
+    pub topics: HashMap<String, u32>,
+    /// Hold each change this long so bursts collapse into a single message.
+    #[serde(default)]
+    pub coalesce_ms: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileSettings {
+    pub path: PathBuf,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpdaterSettings {
+    #[serde(default = "default_updater_repository")]
+    pub repository: String,
+    #[serde(default = "default_updater_interval_secs")]
+    pub interval_secs: u64,
+    #[serde(default)]
+    pub stage: bool,
+}
+
+fn default_updater_repository() -> String {
+    "mdonoughe/vr-status".into()
+}
+
+fn default_updater_interval_secs() -> u64 {
+    24 * 60 * 60
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WirelessSettings {
+    pub quality_property: ETrackedDeviceProperty,
+    #[serde(default = "default_wireless_problem_below")]
+    pub problem_below: f32,
+    #[serde(default = "default_wireless_interval_secs")]
+    pub interval_secs: u64,
+}
+
+fn default_wireless_problem_below() -> f32 {
+    0.5
+}
+
+fn default_wireless_interval_secs() -> u64 {
+    5
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FrameTimingSettings {
+    #[serde(default = "default_frame_timing_interval_secs")]
+    pub interval_secs: u64,
+}
+
+fn default_frame_timing_interval_secs() -> u64 {
+    10
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProcessUsageSettings {
+    #[serde(default = "default_process_usage_interval_secs")]
+    pub interval_secs: u64,
+}
+
+fn default_process_usage_interval_secs() -> u64 {
+    10
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OverlaySettings {
+    #[serde(default = "default_overlay_interval_secs")]
+    pub interval_secs: u64,
+    /// Overlay keys to check besides those of running overlay applications, for tools
+    /// whose overlay key isn't their application key.
+    #[serde(default)]
+    pub keys: Vec<String>,
+}
+
+fn default_overlay_interval_secs() -> u64 {
+    5
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BaseStationSettings {
+    #[serde(default = "default_base_station_interval_secs")]
+    pub interval_secs: u64,
+}
+
+fn default_base_station_interval_secs() -> u64 {
+    10
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceSettings {
+    #[serde(default = "default_device_interval_secs")]
+    pub interval_secs: u64,
+}
+
+fn default_device_interval_secs() -> u64 {
+    30
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SteamVrSettings {
+    #[serde(default = "default_true")]
+    pub register_manifest: bool,
+    #[serde(default = "default_true")]
+    pub auto_launch: bool,
+    /// Register the manifest only until SteamVR exits.
+    #[serde(default)]
+    pub portable: bool,
+    /// Register a manifest generated with the executable's current path instead of the
+    /// shipped one.
+    #[serde(default)]
+    pub generate_manifest: bool,
+    /// Changes how vr-status appears in SteamVR. Only used with `generate_manifest`.
+    #[serde(default)]
+    pub manifest: ManifestSettings,
+}
+
+impl Default for SteamVrSettings {
+    fn default() -> Self {
+        SteamVrSettings {
+            register_manifest: true,
+            auto_launch: true,
+            portable: false,
+            generate_manifest: false,
+            manifest: Default::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestSettings {
+    /// Replaces the name shown in SteamVR's startup applications.
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub launch_type: ManifestLaunchType,
+    /// What SteamVR opens when `launch_type` is `Url`.
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+impl ManifestSettings {
+    fn is_default(&self) -> bool {
+        self.name.is_none()
+            && self.description.is_none()
+            && self.launch_type == ManifestLaunchType::Binary
+            && self.url.is_none()
+    }
+}
+
+#[derive(Deserialize, PartialEq)]
+pub enum ManifestLaunchType {
+    /// SteamVR starts this executable.
+    Binary,
+    /// SteamVR opens `url`, e.g. to start a wrapper through another launcher.
+    Url,
+}
+
+impl Default for ManifestLaunchType {
+    fn default() -> Self {
+        ManifestLaunchType::Binary
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct IgnoreSettings {
+    /// Event type names such as `VREvent_PropertyChanged` to act as if they never happened.
+    #[serde(default)]
+    pub event_types: Vec<String>,
+    /// Application keys or names to leave out of the published application.
+    #[serde(default)]
+    pub applications: Vec<String>,
+}
+
+impl IgnoreSettings {
+    pub fn ignores_event(&self, name: &str) -> bool {
+        self.event_types.iter().any(|t| t == name)
+    }
+
+    pub fn ignores_application(&self, key: &str, name: &str) -> bool {
+        self.applications.iter().any(|a| a == key || a == name)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IpcSettings {
+    #[serde(default = "default_ipc_pipe")]
+    pub pipe: String,
+}
+
+fn default_ipc_pipe() -> String {
+    r"\\.\pipe\vr-status".into()
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebSettings {
+    /// Anything that can reach this address can read and change the settings, including
+    /// the MQTT password, so it's only reachable from this PC by default.
+    #[serde(default = "default_web_listen")]
+    pub listen: SocketAddr,
+}
+
+fn default_web_listen() -> SocketAddr {
+    ([127, 0, 0, 1], 8765).into()
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArcadeSettings {
+    /// Also summarize every station under `<prefix>/fleet/`.
+    #[serde(default)]
+    pub aggregate: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PrivacySettings {
+    #[serde(default)]
+    pub mode: PrivacyMode,
+    /// Mixed into hashes so they can't be matched against hashes of known game names.
+    #[serde(default)]
+    pub salt: String,
+    /// Category to publish for each application key or name in `Category` mode.
+    #[serde(default)]
+    pub categories: HashMap<String, String>,
+    #[serde(default = "default_privacy_category")]
+    pub default_category: String,
+}
+
+fn default_privacy_category() -> String {
+    "Game".into()
+}
+
+#[derive(Deserialize)]
+pub enum PrivacyMode {
+    Hash,
+    Category,
+}
+
+impl Default for PrivacyMode {
+    fn default() -> Self {
+        PrivacyMode::Hash
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WatchdogSettings {
+    /// How long vr-status can go without making progress before `--watchdog` restarts it.
+    #[serde(default = "default_watchdog_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Default for WatchdogSettings {
+    fn default() -> Self {
+        Self {
+            timeout_secs: default_watchdog_timeout_secs(),
+        }
+    }
+}
+
+fn default_watchdog_timeout_secs() -> u64 {
+    60
+}
+
+/// How often periodic publishes run while the headset is in standby.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StandbySettings {
+    /// Seconds between publishes keyed by settings section, or 0 to stop publishing until
+    /// the headset is active again. Sections that aren't listed keep their usual interval.
+    #[serde(default)]
+    pub intervals: BTreeMap<String, u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DisconnectSettings {
+    #[serde(default = "default_disconnect_window_secs")]
+    pub window_secs: u64,
+    /// Disconnects within the window that count as a problem.
+    #[serde(default = "default_disconnect_threshold")]
+    pub threshold: usize,
+}
+
+impl Default for DisconnectSettings {
+    fn default() -> Self {
+        Self {
+            window_secs: default_disconnect_window_secs(),
+            threshold: default_disconnect_threshold(),
+        }
+    }
+}
+
+fn default_disconnect_window_secs() -> u64 {
+    600
+}
+
+fn default_disconnect_threshold() -> usize {
+    3
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InGameSettings {
+    /// Application keys or names that don't count as being in a game.
+    #[serde(default = "default_idle_applications")]
+    pub idle_applications: Vec<String>,
+}
+
+impl Default for InGameSettings {
+    fn default() -> Self {
+        Self {
+            idle_applications: default_idle_applications(),
+        }
+    }
+}
+
+fn default_idle_applications() -> Vec<String> {
+    [
+        "openvr.component.vrcompositor",
+        "openvr.tool.steamvr_environments",
+        "openvr.tool.steamvr_tutorial",
+        "openvr.tool.steamvr_room_setup",
+        "SteamVR Home",
+        "SteamVR Tutorial",
+        "Room Setup",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+impl InGameSettings {
+    pub fn is_game(&self, key: &str, name: &str) -> bool {
+        !key.is_empty() && !self.idle_applications.iter().any(|a| a == key || a == name)
+    }
+}
+
+/// Limits `active` to some applications, so it can mean something narrower than the
+/// headset being in use.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ActiveSettings {
+    /// Application keys or names that `active` is limited to, if any are listed.
+    #[serde(default)]
+    pub allow_applications: Vec<String>,
+    /// Application keys or names that keep `active` OFF.
+    #[serde(default)]
+    pub deny_applications: Vec<String>,
+}
+
+impl ActiveSettings {
+    pub fn allows(&self, key: &str, name: &str) -> bool {
+        let listed = |applications: &[String]| applications.iter().any(|a| a == key || a == name);
+        (self.allow_applications.is_empty() || listed(&self.allow_applications))
+            && !listed(&self.deny_applications)
+    }
+}
+
+/// A command to run when particular events happen.
+#[derive(Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct HookSettings {
+    /// Event types as published to `events/occurrence`, such as `game_started`.
+    #[serde(deserialize_with = "one_or_many")]
+    pub on: Vec<String>,
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// SteamVR settings to change while particular applications are running.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApplicationProfile {
+    /// Application keys such as `steam.app.620980`.
+    #[serde(deserialize_with = "one_or_many")]
+    pub key: Vec<String>,
+    pub set: Vec<SettingChange>,
+    /// Put the settings back when the application stops.
+    #[serde(default = "default_true")]
+    pub restore: bool,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SettingChange {
+    pub section: String,
+    pub key: String,
+    pub value: SettingValue,
+}
+
+/// A SteamVR setting value. Whole numbers are set as integers and others as floats.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum SettingValue {
+    Bool(bool),
+    Int(i32),
+    Float(f32),
+}
+
+/// How to publish a numeric sensor instead of its usual unit and precision.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NumberFormat {
+    #[serde(default)]
+    pub decimals: Option<u32>,
+    #[serde(default)]
+    pub unit: Option<NumberUnit>,
+}
+
+/// What to publish as the application when SteamVR can't say which one is running.
+#[derive(Clone, Copy, Deserialize, PartialEq)]
+pub enum LookupFailure {
+    /// Leave the previous application published.
+    KeepLast,
+    /// Publish the process id.
+    Pid,
+    /// Publish `Unknown`.
+    Unknown,
+    /// Try again after 1, 2, 4... seconds, then leave the previous application published.
+    Retry,
+}
+
+impl Default for LookupFailure {
+    fn default() -> Self {
+        LookupFailure::KeepLast
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApplicationLookupSettings {
+    #[serde(default)]
+    pub on_failure: LookupFailure,
+    #[serde(default = "default_application_lookup_retries")]
+    pub retries: u32,
+}
+
+impl Default for ApplicationLookupSettings {
+    fn default() -> Self {
+        Self {
+            on_failure: LookupFailure::default(),
+            retries: default_application_lookup_retries(),
+        }
+    }
+}
+
+fn default_application_lookup_retries() -> u32 {
+    5
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IdleApplicationSettings {
+    #[serde(default)]
+    pub value: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ControllerActivitySettings {
+    /// Seconds without controller input before the controllers count as put down.
+    #[serde(default = "default_controller_idle_secs")]
+    pub idle_secs: u64,
+}
+
+fn default_controller_idle_secs() -> u64 {
+    30
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReadySettings {
+    #[serde(default = "default_ready_interval_secs")]
+    pub interval_secs: u64,
+    /// Controllers with less charge than this fraction need charging first.
+    #[serde(default = "default_ready_battery_below")]
+    pub battery_below: f32,
+    /// Require every base station to be awake. Off for inside-out headsets.
+    #[serde(default = "default_true")]
+    pub base_stations: bool,
+    /// Require both hand controllers to be on.
+    #[serde(default = "default_true")]
+    pub controllers: bool,
+}
+
+fn default_ready_interval_secs() -> u64 {
+    10
+}
+
+fn default_ready_battery_below() -> f32 {
+    0.2
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PanicGestureSettings {
+    /// Held on both controllers at once.
+    #[serde(default = "default_panic_gesture_buttons")]
+    pub buttons: Vec<ControllerButton>,
+    #[serde(default = "default_panic_gesture_hold_secs")]
+    pub hold_secs: f32,
+}
+
+fn default_panic_gesture_buttons() -> Vec<ControllerButton> {
+    vec![ControllerButton::Menu]
+}
+
+fn default_panic_gesture_hold_secs() -> f32 {
+    3.0
+}
+
+#[derive(Clone, Copy, Deserialize)]
+pub enum ControllerButton {
+    Menu,
+    Grip,
+    A,
+    Touchpad,
+    Trigger,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PoseStreamSettings {
+    /// Where pose datagrams are sent, e.g. `127.0.0.1:4243`.
+    pub address: SocketAddr,
+    #[serde(default = "default_pose_stream_rate_hz")]
+    pub rate_hz: u32,
+    #[serde(default)]
+    pub origin: PoseOrigin,
+}
+
+fn default_pose_stream_rate_hz() -> u32 {
+    90
+}
+
+#[derive(Clone, Copy, Deserialize)]
+pub enum PoseOrigin {
+    /// Relative to the seated zero pose, which suits head tracking on a desktop.
+    Seated,
+    /// Relative to the center of the play area, with the floor at 0.
+    Standing,
+}
+
+impl Default for PoseOrigin {
+    fn default() -> Self {
+        PoseOrigin::Seated
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApplicationElapsedSettings {
+    #[serde(default = "default_application_elapsed_interval_secs")]
+    pub interval_secs: u64,
+}
+
+fn default_application_elapsed_interval_secs() -> u64 {
+    60
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StatsSettings {
+    #[serde(default = "default_stats_interval_secs")]
+    pub interval_secs: u64,
+    /// Local time when a new day starts, so a late session counts towards the day it
+    /// started on.
+    #[serde(default = "default_stats_day_start", deserialize_with = "time_of_day")]
+    pub day_start: NaiveTime,
+    #[serde(default = "default_stats_week_start")]
+    pub week_start: Weekday,
+}
+
+fn default_stats_interval_secs() -> u64 {
+    60
+}
+
+fn default_stats_day_start() -> NaiveTime {
+    NaiveTime::from_hms(4, 0, 0)
+}
+
+fn default_stats_week_start() -> Weekday {
+    Weekday::Mon
+}
+
+fn time_of_day<'de, D>(deserializer: D) -> std::result::Result<NaiveTime, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(&value, "%H:%M").map_err(|_| {
+        serde::de::Error::custom(format!("expected a time like \"04:00\", got {:?}", value))
+    })
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsSettings {
+    #[serde(default = "default_metrics_interval_secs")]
+    pub interval_secs: u64,
+}
+
+fn default_metrics_interval_secs() -> u64 {
+    10
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RawEventSettings {
+    /// Event type names such as `VREvent_ButtonPress` to publish. Empty publishes all.
+    #[serde(default)]
+    pub types: Vec<String>,
+}
+
+impl RawEventSettings {
+    pub fn includes(&self, name: &str) -> bool {
+        self.types.is_empty() || self.types.iter().any(|t| t == name)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NotificationSettings {
+    #[serde(with = "serde_yaml::with::singleton_map")]
+    pub service: NotificationService,
+    #[serde(default = "default_notification_interval_secs")]
+    pub interval_secs: u64,
+    #[serde(default = "default_notification_battery_below")]
+    pub battery_below: f32,
+    #[serde(default = "default_notification_tracking_lost_count")]
+    pub tracking_lost_count: usize,
+    #[serde(default = "default_notification_tracking_lost_window_secs")]
+    pub tracking_lost_window_secs: u64,
+}
+
+fn default_notification_interval_secs() -> u64 {
+    5
+}
+
+fn default_notification_battery_below() -> f32 {
+    0.1
+}
+
+fn default_notification_tracking_lost_count() -> usize {
+    3
+}
+
+fn default_notification_tracking_lost_window_secs() -> u64 {
+    5 * 60
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum NotificationService {
+    Ntfy {
+        #[serde(default = "default_ntfy_server")]
+        server: String,
+        topic: String,
+        #[serde(default)]
+        token: Option<String>,
+    },
+    Pushover {
+        token: String,
+        user: String,
+    },
+}
+
+fn default_ntfy_server() -> String {
+    "https://ntfy.sh".into()
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct BinaryPayload {
+    pub on: String,
+    pub off: String,
+}
+
+impl Default for BinaryPayload {
+    fn default() -> Self {
+        BinaryPayload {
+            on: "ON".into(),
+            off: "OFF".into(),
+        }
+    }
+}
+
+impl BinaryPayload {
+    pub fn format(&self, value: bool) -> &str {
+        if value {
+            &self.on
+        } else {
+            &self.off
+        }
+    }
+
+    pub fn parse(&self, payload: &[u8]) -> Option<bool> {
+        if payload == self.on.as_bytes() {
+            Some(true)
+        } else if payload == self.off.as_bytes() {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+/// Payloads for ON/OFF topics, keyed by topic relative to `<prefix>/<id>/`, with
+/// `default` applying to any topic not listed.
+#[derive(Deserialize, Default, Clone)]
+pub struct Payloads(HashMap<String, BinaryPayload>);
+
+impl Payloads {
+    pub fn get(&self, topic: &str) -> BinaryPayload {
+        self.0
+            .get(topic)
+            .or_else(|| self.0.get("default"))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+fn settings_path() -> Result<PathBuf> {
+    data_file("vr-status.yaml")
+}
+
+pub async fn load_settings() -> Result<Settings> {
+    migrate().await.context("Failed to set up data directory")?;
+    let path = settings_path()?;
+    let mut file = File::open(&path)
+        .await
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut settings = String::new();
+    file.read_to_string(&mut settings)
+        .await
+        .context("Failed to read settings")?;
+    let mut settings = parse_settings(&settings, &path).await?;
+    let dir = path.parent().unwrap();
+    if let Some(credentials) = &mut settings.mqtt.credentials {
+        if let Some(password_file) = &credentials.password_file {
+            let password_file = dir.join(password_file);
+            let password = tokio::fs::read_to_string(&password_file)
+                .await
+                .with_context(|| format!("Failed to read {}", password_file.display()))?;
+            credentials.password = password.trim_end_matches(&['\r', '\n'][..]).to_string();
+        }
+    }
+    Ok(settings)
+}
+
+/// Parses and validates the contents of the settings file at `path`.
+async fn parse_settings(text: &str, path: &Path) -> Result<Settings> {
+    let mut value: Value = serde_yaml::from_str(text)
+        .with_context(|| format!("Failed to parse {}", path.display()))?;
+    let dir = path.parent().unwrap();
+    let settings: Settings = if resolve_includes(&mut value, dir, 0).await? {
+        serde_yaml::from_value(value)
+    } else {
+        // Parsing the text again keeps line numbers in the error.
+        serde_yaml::from_str(text)
+    }
+    .with_context(|| format!("Failed to parse {}", path.display()))?;
+    settings
+        .validate()
+        .with_context(|| format!("Invalid settings in {}", path.display()))?;
+    Ok(settings)
+}
+
+/// Reads `vr-status.yaml` as it is on disk, comments and all, for editing.
+pub async fn read_settings_text() -> Result<String> {
+    let path = settings_path()?;
+    tokio::fs::read_to_string(&path)
+        .await
+        .with_context(|| format!("Failed to read {}", path.display()))
+}
+
+/// Replaces `vr-status.yaml` with `text`, unless it isn't valid settings or changes the
+/// hooks. Hooks run commands, so they can only be changed by editing the file. The new
+/// settings take effect the next time they're loaded.
+pub async fn save_settings(text: &str) -> Result<()> {
+    let path = settings_path()?;
+    let settings = parse_settings(text, &path).await?;
+    let current = parse_settings(&read_settings_text().await?, &path).await?;
+    if settings.hooks != current.hooks {
+        bail!(
+            "hooks can only be changed by editing {} directly",
+            path.display()
+        );
+    }
+    tokio::fs::write(&path, text)
+        .await
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Changes `name` in `vr-status.yaml`, editing just that line so comments survive.
+pub async fn save_name(name: &str) -> Result<()> {
+    let path = settings_path()?;
+    let settings = tokio::fs::read_to_string(&path)
+        .await
+        .context("Failed to read settings")?;
+    let settings = replace_name(&settings, name)
+        .with_context(|| format!("Can't change the name in {}", path.display()))?;
+    tokio::fs::write(&path, settings)
+        .await
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Replaces the value on the top-level `name:` line, keeping every line ending as it was.
+fn replace_name(settings: &str, name: &str) -> Result<String> {
+    let mut replaced = String::with_capacity(settings.len());
+    let mut found = false;
+    for line in settings.split_inclusive('\n') {
+        let content = line.trim_end_matches(&['\r', '\n'][..]);
+        let value = match content.strip_prefix("name:") {
+            Some(value) if !found => value.trim(),
+            _ => {
+                replaced.push_str(line);
+                continue;
+            }
+        };
+        if value.starts_with("!include") {
+            bail!("name comes from an !include; change it in that file instead");
+        }
+        if value.is_empty() || value.starts_with(&['|', '>'][..]) {
+            bail!("name isn't on one line; put its value after name: to change it here");
+        }
+        found = true;
+        // A JSON string is a valid double-quoted YAML scalar.
+        replaced.push_str("name: ");
+        replaced.push_str(&serde_json::to_string(name).unwrap());
+        replaced.push_str(&line[content.len()..]);
+    }
+    if !found {
+        bail!("There's no top-level name line to change");
+    }
+    Ok(replaced)
+}
+
+/// Replaces `!include path` values with the YAML in that file, relative to the file it
+/// appears in, and returns whether there were any. Boxed because async functions can't
+/// recurse directly.
+fn resolve_includes<'a>(
+    value: &'a mut Value,
+    dir: &'a Path,
+    depth: usize,
+) -> Pin<Box<dyn Future<Output = Result<bool>> + Send + 'a>> {
+    const MAX_DEPTH: usize = 8;
+    Box::pin(async move {
+        match value {
+            Value::Tagged(tagged) if tagged.tag == "!include" => {
+                let path = match &tagged.value {
+                    Value::String(path) => dir.join(path),
+                    _ => bail!("!include needs a file name, e.g. `!include secrets.yaml`"),
+                };
+                if depth == MAX_DEPTH {
+                    bail!(
+                        "{} is included too deeply; is it including itself?",
+                        path.display()
+                    );
+                }
+                let contents = tokio::fs::read_to_string(&path)
+                    .await
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                let mut included: Value = serde_yaml::from_str(&contents)
+                    .with_context(|| format!("Failed to parse {}", path.display()))?;
+                resolve_includes(&mut included, path.parent().unwrap(), depth + 1).await?;
+                *value = included;
+                Ok(true)
+            }
+            Value::Tagged(tagged) => resolve_includes(&mut tagged.value, dir, depth).await,
+            Value::Sequence(values) => {
+                let mut any = false;
+                for value in values {
+                    any |= resolve_includes(value, dir, depth).await?;
+                }
+                Ok(any)
+            }
+            Value::Mapping(mapping) => {
+                let mut any = false;
+                for (_, value) in mapping.iter_mut() {
+                    any |= resolve_includes(value, dir, depth).await?;
+                }
+                Ok(any)
+            }
+            _ => Ok(false),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINIMAL: &str = "id: test\nname: Test\nmqtt:\n  host: localhost\n";
+
+    async fn parse(extra: &str) -> Result<Settings> {
+        parse_settings(
+            &format!("{}{}", MINIMAL, extra),
+            Path::new("vr-status.yaml"),
+        )
+        .await
+    }
+
+    async fn error(extra: &str) -> String {
+        match parse(extra).await {
+            Ok(_) => panic!("settings with {:?} were accepted", extra),
+            Err(error) => format!("{:?}", error),
+        }
+    }
+
+    #[tokio::test]
+    async fn minimal_settings_are_valid() {
+        parse("").await.unwrap();
+    }
+
+    /// Writes `files` to a directory of their own and parses `text` as if it were the
+    /// settings file there.
+    async fn parse_with_files(name: &str, text: &str, files: &[(&str, &str)]) -> Result<Settings> {
+        let dir = std::env::temp_dir().join(format!("vr-status-{}-{}", name, std::process::id()));
+        for (file, contents) in files {
+            let file = dir.join(file);
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            std::fs::write(file, contents).unwrap();
+        }
+        let settings = parse_settings(text, &dir.join("vr-status.yaml")).await;
+        std::fs::remove_dir_all(&dir).unwrap();
+        settings
+    }
+
+    #[tokio::test]
+    async fn includes_are_relative_to_the_including_file() {
+        let settings = parse_with_files(
+            "includes",
+            "id: test\nname: Test\nmqtt: !include mqtt/broker.yaml\n",
+            &[
+                ("mqtt/broker.yaml", "host: !include host.yaml\n"),
+                ("mqtt/host.yaml", "broker.local\n"),
+            ],
+        )
+        .await
+        .unwrap();
+        assert_eq!(settings.mqtt.host, vec!["broker.local".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn include_errors() {
+        let looped = format!(
+            "{:?}",
+            parse_with_files(
+                "include-loop",
+                "id: test\nname: !include loop.yaml\nmqtt:\n  host: localhost\n",
+                &[("loop.yaml", "!include loop.yaml\n")],
+            )
+            .await
+            .unwrap_err()
+        );
+        assert!(looped.contains("included too deeply"), "{}", looped);
+        assert!(error("derived: !include [a.yaml]\n")
+            .await
+            .contains("!include needs a file name"));
+        assert!(error("derived: !include missing.yaml\n")
+            .await
+            .contains("Failed to read missing.yaml"));
+    }
+
+    #[tokio::test]
+    async fn derived_expressions_are_checked() {
+        assert!(
+            parse("derived:\n  playing: \"power && application != ''\"\n")
+                .await
+                .is_ok()
+        );
+        assert!(error("derived:\n  playing: \"powr\"\n")
+            .await
+            .contains("Unknown variable powr"));
+        assert!(error("derived:\n  playing: \"power &&\"\n")
+            .await
+            .contains("derived.playing isn't a valid expression"));
+    }
+
+    #[cfg(feature = "updater")]
+    #[tokio::test]
+    async fn updater_interval_of_zero_is_rejected() {
+        assert!(parse("updater:\n  interval_secs: 3600\n").await.is_ok());
+        assert!(error("updater:\n  interval_secs: 0\n")
+            .await
+            .contains("updater.interval_secs"));
+    }
+
+    #[tokio::test]
+    async fn publish_intervals_of_zero_are_rejected() {
+        for name in &["application_elapsed", "stats", "metrics"] {
+            assert!(parse(&format!("{}:\n  interval_secs: 30\n", name))
+                .await
+                .is_ok());
+            assert!(error(&format!("{}:\n  interval_secs: 0\n", name))
+                .await
+                .contains(&format!("{}.interval_secs", name)));
+        }
+    }
+
+    #[test]
+    fn replacing_the_name_keeps_line_endings() {
+        assert_eq!(
+            replace_name("id: test\r\nname: Old # comment\r\nmqtt:\r\n", "New").unwrap(),
+            "id: test\r\nname: \"New\"\r\nmqtt:\r\n"
+        );
+        assert_eq!(
+            replace_name("id: test\nname: Old\n", "New \"PC\"").unwrap(),
+            "id: test\nname: \"New \\\"PC\\\"\"\n"
+        );
+        assert_eq!(
+            replace_name("id: test\nname: Old", "New").unwrap(),
+            "id: test\nname: \"New\""
+        );
+    }
+
+    #[test]
+    fn replacing_the_name_only_changes_the_top_level_key() {
+        assert_eq!(
+            replace_name("id: test\nname: Old\nnames:\n  name: Other\n", "New").unwrap(),
+            "id: test\nname: \"New\"\nnames:\n  name: Other\n"
+        );
+        assert!(replace_name("id: test\nmqtt:\n  name: Other\n", "New").is_err());
+        assert!(replace_name("<<: !include common.yaml\n", "New").is_err());
+        assert!(replace_name("name: !include name.yaml\n", "New").is_err());
+        assert!(replace_name("name: |\n  Old\n", "New").is_err());
+        assert!(replace_name("name:\n  Old\n", "New").is_err());
+    }
+
+    #[tokio::test]
+    async fn rate_limits_of_zero_are_rejected() {
+        assert!(error("  rate_limit:\n    global: 0\n")
+            .await
+            .contains("mqtt.rate_limit.global"));
+        assert!(error("  rate_limit:\n    topics:\n      application: 0\n")
+            .await
+            .contains("mqtt.rate_limit.topics.application"));
+        parse("  rate_limit:\n    global: 1\n    topics:\n      application: 1\n")
+            .await
+            .unwrap();
+    }
+}
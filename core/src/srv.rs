@@ -0,0 +1,96 @@
+use anyhow::{Context, Result};
+use log::info;
+use trust_dns_resolver::TokioAsyncResolver;
+
+use crate::settings::MqttTransport;
+
+/// Sorts `(priority, weight, target, port)` records into the order to try them and drops
+/// the trailing dots from their targets.
+fn order(mut records: Vec<(u16, u16, String, u16)>) -> Vec<(String, u16)> {
+    // Lower priorities come first. Within a priority the heaviest is tried first rather
+    // than picking at random, so the order stays the same between reconnects.
+    records.sort_by_key(|(priority, weight, _, _)| (*priority, std::cmp::Reverse(*weight)));
+    records
+        .into_iter()
+        .map(|(_, _, target, port)| (target.trim_end_matches('.').to_string(), port))
+        // A target of "." means the service isn't offered at this domain.
+        .filter(|(host, _)| !host.is_empty())
+        .collect()
+}
+
+/// Looks up the MQTT brokers `domain` advertises with SRV records and returns their
+/// hosts and ports in the order to try them.
+pub async fn lookup_brokers(domain: &str, transport: &MqttTransport) -> Result<Vec<(String, u16)>> {
+    let service = match transport {
+        MqttTransport::Tcp => "_mqtt._tcp",
+        MqttTransport::Tls => "_secure-mqtt._tcp",
+    };
+    let name = format!("{}.{}.", service, domain.trim_end_matches('.'));
+    let resolver =
+        TokioAsyncResolver::tokio_from_system_conf().context("Failed to read DNS settings")?;
+    let lookup = resolver
+        .srv_lookup(name.as_str())
+        .await
+        .with_context(|| format!("Failed to look up {}", name))?;
+    let brokers = order(
+        lookup
+            .iter()
+            .map(|record| {
+                (
+                    record.priority(),
+                    record.weight(),
+                    record.target().to_utf8(),
+                    record.port(),
+                )
+            })
+            .collect(),
+    );
+    for (host, port) in &brokers {
+        info!("Found MQTT broker {}:{} in DNS", host, port);
+    }
+    Ok(brokers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(priority: u16, weight: u16, target: &str, port: u16) -> (u16, u16, String, u16) {
+        (priority, weight, target.to_string(), port)
+    }
+
+    #[test]
+    fn lower_priorities_then_heavier_weights_come_first() {
+        assert_eq!(
+            order(vec![
+                record(20, 100, "backup.example.com.", 1883),
+                record(10, 10, "light.example.com.", 1883),
+                record(10, 90, "heavy.example.com.", 8883),
+            ]),
+            vec![
+                ("heavy.example.com".to_string(), 8883),
+                ("light.example.com".to_string(), 1883),
+                ("backup.example.com".to_string(), 1883),
+            ]
+        );
+    }
+
+    #[test]
+    fn equal_records_keep_their_order() {
+        assert_eq!(
+            order(vec![
+                record(10, 50, "a.example.com.", 1883),
+                record(10, 50, "b.example.com.", 1883),
+            ]),
+            vec![
+                ("a.example.com".to_string(), 1883),
+                ("b.example.com".to_string(), 1883),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_target_of_dot_is_dropped() {
+        assert!(order(vec![record(0, 0, ".", 0)]).is_empty());
+    }
+}
@@ -0,0 +1,103 @@
+use anyhow::{bail, Result};
+
+/// The status every sink reports. It's sent as a single snapshot so sinks never see a
+/// combination of values that didn't exist at the same time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VrState {
+    pub power: bool,
+    pub active: bool,
+    pub application: String,
+}
+
+impl Default for VrState {
+    fn default() -> Self {
+        // vr-status is only running while SteamVR is.
+        VrState {
+            power: true,
+            active: true,
+            application: String::new(),
+        }
+    }
+}
+
+impl VrState {
+    /// Checks that the combination of values can actually happen.
+    pub fn validate(&self) -> Result<()> {
+        if self.active && !self.power {
+            bail!("Can't be active while power is off");
+        }
+        Ok(())
+    }
+
+    /// Returns a copy with `update` applied, or an error if the result can't happen.
+    pub fn updated(&self, update: impl FnOnce(&mut VrState)) -> Result<VrState> {
+        let mut vr_state = self.clone();
+        update(&mut vr_state);
+        vr_state.validate()?;
+        Ok(vr_state)
+    }
+
+    pub fn set_power(&mut self, power: bool) {
+        self.power = power;
+        // Nothing is active without power.
+        self.active &= power;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vr_state(power: bool, active: bool) -> VrState {
+        VrState {
+            power,
+            active,
+            application: "Beat Saber".to_string(),
+        }
+    }
+
+    #[test]
+    fn active_without_power_is_rejected() {
+        assert!(vr_state(false, true).validate().is_err());
+        assert!(vr_state(true, true)
+            .updated(|vr_state| vr_state.power = false)
+            .is_err());
+        assert!(vr_state(false, false)
+            .updated(|vr_state| vr_state.active = true)
+            .is_err());
+    }
+
+    #[test]
+    fn turning_power_off_clears_active() {
+        let vr_state = vr_state(true, true)
+            .updated(|vr_state| vr_state.set_power(false))
+            .unwrap();
+        assert!(!vr_state.power);
+        assert!(!vr_state.active);
+        assert_eq!(vr_state.application, "Beat Saber");
+    }
+
+    #[test]
+    fn turning_power_on_leaves_active_alone() {
+        let powered = vr_state(false, false)
+            .updated(|vr_state| vr_state.set_power(true))
+            .unwrap();
+        assert_eq!(powered, vr_state(true, false));
+    }
+
+    #[test]
+    fn valid_states_pass_through_unchanged() {
+        for valid in &[
+            vr_state(true, true),
+            vr_state(true, false),
+            vr_state(false, false),
+        ] {
+            valid.validate().unwrap();
+            assert_eq!(&valid.updated(|_| {}).unwrap(), valid);
+        }
+        assert_eq!(
+            VrState::default().updated(|_| {}).unwrap(),
+            VrState::default()
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use std::{io::ErrorKind, path::PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{
+    DateTime, Datelike, Duration, Local, LocalResult, NaiveDateTime, NaiveTime, TimeZone, Utc,
+    Weekday,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{paths::data_file, settings::StatsSettings};
+
+#[derive(Default, Deserialize, Serialize)]
+struct StatsFile {
+    day_start: Option<DateTime<Utc>>,
+    week_start: Option<DateTime<Utc>>,
+    today_secs: i64,
+    week_secs: i64,
+}
+
+/// Totals VR time for the current day and week, persisted in the data directory so a
+/// restart partway through doesn't lose the day.
+pub struct UsageStats {
+    path: PathBuf,
+    file: StatsFile,
+    day_start: NaiveTime,
+    week_start: Weekday,
+    /// When time was last counted, if the headset has been active since then.
+    counted_until: Option<DateTime<Utc>>,
+}
+
+impl UsageStats {
+    pub async fn load(settings: &StatsSettings) -> Result<Self> {
+        let path = data_file("vr-status.stats.json")?;
+        let file = match tokio::fs::read(&path).await {
+            Ok(contents) => serde_json::from_slice(&contents).context("Failed to parse stats")?,
+            Err(error) if error.kind() == ErrorKind::NotFound => StatsFile::default(),
+            Err(error) => return Err(error).context("Failed to read stats"),
+        };
+        Ok(Self {
+            path,
+            file,
+            day_start: settings.day_start,
+            week_start: settings.week_start,
+            counted_until: None,
+        })
+    }
+
+    /// Minutes of VR time today.
+    pub fn today_mins(&self) -> i64 {
+        self.file.today_secs / 60
+    }
+
+    /// Minutes of VR time this week.
+    pub fn week_mins(&self) -> i64 {
+        self.file.week_secs / 60
+    }
+
+    /// Counts the time since the last update if the headset was active, starting a new
+    /// day or week if a boundary has passed, and then starts counting again if `active`.
+    pub async fn update(&mut self, active: bool) -> Result<()> {
+        let now = Utc::now();
+        let (day, week) = self.period_starts(now.with_timezone(&Local));
+        if self.file.day_start != Some(day) {
+            self.file.day_start = Some(day);
+            self.file.today_secs = 0;
+        }
+        if self.file.week_start != Some(week) {
+            self.file.week_start = Some(week);
+            self.file.week_secs = 0;
+        }
+        if let Some(counted_until) = self.counted_until {
+            // Only the part after the boundary belongs to the new day or week.
+            self.file.today_secs += (now - counted_until.max(day)).num_seconds().max(0);
+            self.file.week_secs += (now - counted_until.max(week)).num_seconds().max(0);
+        }
+        self.counted_until = if active { Some(now) } else { None };
+        tokio::fs::write(&self.path, serde_json::to_vec(&self.file).unwrap())
+            .await
+            .context("Failed to write stats")
+    }
+
+    /// The starts of the day and week containing `now`, in local time.
+    fn period_starts(&self, now: DateTime<Local>) -> (DateTime<Utc>, DateTime<Utc>) {
+        let mut date = now.naive_local().date();
+        if now.time() < self.day_start {
+            date = date.pred();
+        }
+        let days_into_week = (date.weekday().num_days_from_monday() + 7
+            - self.week_start.num_days_from_monday())
+            % 7;
+        let week_date = date - Duration::days(days_into_week.into());
+        (
+            local_to_utc(date.and_time(self.day_start)),
+            local_to_utc(week_date.and_time(self.day_start)),
+        )
+    }
+}
+
+/// Converts a local boundary to UTC. A boundary in the hour skipped when daylight saving
+/// starts moves to the end of that hour.
+fn local_to_utc(local: NaiveDateTime) -> DateTime<Utc> {
+    match Local.from_local_datetime(&local) {
+        LocalResult::Single(time) | LocalResult::Ambiguous(time, _) => time.with_timezone(&Utc),
+        LocalResult::None => Local
+            .from_local_datetime(&(local + Duration::hours(1)))
+            .earliest()
+            .map_or_else(
+                || Utc.from_utc_datetime(&local),
+                |time| time.with_timezone(&Utc),
+            ),
+    }
+}
@@ -0,0 +1,68 @@
+use std::time::{Duration, Instant};
+
+use crate::settings::StandbySettings;
+
+/// Settings sections whose periodic publishes can be slowed down during standby.
+pub const PERIODIC: &[&str] = &[
+    "application_elapsed",
+    "base_stations",
+    "devices",
+    "frame_timing",
+    "metrics",
+    "overlays",
+    "process_usage",
+    "stats",
+    "wireless",
+];
+
+/// Decides when periodic publishes run, which may be less often or not at all while the
+/// headset is in standby.
+pub struct Schedule<'a> {
+    standby: &'a StandbySettings,
+    active: bool,
+}
+
+impl<'a> Schedule<'a> {
+    pub fn new(standby: &'a StandbySettings) -> Self {
+        Self {
+            standby,
+            active: true,
+        }
+    }
+
+    /// Returns true when the headset has just left standby and publishes that were slowed
+    /// down should run right away.
+    pub fn set_active(&mut self, active: bool) -> bool {
+        let woke = active && !self.active;
+        self.active = active;
+        woke && !self.standby.intervals.is_empty()
+    }
+
+    fn standby_interval(&self, name: &str) -> Option<u64> {
+        if self.active {
+            None
+        } else {
+            self.standby.intervals.get(name).copied()
+        }
+    }
+
+    /// Whether the publish for the settings section `name` is stopped until the headset
+    /// is active again.
+    pub fn is_suspended(&self, name: &str) -> bool {
+        self.standby_interval(name) == Some(0)
+    }
+
+    /// Whether the publish for `name` that was scheduled for `next` should run now.
+    pub fn due(&self, name: &str, next: Instant) -> bool {
+        !self.is_suspended(name) && Instant::now() >= next
+    }
+
+    /// When the publish for `name` should run after running now, given the section's
+    /// usual `interval_secs`.
+    pub fn next(&self, name: &str, interval_secs: u64) -> Instant {
+        let interval_secs = self
+            .standby_interval(name)
+            .map_or(interval_secs, |standby| standby.max(interval_secs));
+        Instant::now() + Duration::from_secs(interval_secs)
+    }
+}
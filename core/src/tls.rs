@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use log::warn;
+use rumqttc::ClientConfig;
+use rustls::{
+    Certificate, RootCertStore, ServerCertVerified, ServerCertVerifier, TLSError, WebPKIVerifier,
+};
+use webpki::{DNSName, DNSNameRef};
+
+use crate::settings::TlsSettings;
+
+/// Verifies the broker's certificate against a configured name instead of the host that
+/// was connected to.
+struct ServerNameVerifier {
+    name: DNSName,
+    inner: WebPKIVerifier,
+}
+
+impl ServerCertVerifier for ServerNameVerifier {
+    fn verify_server_cert(
+        &self,
+        roots: &RootCertStore,
+        presented_certs: &[Certificate],
+        _dns_name: DNSNameRef,
+        ocsp_response: &[u8],
+    ) -> Result<ServerCertVerified, TLSError> {
+        self.inner
+            .verify_server_cert(roots, presented_certs, self.name.as_ref(), ocsp_response)
+    }
+}
+
+struct AcceptAnyCertificate;
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _roots: &RootCertStore,
+        _presented_certs: &[Certificate],
+        _dns_name: DNSNameRef,
+        _ocsp_response: &[u8],
+    ) -> Result<ServerCertVerified, TLSError> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+pub fn client_config(settings: &TlsSettings) -> Result<ClientConfig> {
+    let mut config = ClientConfig::new();
+    config.root_store = match rustls_native_certs::load_native_certs() {
+        Ok(store) => store,
+        Err((Some(partial), _)) => partial,
+        Err((None, error)) => return Err(anyhow!("Failed to load certificate store {:?}", error)),
+    };
+    config.alpn_protocols = settings
+        .alpn
+        .iter()
+        .map(|protocol| protocol.as_bytes().to_vec())
+        .collect();
+    if settings.danger_accept_invalid_certs {
+        warn!("MQTT broker certificates will not be verified");
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(AcceptAnyCertificate));
+    } else if let Some(sni) = &settings.sni {
+        let name = DNSNameRef::try_from_ascii_str(sni)
+            .map_err(|_| anyhow!("Invalid TLS server name {}", sni))?
+            .to_owned();
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(ServerNameVerifier {
+                name,
+                inner: WebPKIVerifier::new(),
+            }));
+    }
+    Ok(config)
+}
@@ -0,0 +1,133 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::ErrorKind,
+    path::PathBuf,
+};
+
+use anyhow::{bail, Context, Result};
+use log::{error, info};
+use rumqttc::{AsyncClient, Event, MqttOptions, Outgoing, QoS};
+
+use crate::{mqtt::broker_options, paths::data_file, settings::Settings};
+
+/// Remembers every retained topic published for each id, persisted in the data directory,
+/// so they can all be cleared when a PC stops using vr-status.
+pub struct TopicRegistry {
+    path: PathBuf,
+    id: String,
+    topics: BTreeMap<String, BTreeSet<String>>,
+    changed: bool,
+}
+
+impl TopicRegistry {
+    pub async fn load(id: &str) -> Result<Self> {
+        let path = data_file("vr-status.topics.json")?;
+        let topics = match tokio::fs::read(&path).await {
+            Ok(contents) => {
+                serde_json::from_slice(&contents).context("Failed to parse topic registry")?
+            }
+            Err(error) if error.kind() == ErrorKind::NotFound => BTreeMap::new(),
+            Err(error) => return Err(error).context("Failed to read topic registry"),
+        };
+        Ok(Self {
+            path,
+            id: id.to_string(),
+            topics,
+            changed: false,
+        })
+    }
+
+    /// Records that a retained message was published to `topic`.
+    pub fn record(&mut self, topic: &str) {
+        let topics = self.topics.entry(self.id.clone()).or_default();
+        if !topics.contains(topic) {
+            topics.insert(topic.to_string());
+            self.changed = true;
+        }
+    }
+
+    /// Writes the registry if any topics were recorded since it was last written.
+    pub async fn save(&mut self) -> Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        tokio::fs::write(&self.path, serde_json::to_vec(&self.topics).unwrap())
+            .await
+            .context("Failed to write topic registry")?;
+        self.changed = false;
+        Ok(())
+    }
+}
+
+/// Clears every retained topic published for the configured id by publishing an empty
+/// retained message to it on every configured broker, which removes Home Assistant's
+/// discovered entities too, and returns how many there were. The topics are only
+/// forgotten once every broker has been cleared, so running it again retries the rest.
+pub async fn cleanup(settings: &Settings) -> Result<usize> {
+    let mut registry = TopicRegistry::load(&settings.id).await?;
+    let topics = registry.topics.remove(&settings.id).unwrap_or_default();
+    if topics.is_empty() {
+        return Ok(0);
+    }
+    // A separate client id keeps a running vr-status from being disconnected, and no last
+    // will means nothing is retained again afterwards.
+    let client_id = format!("{}-cleanup", settings.id);
+    let mut cleared = BTreeSet::new();
+    let mut failed = Vec::new();
+    for ((host, port), options) in broker_options(settings, &client_id).await? {
+        let broker = format!("{}:{}", host, port);
+        // Discovery can find a broker that's also configured by name.
+        if cleared.contains(&broker) {
+            continue;
+        }
+        match clear(options, &topics).await {
+            Ok(()) => {
+                info!("Cleared {} topics on {}", topics.len(), broker);
+                cleared.insert(broker);
+            }
+            Err(error) => {
+                error!("Failed to clear topics on {}: {:?}", broker, error);
+                failed.push(broker);
+            }
+        }
+    }
+    if !failed.is_empty() {
+        bail!(
+            "Failed to clear topics on {}; run --cleanup again to retry",
+            failed.join(", ")
+        );
+    }
+    if cleared.is_empty() {
+        bail!("No MQTT broker found to clear topics on");
+    }
+    registry.changed = true;
+    registry.save().await?;
+    Ok(topics.len())
+}
+
+/// Publishes an empty retained message to each of `topics` on one broker.
+async fn clear(options: MqttOptions, topics: &BTreeSet<String>) -> Result<()> {
+    let (client, mut event_loop) = AsyncClient::new(options, 10);
+    let event_loop = tokio::spawn(async move {
+        let mut stop = false;
+        loop {
+            match event_loop.poll().await {
+                Ok(Event::Outgoing(Outgoing::Disconnect)) => stop = true,
+                Ok(_) => {}
+                // The broker closes the connection after the disconnect.
+                Err(_) if stop => return Ok(()),
+                Err(error) => return Err(error).context("Failed to connect to MQTT broker"),
+            }
+        }
+    });
+    for topic in topics {
+        info!("Clearing {}", topic);
+        client
+            .publish(topic, QoS::AtLeastOnce, true, Vec::new())
+            .await
+            .with_context(|| format!("Failed to clear {}", topic))?;
+    }
+    // Publishes are sent in order so this waits for everything above to go out.
+    client.disconnect().await?;
+    event_loop.await?
+}
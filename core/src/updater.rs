@@ -0,0 +1,254 @@
+#[cfg(feature = "updater")]
+use std::time::Duration;
+use std::{io::ErrorKind, path::PathBuf};
+
+#[cfg(feature = "updater")]
+use anyhow::bail;
+use anyhow::{Context, Result};
+#[cfg(feature = "updater")]
+use log::{error, info};
+#[cfg(feature = "updater")]
+use serde::Deserialize;
+#[cfg(feature = "updater")]
+use sha2::{Digest, Sha256};
+
+#[cfg(feature = "updater")]
+use crate::settings::UpdaterSettings;
+
+pub const INSTALLED_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The release asset that replaces the running executable.
+#[cfg(feature = "updater")]
+const EXECUTABLE_ASSET: &str = "vr-status.exe";
+/// The release asset holding the SHA-256 of [`EXECUTABLE_ASSET`] in `sha256sum` format.
+#[cfg(feature = "updater")]
+const CHECKSUM_ASSET: &str = "vr-status.exe.sha256";
+
+#[derive(Clone)]
+pub struct Release {
+    pub version: String,
+    pub url: String,
+    pub summary: String,
+}
+
+#[cfg(feature = "updater")]
+#[derive(Deserialize)]
+struct GitHubRelease {
+    tag_name: String,
+    html_url: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    assets: Vec<GitHubAsset>,
+}
+
+#[cfg(feature = "updater")]
+#[derive(Deserialize)]
+struct GitHubAsset {
+    name: String,
+    browser_download_url: String,
+}
+
+#[cfg(feature = "updater")]
+fn parse_version(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches('v')
+        .split(|c: char| !c.is_ascii_digit())
+        .map_while(|part| part.parse().ok())
+        .collect()
+}
+
+#[cfg(feature = "updater")]
+fn is_newer(version: &str) -> bool {
+    parse_version(version) > parse_version(INSTALLED_VERSION)
+}
+
+fn exe_path_with_extension(extension: &str) -> Result<PathBuf> {
+    let mut path = ::std::env::current_exe().context("Could not find installation directory")?;
+    path.set_extension(extension);
+    Ok(path)
+}
+
+/// Deletes the executable left behind by a previously staged update.
+pub async fn remove_previous_version() -> Result<()> {
+    match tokio::fs::remove_file(exe_path_with_extension("exe.old")?).await {
+        Err(error) if error.kind() != ErrorKind::NotFound => {
+            Err(error).context("Failed to remove previous version")
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(feature = "updater")]
+async fn fetch_latest(
+    client: &reqwest::Client,
+    settings: &UpdaterSettings,
+) -> Result<GitHubRelease> {
+    client
+        .get(format!(
+            "https://api.github.com/repos/{}/releases/latest",
+            settings.repository
+        ))
+        .header("Accept", "application/vnd.github.v3+json")
+        .send()
+        .await
+        .context("Failed to request latest release")?
+        .error_for_status()
+        .context("Failed to request latest release")?
+        .json()
+        .await
+        .context("Failed to parse latest release")
+}
+
+#[cfg(feature = "updater")]
+async fn download(
+    client: &reqwest::Client,
+    release: &GitHubRelease,
+    name: &str,
+) -> Result<Vec<u8>> {
+    let asset = release
+        .assets
+        .iter()
+        .find(|asset| asset.name == name)
+        .with_context(|| format!("Release has no {}", name))?;
+    let contents = client
+        .get(&asset.browser_download_url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("Failed to download {}", name))?
+        .bytes()
+        .await
+        .with_context(|| format!("Failed to download {}", name))?;
+    Ok(contents.to_vec())
+}
+
+/// Reads the lowercase hex digest from the first word of a `sha256sum` line.
+#[cfg(feature = "updater")]
+fn parse_checksum(text: &str) -> Result<String> {
+    let checksum = text.split_whitespace().next().unwrap_or_default();
+    if checksum.len() != 64 || !checksum.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        bail!("{} doesn't contain a SHA-256", CHECKSUM_ASSET);
+    }
+    Ok(checksum.to_ascii_lowercase())
+}
+
+#[cfg(feature = "updater")]
+fn sha256(contents: &[u8]) -> String {
+    Sha256::digest(contents)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Downloads the release executable, checks it against the published SHA-256 and swaps it
+/// in for the running one.
+///
+/// Windows won't let a running executable be overwritten but it can be renamed, so the
+/// current executable is moved aside and deleted on the next start.
+#[cfg(feature = "updater")]
+async fn stage(client: &reqwest::Client, release: &GitHubRelease) -> Result<()> {
+    let checksum = download(client, release, CHECKSUM_ASSET).await?;
+    let expected = parse_checksum(&String::from_utf8_lossy(&checksum))?;
+    let contents = download(client, release, EXECUTABLE_ASSET).await?;
+    let actual = sha256(&contents);
+    if actual != expected {
+        bail!(
+            "{} has SHA-256 {} but {} expected {}",
+            EXECUTABLE_ASSET,
+            actual,
+            release.tag_name,
+            expected
+        );
+    }
+
+    let current = ::std::env::current_exe().context("Could not find installation directory")?;
+    let new = exe_path_with_extension("exe.new")?;
+    let old = exe_path_with_extension("exe.old")?;
+    tokio::fs::write(&new, contents)
+        .await
+        .context("Failed to write new version")?;
+    tokio::fs::rename(&current, &old)
+        .await
+        .context("Failed to move current version")?;
+    if let Err(error) = tokio::fs::rename(&new, &current).await {
+        let _ = tokio::fs::rename(&old, &current).await;
+        return Err(error).context("Failed to move new version into place");
+    }
+    Ok(())
+}
+
+#[cfg(feature = "updater")]
+pub async fn update_loop(
+    settings: &UpdaterSettings,
+    sender: tokio::sync::watch::Sender<Option<Release>>,
+) -> Result<()> {
+    let client = reqwest::Client::builder()
+        .user_agent(concat!("vr-status/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .context("Failed to create HTTP client")?;
+    let mut staged = false;
+
+    loop {
+        match fetch_latest(&client, settings).await {
+            Ok(release) => {
+                if settings.stage && !staged && is_newer(&release.tag_name) {
+                    match stage(&client, &release).await {
+                        Ok(()) => {
+                            info!(
+                                "Version {} will be used when SteamVR next starts vr-status",
+                                release.tag_name
+                            );
+                            staged = true;
+                        }
+                        Err(error) => error!("Failed to stage update: {:?}", error),
+                    }
+                }
+
+                let release = Release {
+                    version: release.tag_name.trim_start_matches('v').to_string(),
+                    summary: release.name.unwrap_or_default(),
+                    url: release.html_url,
+                };
+                if sender.send(Some(release)).is_err() {
+                    break;
+                }
+            }
+            Err(error) => error!("Failed to check for updates: {:?}", error),
+        }
+
+        tokio::time::sleep(Duration::from_secs(settings.interval_secs)).await;
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, feature = "updater"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_compare_numerically() {
+        assert_eq!(parse_version("v1.10.2"), vec![1, 10, 2]);
+        assert_eq!(parse_version("2.0.0-beta.1"), vec![2, 0, 0]);
+        assert!(parse_version("v1.10.0") > parse_version("1.9.9"));
+        assert!(!is_newer(INSTALLED_VERSION));
+    }
+
+    #[test]
+    fn checksums() {
+        let digest = sha256(b"abc");
+        assert_eq!(
+            digest,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            parse_checksum(&format!("{}  vr-status.exe\n", digest.to_uppercase())).unwrap(),
+            digest
+        );
+        assert_eq!(parse_checksum(&digest).unwrap(), digest);
+        assert!(parse_checksum("").is_err());
+        assert!(parse_checksum(&digest[1..]).is_err());
+        assert!(parse_checksum(&digest.replace('b', "g")).is_err());
+    }
+}
@@ -0,0 +1,9 @@
+use serde::Serialize;
+
+/// Who is using the PC, so automations on a shared PC can apply per-person rules.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct UserInfo {
+    pub windows_user: String,
+    /// `None` when Steam isn't running or nobody is logged in to it.
+    pub steam_account: Option<String>,
+}
@@ -0,0 +1,53 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Tracks whether each long-running loop is still making progress, so a supervisor can
+/// restart vr-status when one hangs instead of it looking like the headset is off forever.
+#[derive(Default)]
+pub struct Progress {
+    loops: Mutex<HashMap<&'static str, bool>>,
+}
+
+impl Progress {
+    /// Starts expecting `beat(name)` until the returned guard is dropped.
+    pub fn start(self: &Arc<Self>, name: &'static str) -> ProgressGuard {
+        self.loops.lock().unwrap().insert(name, true);
+        ProgressGuard {
+            progress: self.clone(),
+            name,
+        }
+    }
+
+    pub fn beat(&self, name: &'static str) {
+        if let Some(beat) = self.loops.lock().unwrap().get_mut(name) {
+            *beat = true;
+        }
+    }
+
+    /// Returns the loops that haven't beaten since the last check, and starts the next one.
+    pub fn stalled(&self) -> Vec<&'static str> {
+        let mut loops = self.loops.lock().unwrap();
+        let stalled = loops
+            .iter()
+            .filter(|(_, beat)| !**beat)
+            .map(|(name, _)| *name)
+            .collect();
+        for beat in loops.values_mut() {
+            *beat = false;
+        }
+        stalled
+    }
+}
+
+pub struct ProgressGuard {
+    progress: Arc<Progress>,
+    name: &'static str,
+}
+
+impl Drop for ProgressGuard {
+    fn drop(&mut self) {
+        self.progress.loops.lock().unwrap().remove(self.name);
+    }
+}
@@ -0,0 +1,276 @@
+use std::net::IpAddr;
+
+use anyhow::{Context, Result};
+use log::{debug, error, info};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::mpsc::UnboundedSender,
+};
+
+use crate::{
+    mqtt::State,
+    settings::{read_settings_text, save_settings, WebSettings},
+};
+
+const PAGE: &str = include_str!("web.html");
+
+/// Requests are small, so anything bigger than this is refused rather than buffered.
+const MAX_HEAD: usize = 16 * 1024;
+const MAX_BODY: usize = 1024 * 1024;
+
+struct Request {
+    method: String,
+    path: String,
+    host: Option<String>,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn new(status: &'static str, content_type: &'static str, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type,
+            body: body.into(),
+        }
+    }
+
+    fn text(status: &'static str, body: impl Into<String>) -> Self {
+        Self::new(status, "text/plain; charset=utf-8", body)
+    }
+}
+
+/// Reads one HTTP/1.1 request, returning `None` if the client closed the connection or
+/// sent something that isn't one.
+async fn read_request(stream: &mut TcpStream) -> Result<Option<Request>> {
+    let mut buffer = Vec::new();
+    let head_end = loop {
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end;
+        }
+        if buffer.len() > MAX_HEAD {
+            return Ok(None);
+        }
+        let mut chunk = [0; 4096];
+        let read = stream
+            .read(&mut chunk)
+            .await
+            .context("Failed to read request")?;
+        if read == 0 {
+            return Ok(None);
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+    let head = match std::str::from_utf8(&buffer[..head_end]) {
+        Ok(head) => head,
+        Err(_) => return Ok(None),
+    };
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (method, path) = match (request_line.next(), request_line.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Ok(None),
+    };
+    let mut host = None;
+    let mut content_length = 0;
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("host") {
+                host = Some(value.to_string());
+            } else if name.eq_ignore_ascii_case("content-length") {
+                content_length = match value.parse() {
+                    Ok(length) if length <= MAX_BODY => length,
+                    _ => return Ok(None),
+                };
+            }
+        }
+    }
+    let mut body = buffer.split_off(head_end + 4);
+    if body.len() < content_length {
+        let start = body.len();
+        body.resize(content_length, 0);
+        stream
+            .read_exact(&mut body[start..])
+            .await
+            .context("Failed to read request body")?;
+    }
+    body.truncate(content_length);
+    Ok(Some(Request {
+        method,
+        path,
+        host,
+        body,
+    }))
+}
+
+/// Whether the Host header names this PC by address or as localhost. A web page can
+/// point its own domain name at this address, and refusing names other than localhost
+/// keeps it from reading the settings that way.
+fn host_allowed(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        // An IPv6 address with a port is bracketed.
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => host.rsplit_once(':').map_or(host, |(name, _)| name),
+    };
+    name.eq_ignore_ascii_case("localhost") || name.parse::<IpAddr>().is_ok()
+}
+
+/// Handles one request, returning the response and whether the settings were saved.
+async fn respond(request: &Request, state: &State) -> (Response, bool) {
+    if !request.host.as_deref().map_or(false, host_allowed) {
+        return (
+            Response::text("403 Forbidden", "Use the address vr-status listens on"),
+            false,
+        );
+    }
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/") => (
+            Response::new("200 OK", "text/html; charset=utf-8", PAGE),
+            false,
+        ),
+        ("GET", "/state") => (
+            Response::new("200 OK", "application/json", state.snapshot().to_string()),
+            false,
+        ),
+        ("GET", "/settings") => match read_settings_text().await {
+            Ok(text) => (
+                Response::new("200 OK", "text/yaml; charset=utf-8", text),
+                false,
+            ),
+            Err(error) => (
+                Response::text("500 Internal Server Error", format!("{:?}", error)),
+                false,
+            ),
+        },
+        // Only PUT changes the settings. Browsers won't send one from another site without
+        // asking first, and nothing here says yes.
+        ("PUT", "/settings") => {
+            let text = match String::from_utf8(request.body.clone()) {
+                Ok(text) => text,
+                Err(_) => {
+                    return (
+                        Response::text("400 Bad Request", "Settings must be UTF-8"),
+                        false,
+                    )
+                }
+            };
+            match save_settings(&text).await {
+                Ok(()) => (Response::text("200 OK", "Saved"), true),
+                Err(error) => (
+                    Response::text("400 Bad Request", format!("{:?}", error)),
+                    false,
+                ),
+            }
+        }
+        (_, "/") | (_, "/state") | (_, "/settings") => (
+            Response::text("405 Method Not Allowed", "Method not allowed"),
+            false,
+        ),
+        _ => (Response::text("404 Not Found", "Not found"), false),
+    }
+}
+
+async fn serve_client(
+    mut stream: TcpStream,
+    state: State,
+    saved: UnboundedSender<()>,
+) -> Result<()> {
+    let request = match read_request(&mut stream).await? {
+        Some(request) => request,
+        None => return Ok(()),
+    };
+    debug!("Web request {} {}", request.method, request.path);
+    let (response, was_saved) = respond(&request, &state).await;
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    stream
+        .write_all(head.as_bytes())
+        .await
+        .context("Failed to write response")?;
+    stream
+        .write_all(response.body.as_bytes())
+        .await
+        .context("Failed to write response")?;
+    stream
+        .shutdown()
+        .await
+        .context("Failed to close connection")?;
+    if was_saved {
+        let _ = saved.send(());
+    }
+    Ok(())
+}
+
+/// Serves a page showing the state and editing the settings, and returns once new
+/// settings have been saved so vr-status can start again with them.
+pub async fn web_loop(settings: &WebSettings, state: State) -> Result<()> {
+    let listener = TcpListener::bind(settings.listen)
+        .await
+        .with_context(|| format!("Failed to listen on {}", settings.listen))?;
+    info!("Settings page at http://{}/", settings.listen);
+    let (saved_send, mut saved_receive) = tokio::sync::mpsc::unbounded_channel();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, address) = accepted.context("Failed to accept connection")?;
+                debug!("Web client connected from {}", address);
+                let state = state.clone();
+                let saved = saved_send.clone();
+                tokio::spawn(async move {
+                    if let Err(error) = serve_client(stream, state, saved).await {
+                        error!("Web client failed: {:?}", error);
+                    }
+                });
+            }
+            _ = saved_receive.recv() => {
+                info!("Settings saved from the web page");
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_and_localhost_are_allowed() {
+        for host in &[
+            "localhost",
+            "LocalHost:8080",
+            "127.0.0.1",
+            "192.168.1.10:8080",
+            "[::1]",
+            "[::1]:8080",
+            "[fe80::1]:80",
+        ] {
+            assert!(host_allowed(host), "{}", host);
+        }
+    }
+
+    #[test]
+    fn other_names_are_refused() {
+        for host in &[
+            "",
+            "example.com",
+            "example.com:8080",
+            "localhost.example.com",
+            "127.0.0.1.nip.io:8080",
+            "[example.com]:8080",
+        ] {
+            assert!(!host_allowed(host), "{}", host);
+        }
+    }
+}
@@ -0,0 +1,24 @@
+use anyhow::Result;
+use bindings::openvr::k_unTrackedDeviceIndex_Hmd;
+
+use crate::{openvr::VrSystem, settings::WirelessSettings};
+
+#[derive(Clone, PartialEq)]
+pub struct LinkStatus {
+    pub quality: f32,
+    pub problem: bool,
+}
+
+/// Reads the headset link quality from the driver-specific property named in the
+/// settings.
+///
+/// OpenVR has no standard property for this, so wireless adapters that report it do
+/// so through their own vendor-specific properties.
+pub fn read_link_status(system: &VrSystem, settings: &WirelessSettings) -> Result<LinkStatus> {
+    let quality = system
+        .get_float_tracked_device_property(k_unTrackedDeviceIndex_Hmd, settings.quality_property)?;
+    Ok(LinkStatus {
+        quality,
+        problem: quality < settings.problem_below,
+    })
+}
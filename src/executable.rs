@@ -0,0 +1,37 @@
+use std::{ffi::OsString, os::windows::ffi::OsStringExt};
+
+use anyhow::{bail, Result};
+use bindings::Windows::Win32::{
+    Foundation::{CloseHandle, PWSTR},
+    System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+        PROCESS_QUERY_LIMITED_INFORMATION,
+    },
+};
+
+/// Returns the full path of the executable running as `pid`.
+pub fn executable_path(pid: u32) -> Result<String> {
+    // Long paths can exceed MAX_PATH.
+    let mut buffer = vec![0u16; 32768];
+    let mut size = buffer.len() as u32;
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid);
+        if process.is_null() {
+            bail!("OpenProcess failed for {}", pid);
+        }
+        let ok = QueryFullProcessImageNameW(
+            process,
+            PROCESS_NAME_WIN32,
+            PWSTR(buffer.as_mut_ptr()),
+            &mut size,
+        )
+        .as_bool();
+        CloseHandle(process);
+        if !ok {
+            bail!("QueryFullProcessImageNameW failed for {}", pid);
+        }
+    }
+    Ok(OsString::from_wide(&buffer[..size as usize])
+        .to_string_lossy()
+        .into_owned())
+}
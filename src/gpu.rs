@@ -0,0 +1,37 @@
+use std::{ffi::OsString, os::windows::ffi::OsStringExt};
+
+use anyhow::{bail, Context, Result};
+use bindings::Windows::Win32::Graphics::Dxgi::{
+    CreateDXGIFactory1, IDXGIFactory1, DXGI_ADAPTER_DESC1,
+};
+
+/// Finds the name of the graphics adapter with the LUID SteamVR reports for the headset,
+/// such as "NVIDIA GeForce RTX 3070 Laptop GPU".
+pub fn adapter_name(luid: u64) -> Result<String> {
+    unsafe {
+        let factory: IDXGIFactory1 =
+            CreateDXGIFactory1().context("Failed to create DXGI factory")?;
+        // EnumAdapters1 fails with DXGI_ERROR_NOT_FOUND after the last adapter.
+        let mut index = 0;
+        while let Ok(adapter) = factory.EnumAdapters1(index) {
+            index += 1;
+            let mut desc = DXGI_ADAPTER_DESC1::default();
+            if adapter.GetDesc1(&mut desc).is_err() {
+                continue;
+            }
+            let adapter_luid =
+                (desc.AdapterLuid.HighPart as u32 as u64) << 32 | desc.AdapterLuid.LowPart as u64;
+            if adapter_luid == luid {
+                let len = desc
+                    .Description
+                    .iter()
+                    .position(|c| *c == 0)
+                    .unwrap_or(desc.Description.len());
+                return Ok(OsString::from_wide(&desc.Description[..len])
+                    .to_string_lossy()
+                    .into_owned());
+            }
+        }
+    }
+    bail!("No graphics adapter has LUID {:x}", luid)
+}
@@ -0,0 +1,37 @@
+use log::{Level, Log, Metadata, Record};
+use tokio::sync::watch;
+
+/// Logs through env_logger and keeps the most recent error so it can be published for
+/// people who can't easily find the log.
+struct ErrorCapture {
+    inner: env_logger::Logger,
+    last_error: watch::Sender<Option<String>>,
+}
+
+impl Log for ErrorCapture {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() == Level::Error && self.inner.matches(record) {
+            let _ = self.last_error.send(Some(record.args().to_string()));
+        }
+        self.inner.log(record);
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Installs the logger and returns the most recent error it logged.
+pub fn init() -> watch::Receiver<Option<String>> {
+    let inner =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).build();
+    let (last_error, receiver) = watch::channel(None);
+    log::set_max_level(inner.filter());
+    log::set_boxed_logger(Box::new(ErrorCapture { inner, last_error }))
+        .expect("Logger was already installed");
+    receiver
+}
@@ -1,158 +1,1570 @@
-mod mqtt;
-mod openvr;
-mod settings;
+mod executable;
+mod gpu;
+mod logger;
+mod power;
+mod session;
+mod startup;
+mod usage;
+mod user;
+mod watchdog;
 
-use std::{ffi::CStr, time::Duration};
+use std::{
+    ffi::{CStr, CString},
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use bindings::{
     openvr::{
+        k_unTrackedDeviceIndexInvalid, k_unTrackedDeviceIndex_Hmd,
+        ETrackedControllerRole_ETrackedControllerRole_TrackedControllerRole_LeftHand,
+        ETrackedControllerRole_ETrackedControllerRole_TrackedControllerRole_RightHand,
+        ETrackedDeviceProperty_ETrackedDeviceProperty_Prop_SerialNumber_String,
         EVRApplicationProperty_EVRApplicationProperty_VRApplicationProperty_Name_String,
+        EVRApplicationProperty_EVRApplicationProperty_VRApplicationProperty_WorkingDirectory_String,
         EVRApplicationType_EVRApplicationType_VRApplication_Background,
+        EVREventType_EVREventType_VREvent_ApplicationMimeTypeLoad,
+        EVREventType_EVREventType_VREvent_ChaperoneRoomSetupFinished,
+        EVREventType_EVREventType_VREvent_ChaperoneUniverseHasChanged,
+        EVREventType_EVREventType_VREvent_Compositor_DisplayDisconnected,
+        EVREventType_EVREventType_VREvent_Compositor_DisplayReconnected,
+        EVREventType_EVREventType_VREvent_Compositor_HDCPError,
         EVREventType_EVREventType_VREvent_EnterStandbyMode,
-        EVREventType_EVREventType_VREvent_LeaveStandbyMode, EVREventType_EVREventType_VREvent_Quit,
+        EVREventType_EVREventType_VREvent_LeaveStandbyMode,
+        EVREventType_EVREventType_VREvent_PropertyChanged, EVREventType_EVREventType_VREvent_Quit,
         EVREventType_EVREventType_VREvent_SceneApplicationChanged,
         EVREventType_EVREventType_VREvent_SceneApplicationStateChanged,
+        EVREventType_EVREventType_VREvent_ScreenshotTaken,
+        EVREventType_EVREventType_VREvent_TrackedCamera_PauseVideoStream,
+        EVREventType_EVREventType_VREvent_TrackedCamera_ResumeVideoStream,
+        EVREventType_EVREventType_VREvent_TrackedCamera_StartVideoStream,
+        EVREventType_EVREventType_VREvent_TrackedCamera_StopVideoStream,
+        EVREventType_EVREventType_VREvent_TrackedDeviceActivated,
+        EVREventType_EVREventType_VREvent_TrackedDeviceDeactivated,
+        EVREventType_EVREventType_VREvent_TrackedDeviceUserInteractionEnded,
+        EVREventType_EVREventType_VREvent_TrackedDeviceUserInteractionStarted,
+        EVRSceneApplicationState, EVRSceneApplicationState_EVRSceneApplicationState_None,
+        EVRSceneApplicationState_EVRSceneApplicationState_Quitting,
+        EVRSceneApplicationState_EVRSceneApplicationState_Running,
+        EVRSceneApplicationState_EVRSceneApplicationState_Starting,
+        EVRSceneApplicationState_EVRSceneApplicationState_Waiting, TrackedDeviceIndex_t,
+    },
+    Windows::Win32::UI::WindowsAndMessaging::{
+        MessageBoxW, MB_ICONERROR, MB_ICONINFORMATION, MB_OK,
     },
-    Windows::Win32::UI::WindowsAndMessaging::{MessageBoxW, MB_ICONERROR, MB_OK},
 };
 use cstr::cstr;
-use log::{debug, error, info};
-use openvr::{VrApplications, VrSystem};
+use log::{debug, error, info, warn};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio_util::sync::CancellationToken;
+#[cfg(feature = "file")]
+use vr_status_core::file::file_loop;
+#[cfg(feature = "ipc")]
+use vr_status_core::ipc::ipc_loop;
+#[cfg(feature = "notifications")]
+use vr_status_core::notify::notify_loop;
+#[cfg(feature = "updater")]
+use vr_status_core::updater::update_loop;
+#[cfg(feature = "web")]
+use vr_status_core::web::web_loop;
+use vr_status_core::{
+    activity::{hands_on_controllers, ControllerActivity},
+    base_stations::read_base_stations,
+    calibration::Calibration,
+    camera::CameraStatus,
+    command::{clamp, Command, BRIGHTNESS_RANGE, STANDBY_TIMEOUT_RANGE, SUPERSAMPLING_RANGE},
+    devices::read_devices,
+    disconnects::DisconnectTracker,
+    display::read_display_status,
+    drivers::read_drivers,
+    events::{Occurrence, OccurrenceEvent, RawEvent, Reason},
+    frame_timing::{sample_frame_stats, FrameCounter},
+    gesture::PanicGesture,
+    history::History,
+    manifest::write_manifest,
+    mqtt::{mqtt_loop, MqttHandle, State},
+    notify::{CriticalConditions, Notification},
+    openvr::{
+        OpenVr, VrApplications, VrChaperone, VrCompositor, VrOverlay, VrSettings, VrSystem,
+        VrTrackedCamera,
+    },
+    overlays::{is_overlay_application, read_overlays},
+    pose_stream::PoseStream,
+    process::{LaunchTracker, ProcessInfo},
+    profiles::Profiles,
+    properties::PropertyCache,
+    readiness::Readiness,
+    sessions::SessionLog,
+    settings::{load_settings, LookupFailure, Settings, UniqueIdSource},
+    stats::UsageStats,
+    telemetry::Schedule,
+    topics::{cleanup, TopicRegistry},
+    updater::remove_previous_version,
+    watchdog::Progress,
+    wireless::read_link_status,
+};
 
 use crate::{
-    mqtt::{mqtt_loop, MqttHandle, State},
-    openvr::OpenVr,
-    settings::load_settings,
+    executable::executable_path,
+    gpu::adapter_name,
+    power::{PowerEvent, PowerNotifications},
+    session::watch_lock_state,
+    startup::{install_startup, remove_startup},
+    usage::UsageSampler,
+    user::read_user,
+    watchdog::{heartbeat, supervise, RESTARTED_VAR},
 };
 
-async fn run() -> Result<()> {
-    let settings = load_settings().await?;
+/// With `startup`, vr-status was started with Windows rather than by SteamVR, so it waits
+/// for SteamVR and keeps SteamVR from starting another copy. If new settings were saved
+/// and vr-status should start again with them, returns the session log to pass back in,
+/// since reloading the settings doesn't start a new SteamVR session.
+async fn run(
+    startup: bool,
+    sessions: Option<SessionLog>,
+    last_error: tokio::sync::watch::Receiver<Option<String>>,
+    progress: Arc<Progress>,
+) -> Result<Option<SessionLog>> {
+    // Shared with the OpenVR thread.
+    let settings = Arc::new(load_settings().await?);
+
+    if let Err(error) = remove_previous_version().await {
+        warn!("{:?}", error);
+    }
 
     let id = cstr!("mdonoughe.VrStatus");
-    let vr = OpenVr::new(EVRApplicationType_EVRApplicationType_VRApplication_Background)?;
+    let vr = if startup {
+        wait_for_steamvr().await
+    } else {
+        OpenVr::new(EVRApplicationType_EVRApplicationType_VRApplication_Background)?
+    };
+
     let system = vr.system()?;
     let applications = vr.applications()?;
 
-    let mut path = ::std::env::current_exe().context("Could not find installation directory")?;
-    path.pop();
-    path.push("vr-status.vrmanifest\0");
-    let path = path
-        .to_str()
-        .context("Invalid characters in installation path")?;
-    let path = CStr::from_bytes_with_nul(path.as_bytes())
-        .context("Null characters in installation path")?;
-
-    if let Err(error) = applications.add_application_manifest(path, false) {
-        bail!(
-            "Failed to register application {}: {}",
-            error,
-            applications
-                .get_applications_err_name_from_enum(error)
-                .to_string_lossy()
-        )
+    if settings.steamvr.register_manifest {
+        let mut path = if settings.steamvr.generate_manifest {
+            write_manifest(&settings.steamvr.manifest)
+                .await
+                .context("Failed to generate application manifest")?
+        } else {
+            let mut path =
+                ::std::env::current_exe().context("Could not find installation directory")?;
+            path.pop();
+            path.push("vr-status.vrmanifest");
+            path
+        }
+        .into_os_string();
+        path.push("\0");
+        let path = path
+            .to_str()
+            .context("Invalid characters in manifest path")?;
+        let path = CStr::from_bytes_with_nul(path.as_bytes())
+            .context("Null characters in manifest path")?;
+        applications
+            .add_application_manifest(path, settings.steamvr.portable)
+            .context("Failed to register application")?;
     }
 
-    if !applications.get_application_auto_launch(id) {
-        if let Err(error) = applications.set_application_auto_launch(id, true) {
-            bail!(
-                "Failed to enable auto launch {}: {}",
-                error,
-                applications
-                    .get_applications_err_name_from_enum(error)
-                    .to_string_lossy()
-            )
-        }
+    // Started with Windows, another copy started by SteamVR would report everything twice.
+    let auto_launch = !startup;
+    if settings.steamvr.auto_launch && applications.get_application_auto_launch(id) != auto_launch {
+        applications
+            .set_application_auto_launch(id, auto_launch)
+            .context("Failed to change auto launch")?;
     }
 
-    let (active_send, active_receive) = tokio::sync::watch::channel(true);
-    let (application_send, application_receive) = tokio::sync::watch::channel(String::new());
+    let unique_id = match settings.hass_unique_id {
+        UniqueIdSource::Id => settings.id.clone(),
+        UniqueIdSource::Serial => match system.get_string_tracked_device_property(
+            k_unTrackedDeviceIndex_Hmd,
+            ETrackedDeviceProperty_ETrackedDeviceProperty_Prop_SerialNumber_String,
+        ) {
+            Ok(serial) => serial,
+            Err(error) => {
+                warn!(
+                    "Failed to read headset serial number, falling back to id: {:?}",
+                    error
+                );
+                settings.id.clone()
+            }
+        },
+    };
 
-    let mqtt = MqttHandle {
-        active: active_send,
-        application: application_send,
+    let shutdown = CancellationToken::new();
+    let (vr_state_send, vr_state_receive) = tokio::sync::watch::channel(Default::default());
+    let (update_send, update_receive) = tokio::sync::watch::channel(None);
+    let (link_send, link_receive) = tokio::sync::watch::channel(None);
+    let (mirror_window_send, mirror_window_receive) = tokio::sync::watch::channel(false);
+    let (scene_state_send, scene_state_receive) = tokio::sync::watch::channel(String::new());
+    let (frame_stats_send, frame_stats_receive) = tokio::sync::watch::channel(None);
+    let (frame_counts_send, frame_counts_receive) = tokio::sync::watch::channel(None);
+    let (application_key_send, application_key_receive) =
+        tokio::sync::watch::channel(String::new());
+    let (supersampling_send, supersampling_receive) = tokio::sync::watch::channel(None);
+    let (brightness_send, brightness_receive) = tokio::sync::watch::channel(None);
+    let (standby_timeout_send, standby_timeout_receive) = tokio::sync::watch::channel(None);
+    let (motion_smoothing_send, motion_smoothing_receive) = tokio::sync::watch::channel(None);
+    let (safe_mode_send, safe_mode_receive) = tokio::sync::watch::channel(None);
+    let (base_stations_send, base_stations_receive) =
+        tokio::sync::watch::channel(Default::default());
+    let (devices_send, devices_receive) = tokio::sync::watch::channel(Default::default());
+    let (reason_send, reason_receive) = tokio::sync::watch::channel(None);
+    let (camera_send, camera_receive) = tokio::sync::watch::channel(None);
+    let (display_send, display_receive) = tokio::sync::watch::channel(None);
+    let (display_problem_send, display_problem_receive) = tokio::sync::watch::channel(false);
+    let (controllers_in_use_send, controllers_in_use_receive) = tokio::sync::watch::channel(false);
+    let (ready_to_play_send, ready_to_play_receive) = tokio::sync::watch::channel(None);
+    let (hands_on_controllers_send, hands_on_controllers_receive) =
+        tokio::sync::watch::channel(None);
+    let (calibration_send, calibration_receive) = tokio::sync::watch::channel(None);
+    let (process_send, process_receive) = tokio::sync::watch::channel(None);
+    let (process_usage_send, process_usage_receive) = tokio::sync::watch::channel(None);
+    let (overlays_send, overlays_receive) = tokio::sync::watch::channel(Default::default());
+    let (boundary_collisions_send, boundary_collisions_receive) = tokio::sync::watch::channel(0);
+    let (disconnects_send, disconnects_receive) = tokio::sync::watch::channel(Default::default());
+    let (user_send, user_receive) = tokio::sync::watch::channel(None);
+    let (poll_latency_send, poll_latency_receive) = tokio::sync::watch::channel(None);
+    let (drivers_send, drivers_receive) = tokio::sync::watch::channel(Default::default());
+    let (locked_send, locked_receive) = tokio::sync::watch::channel(false);
+    let (events_send, events_receive) = tokio::sync::mpsc::unbounded_channel();
+    let (occurrences_send, occurrences_receive) = tokio::sync::mpsc::unbounded_channel();
+    let (command_send, command_receive) = tokio::sync::mpsc::unbounded_channel();
+    let (notification_send, notification_receive) = tokio::sync::mpsc::unbounded_channel();
+
+    let mut mqtt = MqttHandle {
+        vr_state: vr_state_send,
+        link: link_send,
+        mirror_window: mirror_window_send,
+        scene_state: scene_state_send,
+        frame_stats: frame_stats_send,
+        frame_counts: frame_counts_send,
+        base_stations: base_stations_send,
+        devices: devices_send,
+        application_key: application_key_send,
+        supersampling: supersampling_send,
+        brightness: brightness_send,
+        standby_timeout: standby_timeout_send,
+        motion_smoothing: motion_smoothing_send,
+        safe_mode: safe_mode_send,
+        events: events_send,
+        occurrences: occurrences_send,
+        reason: reason_send,
+        camera: camera_send,
+        drivers: drivers_send,
+        display: display_send,
+        display_problem: display_problem_send,
+        controllers_in_use: controllers_in_use_send,
+        ready_to_play: ready_to_play_send,
+        hands_on_controllers: hands_on_controllers_send,
+        calibration: calibration_send,
+        process: process_send,
+        process_usage: process_usage_send,
+        overlays: overlays_send,
+        boundary_collisions: boundary_collisions_send,
+        disconnects: disconnects_send,
+        user: user_send,
+        poll_latency: poll_latency_send,
+        progress: progress.clone(),
+        shutdown: shutdown.clone(),
+    };
+    // Only the first session after a restart reports it.
+    if std::env::var_os(RESTARTED_VAR).is_some() {
+        std::env::remove_var(RESTARTED_VAR);
+        mqtt.send_occurrence(Occurrence::WatchdogRestart)
+            .context("Failed to queue watchdog event")?;
+    }
+
+    if let Err(error) = watch_lock_state(locked_send) {
+        warn!("Session lock state is not available: {:?}", error);
+    }
+
+    let mut sessions = match sessions {
+        Some(sessions) => sessions,
+        None => {
+            let sessions = SessionLog::start()
+                .await
+                .context("Failed to record SteamVR session")?;
+            info!("SteamVR session counts: {:?}", sessions.counts());
+            sessions
+        }
     };
 
     let state = State {
-        active: active_receive,
-        application: application_receive,
+        vr_state: vr_state_receive,
+        update: update_receive,
+        link: link_receive,
+        mirror_window: mirror_window_receive,
+        scene_state: scene_state_receive,
+        frame_stats: frame_stats_receive,
+        frame_counts: frame_counts_receive,
+        base_stations: base_stations_receive,
+        devices: devices_receive,
+        application_key: application_key_receive,
+        supersampling: supersampling_receive,
+        brightness: brightness_receive,
+        standby_timeout: standby_timeout_receive,
+        motion_smoothing: motion_smoothing_receive,
+        safe_mode: safe_mode_receive,
+        reason: reason_receive,
+        camera: camera_receive,
+        drivers: drivers_receive,
+        locked: locked_receive,
+        display: display_receive,
+        display_problem: display_problem_receive,
+        controllers_in_use: controllers_in_use_receive,
+        ready_to_play: ready_to_play_receive,
+        hands_on_controllers: hands_on_controllers_receive,
+        calibration: calibration_receive,
+        process: process_receive,
+        process_usage: process_usage_receive,
+        overlays: overlays_receive,
+        boundary_collisions: boundary_collisions_receive,
+        disconnects: disconnects_receive,
+        user: user_receive,
+        poll_latency: poll_latency_receive,
+        progress,
+        last_error,
+        sessions: sessions.counts().clone(),
+        shutdown: shutdown.clone(),
+    };
+
+    #[cfg(feature = "ipc")]
+    let ipc_commands = command_send.clone();
+    #[cfg(feature = "ipc")]
+    let ipc_notifications = settings
+        .notifications
+        .as_ref()
+        .map(|_| notification_send.clone());
+    // OpenVR calls block, so they run on their own thread where a slow or hung call can't
+    // hold up the sinks or the MQTT keepalives. The MqttHandle channels carry the state
+    // back.
+    let (vr_send, vr_receive) = tokio::sync::oneshot::channel();
+    let vr_settings = settings.clone();
+    std::thread::Builder::new()
+        .name("openvr".into())
+        .spawn(move || {
+            let result = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .context("Failed to start OpenVR runtime")
+                .and_then(|runtime| {
+                    runtime.block_on(async {
+                        let power = PowerNotifications::register()
+                            .context("Failed to register for power events")?;
+                        vr_loop(
+                            &vr_settings,
+                            vr,
+                            power,
+                            command_receive,
+                            notification_send,
+                            mqtt,
+                        )
+                        .await
+                    })
+                });
+            let _ = vr_send.send(result);
+        })
+        .context("Failed to start OpenVR thread")?;
+    let main_future = async {
+        vr_receive
+            .await
+            .unwrap_or_else(|_| Err(anyhow!("OpenVR thread stopped unexpectedly")))
+    };
+    let file_state = state.clone();
+    let file_future = async {
+        // Settings validation rejects sinks that weren't built in.
+        match &settings.file {
+            #[cfg(feature = "file")]
+            Some(file) => file_loop(file, file_state).await,
+            _ => {
+                file_state.shutdown.cancelled().await;
+                Ok(())
+            }
+        }
+    };
+    let updater_future = async {
+        match &settings.updater {
+            #[cfg(feature = "updater")]
+            Some(updater) => update_loop(updater, update_send).await,
+            _ => {
+                // Keep the sender alive so the MQTT loop doesn't see the channel close.
+                let _update_send = update_send;
+                std::future::pending().await
+            }
+        }
+    };
+    let notify_future = async {
+        match &settings.notifications {
+            #[cfg(feature = "notifications")]
+            Some(notifications) => notify_loop(notifications, notification_receive).await,
+            _ => {
+                let _notification_receive = notification_receive;
+                std::future::pending().await
+            }
+        }
     };
+    #[cfg(feature = "ipc")]
+    let ipc_state = state.clone();
+    let ipc_future = async {
+        match &settings.ipc {
+            #[cfg(feature = "ipc")]
+            Some(ipc) => ipc_loop(ipc, ipc_state, ipc_commands, ipc_notifications).await,
+            _ => std::future::pending().await,
+        }
+    };
+    #[cfg(feature = "web")]
+    let web_state = state.clone();
+    let web_future = async {
+        match &settings.web {
+            #[cfg(feature = "web")]
+            Some(web) => web_loop(web, web_state).await,
+            _ => std::future::pending().await,
+        }
+    };
+    let history = History::load().await?;
+    let stats = match &settings.stats {
+        Some(stats) => Some(UsageStats::load(stats).await?),
+        None => None,
+    };
+    let registry = TopicRegistry::load(&settings.id).await?;
+    let mqtt_future = mqtt_loop(
+        &settings,
+        &unique_id,
+        history,
+        stats,
+        registry,
+        state,
+        events_receive,
+        occurrences_receive,
+        command_send,
+    );
 
-    let main_future = main_loop(&system, &applications, mqtt);
-    let mqtt_future = mqtt_loop(&settings, state);
+    tokio::pin!(main_future, mqtt_future, file_future);
+    // Shutdown requests are checked first so a sink finishing because of one doesn't
+    // skip the orderly path.
+    let mut reload = false;
+    let vr = tokio::select! {
+        biased;
+        result = &mut main_future => result?,
+        _ = shutdown.cancelled() => {
+            info!("Shutting down");
+            stop_vr(main_future).await?
+        }
+        _ = tokio::signal::ctrl_c() => {
+            info!("Interrupted");
+            shutdown.cancel();
+            stop_vr(main_future).await?
+        }
+        result = web_future => {
+            result?;
+            info!("Restarting with the new settings");
+            reload = true;
+            shutdown.cancel();
+            stop_vr(main_future).await?
+        }
+        result = &mut mqtt_future => return result.map(|()| None),
+        result = &mut file_future => return result.map(|()| None),
+        result = updater_future => return result.map(|()| None),
+        result = notify_future => return result.map(|()| None),
+        result = ipc_future => return result.map(|()| None),
+    };
+    if vr.is_some() && !reload {
+        sessions.quit().await?;
+    }
 
-    tokio::select! {
-        result = main_future => result,
-        result = mqtt_future => result,
+    // Give the sinks a chance to publish the final state before SteamVR sees vr-status
+    // go away.
+    shutdown.cancel();
+    match tokio::time::timeout(SHUTDOWN_TIMEOUT, async {
+        tokio::try_join!(mqtt_future, file_future)
+    })
+    .await
+    {
+        Ok(result) => {
+            result?;
+        }
+        Err(_) => warn!("Timed out publishing final state"),
     }
+    drop(vr);
+    Ok(if reload { Some(sessions) } else { None })
 }
 
-async fn main_loop<'a>(
-    system: &VrSystem<'a>,
-    applications: &VrApplications<'a>,
-    mut mqtt: MqttHandle,
-) -> Result<()> {
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+const STEAMVR_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+async fn wait_for_steamvr() -> OpenVr {
+    info!("Waiting for SteamVR");
     loop {
-        match system.poll_next_event() {
-            Some(event) =>
+        match OpenVr::new(EVRApplicationType_EVRApplicationType_VRApplication_Background) {
+            Ok(vr) => return vr,
+            Err(error) => debug!("SteamVR is not running yet: {:?}", error),
+        }
+        tokio::time::sleep(STEAMVR_RETRY_INTERVAL).await;
+    }
+}
+
+/// Waits for the OpenVR loop to notice shutdown, unless it's stuck in a call that isn't
+/// coming back.
+async fn stop_vr(
+    main_future: impl Future<Output = Result<Option<OpenVr>>>,
+) -> Result<Option<OpenVr>> {
+    match tokio::time::timeout(SHUTDOWN_TIMEOUT, main_future).await {
+        Ok(result) => result,
+        Err(_) => {
+            warn!("OpenVR did not stop");
+            Ok(None)
+        }
+    }
+}
+
+const STEAMVR_SECTION: &CStr = cstr!("steamvr");
+const SUPERSAMPLE_SCALE: &CStr = cstr!("supersampleScale");
+const SUPERSAMPLE_MANUAL_OVERRIDE: &CStr = cstr!("supersampleManualOverride");
+const MOTION_SMOOTHING: &CStr = cstr!("motionSmoothing");
+/// Set when SteamVR starts with add-ons disabled, usually after one crashed it.
+const SAFE_MODE: &CStr = cstr!("enableSafeMode");
+/// Display brightness, where 1.0 is 100%. Only some headsets have it.
+const ANALOG_GAIN: &CStr = cstr!("analogGain");
+const POWER_SECTION: &CStr = cstr!("power");
+/// Seconds without the headset being worn before SteamVR puts it into standby.
+const TURN_OFF_SCREENS_TIMEOUT: &CStr = cstr!("turnOffScreensTimeout");
+
+fn set_supersampling(vr_settings: &VrSettings, scale: f32) -> Result<()> {
+    let scale = clamp(scale, SUPERSAMPLING_RANGE)
+        .with_context(|| format!("Invalid supersampling scale {}", scale))?;
+    // The scale is ignored unless SteamVR's automatic resolution is overridden.
+    vr_settings.set_bool(STEAMVR_SECTION, SUPERSAMPLE_MANUAL_OVERRIDE, true)?;
+    vr_settings.set_float(STEAMVR_SECTION, SUPERSAMPLE_SCALE, scale)?;
+    Ok(())
+}
+
+/// Launches the application registered for the URL's scheme, such as the SteamVR web
+/// browser for https.
+fn launch_url(
+    applications: &VrApplications,
+    launches: &mut LaunchTracker,
+    url: &str,
+) -> Result<()> {
+    let scheme = match url.find(':') {
+        Some(end) => &url[..end],
+        None => bail!("URL has no scheme"),
+    };
+    let mime_type = CString::new(format!("x-scheme-handler/{}", scheme.to_ascii_lowercase()))
+        .context("Invalid URL scheme")?;
+    let handlers = applications.get_applications_that_support_mime_type(&mime_type)?;
+    if handlers.is_empty() {
+        bail!("No application handles {}", mime_type.to_string_lossy());
+    }
+    debug!(
+        "Applications that handle {}: {:?}",
+        mime_type.to_string_lossy(),
+        handlers
+    );
+    launches.requested(handlers);
+    let url = CString::new(url).context("Invalid URL")?;
+    Ok(applications.launch_application_from_mime_type(&mime_type, &url)?)
+}
+
+fn find_device(system: &VrSystem, device: &str) -> Option<TrackedDeviceIndex_t> {
+    let index = match device {
+        "left" => system.get_tracked_device_index_for_controller_role(
+            ETrackedControllerRole_ETrackedControllerRole_TrackedControllerRole_LeftHand,
+        ),
+        "right" => system.get_tracked_device_index_for_controller_role(
+            ETrackedControllerRole_ETrackedControllerRole_TrackedControllerRole_RightHand,
+        ),
+        index => index.parse().ok()?,
+    };
+    if index == k_unTrackedDeviceIndexInvalid {
+        None
+    } else {
+        Some(index)
+    }
+}
+
+fn scene_application_state_name(state: EVRSceneApplicationState) -> &'static str {
+    #[allow(non_upper_case_globals)]
+    match state {
+        EVRSceneApplicationState_EVRSceneApplicationState_None => "none",
+        EVRSceneApplicationState_EVRSceneApplicationState_Starting => "starting",
+        EVRSceneApplicationState_EVRSceneApplicationState_Quitting => "quitting",
+        EVRSceneApplicationState_EVRSceneApplicationState_Running => "running",
+        EVRSceneApplicationState_EVRSceneApplicationState_Waiting => "waiting",
+        _ => "unknown",
+    }
+}
+
+/// Applies `application_lookup.on_failure` after the running application couldn't be
+/// identified. Returns true if the lookup should be tried again.
+fn application_lookup_failed(settings: &Settings, mqtt: &mut MqttHandle, pid: u32) -> Result<bool> {
+    let application = match settings.application_lookup.on_failure {
+        LookupFailure::KeepLast => return Ok(false),
+        LookupFailure::Retry => return Ok(true),
+        LookupFailure::Pid => pid.to_string(),
+        LookupFailure::Unknown => "Unknown".to_string(),
+    };
+    mqtt.set_process(None)
+        .context("Failed to queue process update")?;
+    mqtt.set_application(application)
+        .context("Failed to queue application update")?;
+    Ok(false)
+}
+
+/// Publishes the scene application. Returns true if it couldn't be identified and
+/// should be looked up again.
+fn scene_application_changed(
+    settings: &Settings,
+    interfaces: &Interfaces,
+    launches: &mut LaunchTracker,
+    mqtt: &mut MqttHandle,
+) -> Result<bool> {
+    let applications = &interfaces.applications;
+    let scene_state = applications.get_scene_application_state();
+    debug!(
+        "Scene application state is now {}",
+        scene_application_state_name(scene_state)
+    );
+    mqtt.set_scene_state(scene_application_state_name(scene_state).to_string())
+        .context("Failed to queue scene application state update")?;
+
+    let pid = applications.get_current_scene_process_id();
+    if pid == 0 || scene_state == EVRSceneApplicationState_EVRSceneApplicationState_None {
+        mqtt.set_application_key(String::new())
+            .context("Failed to queue application key update")?;
+        mqtt.set_process(None)
+            .context("Failed to queue process update")?;
+        if let Some(idle) = &settings.idle_application {
+            info!("No application is running");
+            mqtt.set_application(idle.value.clone())
+                .context("Failed to queue application update")?;
+        }
+        return Ok(false);
+    }
+    debug!("Active application pid is now {}", pid);
+    let key = match applications
+        .get_application_key_by_process_id(pid)
+        .context("Failed to get application key")
+    {
+        Ok(key) => key,
+        Err(error) => {
+            error!("Failed to retrieve application key: {:?}", error);
+            return application_lookup_failed(settings, mqtt, pid);
+        }
+    };
+    debug!("Active application key is now {}", key.to_string_lossy());
+    if settings.overlays.is_some() && is_overlay_application(applications, &key) {
+        debug!("Ignoring overlay application {}", key.to_string_lossy());
+        return Ok(false);
+    }
+    let key_string = key.to_string_lossy();
+    // The dashboard may be closed by the time the application is running, so how it was
+    // started is decided as soon as it appears.
+    let (launched_by, launch_arguments) = launches.started(
+        &key_string,
+        pid,
+        applications.get_application_auto_launch(&key),
+        interfaces
+            .overlay
+            .as_ref()
+            .map_or(false, |overlay| overlay.is_dashboard_visible()),
+    );
+    // The name isn't available until the application is running, but automations that
+    // only need to know which application it is can start on the key.
+    if !settings
+        .ignore
+        .ignores_application(&key_string, &key_string)
+    {
+        mqtt.set_application_key(key_string.into_owned())
+            .context("Failed to queue application key update")?;
+    }
+
+    if scene_state != EVRSceneApplicationState_EVRSceneApplicationState_Running {
+        // While SteamVR is switching applications the old one may still be reported, so
+        // wait for the new one to be running before publishing its name.
+        return Ok(false);
+    }
+
+    match applications
+        .get_application_property_string(
+            &key,
+            EVRApplicationProperty_EVRApplicationProperty_VRApplicationProperty_Name_String,
+        )
+        .context("Failed to get application name")
+    {
+        Ok(name)
+            if settings
+                .ignore
+                .ignores_application(&key.to_string_lossy(), &name) =>
+        {
+            debug!("Ignoring application {}", name);
+        }
+        Ok(name) => {
+            info!("Active application is now {}", name);
+            let process = ProcessInfo {
+                launched_by,
+                launch_arguments,
+                ..read_process_info(applications, &key, pid)
+            };
+            mqtt.set_process(Some(process))
+                .context("Failed to queue process update")?;
+            mqtt.set_application(name)
+                .context("Failed to queue application update")?;
+        }
+        Err(error) => {
+            error!("Failed to retrieve application name: {:?}", error);
+            return application_lookup_failed(settings, mqtt, pid);
+        }
+    }
+    Ok(false)
+}
+
+fn read_process_info(applications: &VrApplications, key: &CStr, pid: u32) -> ProcessInfo {
+    let executable = executable_path(pid).unwrap_or_else(|error| {
+        debug!("Executable not available: {:?}", error);
+        String::new()
+    });
+    // Windows can't report another process's current directory, but SteamVR starts
+    // applications in the one from their manifest.
+    let working_directory = applications
+        .get_application_property_string(
+            key,
+            EVRApplicationProperty_EVRApplicationProperty_VRApplicationProperty_WorkingDirectory_String,
+        )
+        .unwrap_or_else(|error| {
+            debug!("Working directory not available: {:?}", error);
+            String::new()
+        });
+    ProcessInfo {
+        executable,
+        working_directory,
+        ..Default::default()
+    }
+}
+
+fn update_display_status(
+    system: &VrSystem,
+    vr_settings: Option<&VrSettings>,
+    mqtt: &mut MqttHandle,
+) -> Result<()> {
+    match read_display_status(system) {
+        Ok(mut display) => {
+            if let Some(luid) = system.get_output_device_luid() {
+                match adapter_name(luid) {
+                    Ok(name) => display.gpu = Some(name),
+                    Err(error) => debug!("Failed to find graphics adapter: {:?}", error),
+                }
+            }
+            let previous = mqtt
+                .display
+                .borrow()
+                .as_ref()
+                .map(|previous| (previous.render_width, previous.render_height));
+            if let Some((width, height)) = previous
+                .filter(|&previous| previous != (display.render_width, display.render_height))
             {
-                #[allow(non_upper_case_globals)]
-                match event.eventType as i32 {
-                    EVREventType_EVREventType_VREvent_SceneApplicationChanged
-                    | EVREventType_EVREventType_VREvent_SceneApplicationStateChanged => {
-                        let pid = applications.get_current_scene_process_id();
-                        if pid != 0 {
-                            debug!("Active application pid is now {}", pid);
-                            match applications
-                                .get_application_key_by_process_id(pid)
-                                .context("Failed to get application key")
-                            {
-                                Ok(key) => {
-                                    debug!(
-                                        "Active application key is now {}",
-                                        key.to_string_lossy()
-                                    );
-                                    match applications.get_application_property_string(&key, EVRApplicationProperty_EVRApplicationProperty_VRApplicationProperty_Name_String).context("Failed to get application name") {
-                                        Ok(name) => {
-                                            info!("Active application is now {}", name);
-                                            mqtt.set_application(name).context("Failed to queue application update")?;
-                                        }
-                                        Err(error) => {
-                                            error!("Failed to retrieve application name: {:?}", error)
-                                        }
-                                    }
+                info!(
+                    "Render resolution changed from {}x{} to {}x{}",
+                    width, height, display.render_width, display.render_height
+                );
+                let reason = vr_settings
+                    .and_then(|vr_settings| {
+                        vr_settings
+                            .get_bool(STEAMVR_SECTION, SUPERSAMPLE_MANUAL_OVERRIDE)
+                            .ok()
+                    })
+                    .map(|manual| if manual { "manual" } else { "auto" });
+                let event = OccurrenceEvent::new(Occurrence::RenderResolutionChanged, reason)
+                    .detail("width", display.render_width)
+                    .detail("height", display.render_height)
+                    .detail("previous_width", width)
+                    .detail("previous_height", height);
+                mqtt.send_occurrence_event(event)
+                    .context("Failed to queue render resolution event")?;
+            }
+            mqtt.set_display(Some(display))
+                .context("Failed to queue display update")
+        }
+        Err(error) => {
+            debug!("Display status not available: {:?}", error);
+            Ok(())
+        }
+    }
+}
+
+fn set_camera_active(
+    camera: &mut Option<CameraStatus>,
+    active: bool,
+    mqtt: &mut MqttHandle,
+) -> Result<()> {
+    if let Some(camera) = camera {
+        camera.active = active;
+        mqtt.set_camera(Some(camera.clone()))
+            .context("Failed to queue camera update")?;
+    }
+    Ok(())
+}
+
+enum LoopExit {
+    Quit,
+    Resumed,
+}
+
+async fn vr_loop(
+    settings: &Settings,
+    mut vr: OpenVr,
+    mut power: PowerNotifications,
+    mut commands: UnboundedReceiver<Command>,
+    notifications: UnboundedSender<Notification>,
+    mut mqtt: MqttHandle,
+) -> Result<Option<OpenVr>> {
+    loop {
+        let exit = {
+            let system = vr.system()?;
+            let applications = vr.applications()?;
+            let compositor = match vr.compositor() {
+                Ok(compositor) => Some(compositor),
+                Err(error) => {
+                    warn!("Mirror window control is not available: {:?}", error);
+                    None
+                }
+            };
+            let vr_settings = match vr.settings() {
+                Ok(vr_settings) => Some(vr_settings),
+                Err(error) => {
+                    warn!("SteamVR settings control is not available: {:?}", error);
+                    None
+                }
+            };
+            match vr.driver_manager() {
+                Ok(driver_manager) => mqtt
+                    .set_drivers(read_drivers(&driver_manager))
+                    .context("Failed to queue driver update")?,
+                Err(error) => warn!("Driver list is not available: {:?}", error),
+            }
+            let tracked_camera = match vr.tracked_camera() {
+                Ok(tracked_camera) => Some(tracked_camera),
+                Err(error) => {
+                    warn!("Camera status is not available: {:?}", error);
+                    None
+                }
+            };
+            let overlay = match settings.overlays {
+                Some(_) => match vr.overlay() {
+                    Ok(overlay) => Some(overlay),
+                    Err(error) => {
+                        warn!("Overlay status is not available: {:?}", error);
+                        None
+                    }
+                },
+                None => None,
+            };
+            let chaperone = match vr.chaperone() {
+                Ok(chaperone) => Some(chaperone),
+                Err(error) => {
+                    warn!("Chaperone status is not available: {:?}", error);
+                    None
+                }
+            };
+            let interfaces = Interfaces {
+                system,
+                applications,
+                chaperone,
+                compositor,
+                overlay,
+                settings: vr_settings,
+                tracked_camera,
+            };
+            main_loop(
+                settings,
+                &interfaces,
+                &mut power,
+                &mut commands,
+                &notifications,
+                &mut mqtt,
+            )
+            .await?
+        };
+
+        match exit {
+            LoopExit::Quit => {
+                // The caller records the quit and drops this once everything else has
+                // finished.
+                return Ok(Some(vr));
+            }
+            LoopExit::Resumed => {
+                // The connection to SteamVR does not reliably survive sleep, so start a
+                // new session. If SteamVR went away in the meantime there's nothing left
+                // to report on.
+                drop(vr);
+                vr = match OpenVr::new(
+                    EVRApplicationType_EVRApplicationType_VRApplication_Background,
+                ) {
+                    Ok(vr) => vr,
+                    Err(error) => {
+                        info!("SteamVR did not resume: {:?}", error);
+                        return Ok(None);
+                    }
+                };
+                mqtt.set_power(true)
+                    .context("Failed to queue power update")?;
+                mqtt.send_occurrence_because(Occurrence::StandbyLeft, "resume")
+                    .context("Failed to queue standby event")?;
+                mqtt.set_active(true)
+                    .context("Failed to queue standby update")?;
+            }
+        }
+    }
+}
+
+/// The OpenVR interfaces for one SteamVR session.
+struct Interfaces<'a> {
+    system: VrSystem<'a>,
+    applications: VrApplications<'a>,
+    chaperone: Option<VrChaperone<'a>>,
+    compositor: Option<VrCompositor<'a>>,
+    overlay: Option<VrOverlay<'a>>,
+    settings: Option<VrSettings<'a>>,
+    tracked_camera: Option<VrTrackedCamera<'a>>,
+}
+
+async fn main_loop<'a>(
+    settings: &Settings,
+    interfaces: &Interfaces<'a>,
+    power: &mut PowerNotifications,
+    commands: &mut UnboundedReceiver<Command>,
+    notifications: &UnboundedSender<Notification>,
+    mqtt: &mut MqttHandle,
+) -> Result<LoopExit> {
+    let system = &interfaces.system;
+    let applications = &interfaces.applications;
+    let compositor = interfaces.compositor.as_ref();
+    let vr_settings = interfaces.settings.as_ref();
+
+    // The longest pulse OpenVR accepts is too short to notice, so keep pulsing until
+    // the deadline.
+    const IDENTIFY_DURATION: Duration = Duration::from_secs(1);
+    let mut identifying = Vec::new();
+    let mut next_link_check = Instant::now();
+    let mut next_state_check = Instant::now();
+    let mut next_frame_timing_check = Instant::now();
+    let mut next_process_usage_check = Instant::now();
+    let mut next_overlay_check = Instant::now();
+    let mut usage_sampler = UsageSampler::new();
+    let mut frame_counter = FrameCounter::default();
+    let mut next_base_station_check = Instant::now();
+    let mut next_device_check = Instant::now();
+    let mut next_critical_check = Instant::now();
+    let mut next_ready_check = Instant::now();
+    let mut schedule = Schedule::new(&settings.standby);
+    // The next application lookup retry and how many have been made.
+    let mut lookup_retry: Option<(Instant, u32)> = None;
+    let mut critical_conditions = CriticalConditions::default();
+    let mut bounds_visible = false;
+    let mut panic_gesture = settings.panic_gesture.as_ref().map(PanicGesture::new);
+    let mut launches = LaunchTracker::default();
+    let mut profiles = Profiles::new(&settings.on_application);
+    let mut controller_activity = settings
+        .controllers_in_use
+        .as_ref()
+        .map(ControllerActivity::new);
+    let mut disconnects = DisconnectTracker::new(&settings.disconnects);
+    let mut properties = PropertyCache::default();
+    let _progress = mqtt.progress.start("openvr");
+    // The slowest pass through the loop is reported rather than every pass.
+    const POLL_LATENCY_INTERVAL: Duration = Duration::from_secs(10);
+    let mut slowest_poll = Duration::default();
+    let mut next_poll_latency = Instant::now() + POLL_LATENCY_INTERVAL;
+    let mut pose_stream = match &settings.pose_stream {
+        Some(pose_stream) => match PoseStream::new(pose_stream) {
+            Ok(pose_stream) => Some(pose_stream),
+            Err(error) => {
+                error!("Pose streaming is not available: {:?}", error);
+                None
+            }
+        },
+        None => None,
+    };
+    // Poll faster when poses are streamed faster than the usual 50Hz.
+    let poll_interval = pose_stream
+        .as_ref()
+        .map_or(Duration::from_millis(20), |pose_stream| {
+            pose_stream.interval().min(Duration::from_millis(20))
+        });
+    update_display_status(system, vr_settings, mqtt)?;
+    mqtt.set_display_problem(false)
+        .context("Failed to queue display problem update")?;
+    let mut camera = interfaces.tracked_camera.as_ref().map(CameraStatus::new);
+    mqtt.set_camera(camera.clone())
+        .context("Failed to queue camera update")?;
+    loop {
+        let poll_started = Instant::now();
+        mqtt.progress.beat("openvr");
+        if mqtt.shutdown.is_cancelled() {
+            return Ok(LoopExit::Quit);
+        }
+
+        if schedule.set_active(mqtt.vr_state.borrow().active) {
+            next_link_check = Instant::now();
+            next_frame_timing_check = Instant::now();
+            next_process_usage_check = Instant::now();
+            next_overlay_check = Instant::now();
+            next_base_station_check = Instant::now();
+            next_device_check = Instant::now();
+        }
+
+        while let Ok(command) = commands.try_recv() {
+            match command {
+                Command::MirrorWindow(visible) => match compositor {
+                    Some(compositor) => {
+                        if visible {
+                            compositor.show_mirror_window();
+                        } else {
+                            compositor.hide_mirror_window();
+                        }
+                        next_state_check = Instant::now();
+                    }
+                    None => warn!("Can't change mirror window without compositor"),
+                },
+                Command::Supersampling(scale) => match vr_settings {
+                    Some(vr_settings) => {
+                        if let Err(error) = set_supersampling(vr_settings, scale) {
+                            error!("Failed to set supersampling: {:?}", error);
+                        }
+                        next_state_check = Instant::now();
+                    }
+                    None => warn!("Can't change supersampling without settings"),
+                },
+                Command::MotionSmoothing(enabled) => match vr_settings {
+                    Some(vr_settings) => {
+                        if let Err(error) =
+                            vr_settings.set_bool(STEAMVR_SECTION, MOTION_SMOOTHING, enabled)
+                        {
+                            error!("Failed to set motion smoothing: {:?}", error);
+                        }
+                        next_state_check = Instant::now();
+                    }
+                    None => warn!("Can't change motion smoothing without settings"),
+                },
+                Command::StandbyTimeout(seconds) => match vr_settings {
+                    Some(vr_settings) => {
+                        match clamp(seconds, STANDBY_TIMEOUT_RANGE) {
+                            Some(seconds) => {
+                                if let Err(error) = vr_settings.set_float(
+                                    POWER_SECTION,
+                                    TURN_OFF_SCREENS_TIMEOUT,
+                                    seconds,
+                                ) {
+                                    error!("Failed to set standby timeout: {:?}", error);
                                 }
-                                Err(error) => {
-                                    error!("Failed to retrieve application key: {:?}", error)
+                            }
+                            None => warn!("Invalid standby timeout {}", seconds),
+                        }
+                        next_state_check = Instant::now();
+                    }
+                    None => warn!("Can't change standby timeout without settings"),
+                },
+                Command::Brightness(percent) => match vr_settings {
+                    Some(vr_settings) => {
+                        match clamp(percent, BRIGHTNESS_RANGE) {
+                            Some(percent) => {
+                                if let Err(error) = vr_settings.set_float(
+                                    STEAMVR_SECTION,
+                                    ANALOG_GAIN,
+                                    percent / 100.0,
+                                ) {
+                                    error!("Failed to set brightness: {:?}", error);
                                 }
                             }
+                            None => warn!("Invalid brightness {}", percent),
+                        }
+                        next_state_check = Instant::now();
+                    }
+                    None => warn!("Can't change brightness without settings"),
+                },
+                Command::LaunchApplication(key) => match CString::new(key) {
+                    Ok(key) => {
+                        launches.requested(vec![key.to_string_lossy().into_owned()]);
+                        if let Err(error) = applications.launch_application(&key) {
+                            error!("Failed to launch {}: {}", key.to_string_lossy(), error);
                         }
                     }
-                    EVREventType_EVREventType_VREvent_EnterStandbyMode => mqtt
-                        .set_active(false)
-                        .context("Failed to queue standby update")?,
-                    EVREventType_EVREventType_VREvent_LeaveStandbyMode => mqtt
-                        .set_active(true)
-                        .context("Failed to queue standby update")?,
-                    EVREventType_EVREventType_VREvent_Quit => {
-                        system.acknowledge_quit_exiting();
-                        break;
+                    Err(error) => warn!("Invalid application key: {:?}", error),
+                },
+                Command::LaunchUrl(url) => {
+                    if let Err(error) = launch_url(applications, &mut launches, &url) {
+                        error!("Failed to launch {}: {:?}", url, error);
                     }
-                    _ => {}
                 }
+                Command::Quit => {
+                    info!("Quit requested");
+                    mqtt.shutdown.cancel();
+                }
+                Command::Identify(device) => match find_device(system, &device) {
+                    Some(index) => identifying.push((index, Instant::now() + IDENTIFY_DURATION)),
+                    None => warn!("Can't identify unknown device {}", device),
+                },
             }
-            None => tokio::time::sleep(Duration::from_millis(20)).await,
         }
-    }
+        if let Some(panic_gesture) = &mut panic_gesture {
+            let pressed = |hand| {
+                find_device(system, hand)
+                    .and_then(|index| system.get_controller_state(index))
+                    .map(|state| state.ulButtonPressed)
+            };
+            if panic_gesture.update(pressed("left"), pressed("right"), Instant::now()) {
+                warn!("Panic gesture detected");
+                mqtt.send_occurrence(Occurrence::Panic)
+                    .context("Failed to queue panic event")?;
+                // Let whoever is in the headset know it worked.
+                for hand in &["left", "right"] {
+                    if let Some(index) = find_device(system, hand) {
+                        identifying.push((index, Instant::now() + IDENTIFY_DURATION));
+                    }
+                }
+            }
+        }
+        if let Some(vr_settings) = vr_settings {
+            if profiles.update(&mqtt.application_key.borrow(), vr_settings) {
+                next_state_check = Instant::now();
+            }
+        }
+        if let Some(controller_activity) = &mut controller_activity {
+            let state = |hand| {
+                find_device(system, hand).and_then(|index| system.get_controller_state(index))
+            };
+            let in_use =
+                controller_activity.update([state("left"), state("right")], Instant::now());
+            mqtt.set_controllers_in_use(in_use)
+                .context("Failed to queue controller activity update")?;
+        }
+        let held = hands_on_controllers(
+            system,
+            &mut properties,
+            &[find_device(system, "left"), find_device(system, "right")],
+        );
+        mqtt.set_hands_on_controllers(held)
+            .context("Failed to queue hand presence update")?;
+        if let Some(pose_stream) = &mut pose_stream {
+            pose_stream.update(system);
+        }
+        identifying.retain(|(index, until)| {
+            system.trigger_haptic_pulse(*index, 0, 3999);
+            Instant::now() < *until
+        });
 
-    Ok(())
+        if power.try_recv() == Some(PowerEvent::Suspend) {
+            info!("System is suspending");
+            mqtt.send_occurrence_because(Occurrence::StandbyEntered, "suspend")
+                .context("Failed to queue standby event")?;
+            mqtt.set_reason(Reason::Suspend)
+                .context("Failed to queue reason update")?;
+            mqtt.set_active(false)
+                .context("Failed to queue standby update")?;
+            mqtt.set_power(false)
+                .context("Failed to queue power update")?;
+            loop {
+                match power.recv().await {
+                    Some(PowerEvent::Resume) => break,
+                    Some(PowerEvent::Suspend) => {}
+                    None => bail!("Power notifications stopped"),
+                }
+            }
+            info!("System resumed");
+            return Ok(LoopExit::Resumed);
+        }
+
+        if Instant::now() >= next_state_check {
+            next_state_check = Instant::now() + Duration::from_secs(1);
+            mqtt.set_disconnects(disconnects.status(Instant::now()))
+                .context("Failed to queue disconnect update")?;
+            if let Some(chaperone) = &interfaces.chaperone {
+                // SteamVR only shows the bounds when something gets close to them.
+                let visible = chaperone.are_bounds_visible();
+                if visible && !bounds_visible {
+                    mqtt.send_occurrence(Occurrence::ChaperoneCollision)
+                        .context("Failed to queue chaperone event")?;
+                    mqtt.add_boundary_collision()
+                        .context("Failed to queue boundary collision update")?;
+                }
+                bounds_visible = visible;
+                let calibration = Calibration::from_state(chaperone.get_calibration_state());
+                if calibration.needs_setup() && *mqtt.calibration.borrow() != Some(calibration) {
+                    warn!("Room setup needed: {:?}", calibration);
+                }
+                mqtt.set_calibration(Some(calibration))
+                    .context("Failed to queue calibration update")?;
+            }
+            match read_user() {
+                Ok(user) => {
+                    if mqtt.user.borrow().as_ref() != Some(&user) {
+                        info!("Active user is now {:?}", user);
+                    }
+                    mqtt.set_user(Some(user))
+                        .context("Failed to queue user update")?;
+                }
+                Err(error) => debug!("User not available: {:?}", error),
+            }
+            if let Some(compositor) = compositor {
+                mqtt.set_mirror_window(compositor.is_mirror_window_visible())
+                    .context("Failed to queue mirror window update")?;
+            }
+            // Automatic resolution changes the render size without a property change.
+            let render_size = mqtt
+                .display
+                .borrow()
+                .as_ref()
+                .map(|display| (display.render_width, display.render_height));
+            if render_size.map_or(false, |render_size| {
+                render_size != system.get_recommended_render_target_size()
+            }) {
+                update_display_status(system, vr_settings, mqtt)?;
+            }
+            if let Some(vr_settings) = vr_settings {
+                match vr_settings.get_float(STEAMVR_SECTION, SUPERSAMPLE_SCALE) {
+                    Ok(scale) => mqtt
+                        .set_supersampling(Some(scale))
+                        .context("Failed to queue supersampling update")?,
+                    Err(error) => debug!("Supersampling not available: {:?}", error),
+                }
+                match vr_settings.get_float(POWER_SECTION, TURN_OFF_SCREENS_TIMEOUT) {
+                    Ok(seconds) => mqtt
+                        .set_standby_timeout(Some(seconds))
+                        .context("Failed to queue standby timeout update")?,
+                    Err(error) => debug!("Standby timeout not available: {:?}", error),
+                }
+                match vr_settings.get_bool(STEAMVR_SECTION, MOTION_SMOOTHING) {
+                    Ok(enabled) => mqtt
+                        .set_motion_smoothing(Some(enabled))
+                        .context("Failed to queue motion smoothing update")?,
+                    Err(error) => debug!("Motion smoothing not available: {:?}", error),
+                }
+                match vr_settings.get_bool(STEAMVR_SECTION, SAFE_MODE) {
+                    Ok(enabled) => mqtt
+                        .set_safe_mode(Some(enabled))
+                        .context("Failed to queue safe mode update")?,
+                    Err(error) => debug!("Safe mode not available: {:?}", error),
+                }
+                match vr_settings.get_float(STEAMVR_SECTION, ANALOG_GAIN) {
+                    Ok(gain) => mqtt
+                        .set_brightness(Some(gain * 100.0))
+                        .context("Failed to queue brightness update")?,
+                    Err(error) => debug!("Brightness not available: {:?}", error),
+                }
+            }
+        }
+
+        if let (Some(frame_timing), Some(compositor)) = (&settings.frame_timing, compositor) {
+            if schedule.due("frame_timing", next_frame_timing_check) {
+                next_frame_timing_check = schedule.next("frame_timing", frame_timing.interval_secs);
+                match sample_frame_stats(system, compositor) {
+                    Ok(frame_stats) => mqtt
+                        .set_frame_stats(frame_stats)
+                        .context("Failed to queue frame timing update")?,
+                    Err(error) => debug!("Frame timing not available: {:?}", error),
+                }
+                frame_counter.update(&compositor.get_cumulative_stats());
+                mqtt.set_frame_counts(Some(frame_counter.counts.clone()))
+                    .context("Failed to queue frame count update")?;
+            }
+        }
+
+        if let Some(process_usage) = &settings.process_usage {
+            if schedule.due("process_usage", next_process_usage_check) {
+                next_process_usage_check =
+                    schedule.next("process_usage", process_usage.interval_secs);
+                match applications.get_current_scene_process_id() {
+                    0 => {
+                        usage_sampler.reset();
+                        mqtt.set_process_usage(None)
+                            .context("Failed to queue process usage update")?;
+                    }
+                    pid => match usage_sampler.sample(pid) {
+                        Ok(Some(usage)) => mqtt
+                            .set_process_usage(Some(usage))
+                            .context("Failed to queue process usage update")?,
+                        Ok(None) => {}
+                        Err(error) => debug!("Process usage not available: {:?}", error),
+                    },
+                }
+            }
+        }
+
+        if let (Some(overlays), Some(overlay)) = (&settings.overlays, &interfaces.overlay) {
+            if schedule.due("overlays", next_overlay_check) {
+                next_overlay_check = schedule.next("overlays", overlays.interval_secs);
+                mqtt.set_overlays(read_overlays(applications, overlay, overlays))
+                    .context("Failed to queue overlay update")?;
+            }
+        }
+
+        if let Some((at, retries)) = lookup_retry {
+            if Instant::now() >= at {
+                lookup_retry = None;
+                let retries = retries + 1;
+                if scene_application_changed(settings, interfaces, &mut launches, mqtt)? {
+                    if retries < settings.application_lookup.retries {
+                        lookup_retry = Some((
+                            Instant::now() + Duration::from_secs(1 << retries.min(10)),
+                            retries,
+                        ));
+                    } else {
+                        warn!("Giving up identifying the application");
+                    }
+                }
+            }
+        }
+
+        if let Some(base_stations) = &settings.base_stations {
+            if schedule.due("base_stations", next_base_station_check) {
+                next_base_station_check =
+                    schedule.next("base_stations", base_stations.interval_secs);
+                mqtt.set_base_stations(read_base_stations(system))
+                    .context("Failed to queue base station update")?;
+            }
+        }
+
+        if let Some(devices) = &settings.devices {
+            if schedule.due("devices", next_device_check) {
+                next_device_check = schedule.next("devices", devices.interval_secs);
+                mqtt.set_devices(read_devices(system, &mut properties))
+                    .context("Failed to queue device update")?;
+            }
+        }
+
+        if let Some(ready) = &settings.ready_to_play {
+            if Instant::now() >= next_ready_check {
+                next_ready_check = Instant::now() + Duration::from_secs(ready.interval_secs);
+                mqtt.set_ready_to_play(Readiness::check(system, &mut properties, ready))
+                    .context("Failed to queue readiness update")?;
+            }
+        }
+
+        if let Some(notify) = &settings.notifications {
+            if Instant::now() >= next_critical_check {
+                next_critical_check = Instant::now() + Duration::from_secs(notify.interval_secs);
+                for notification in critical_conditions.check(system, notify) {
+                    notifications
+                        .send(notification)
+                        .map_err(|_| anyhow!("Failed to send notification"))?;
+                }
+            }
+        }
+
+        if let Some(wireless) = &settings.wireless {
+            if schedule.due("wireless", next_link_check) {
+                next_link_check = schedule.next("wireless", wireless.interval_secs);
+                let link = match read_link_status(system, wireless) {
+                    Ok(link) => Some(link),
+                    Err(error) => {
+                        debug!("Link quality not available: {:?}", error);
+                        None
+                    }
+                };
+                mqtt.set_link(link).context("Failed to queue link update")?;
+            }
+        }
+
+        // Handle everything that queued up while sleeping before checking again.
+        while let Some(event) = system.poll_next_event() {
+            if !settings.ignore.event_types.is_empty()
+                && settings.ignore.ignores_event(
+                    &system
+                        .get_event_type_name_from_enum(event.eventType as _)
+                        .to_string_lossy(),
+                )
+            {
+                continue;
+            }
+            if let Some(raw_events) = &settings.raw_events {
+                let event = RawEvent::new(system, &event);
+                if raw_events.includes(&event.name) {
+                    mqtt.send_event(event)
+                        .context("Failed to queue raw event")?;
+                }
+            }
+            #[allow(non_upper_case_globals)]
+            match event.eventType as i32 {
+                EVREventType_EVREventType_VREvent_SceneApplicationChanged
+                | EVREventType_EVREventType_VREvent_SceneApplicationStateChanged => {
+                    lookup_retry = None;
+                    if scene_application_changed(settings, interfaces, &mut launches, mqtt)? {
+                        lookup_retry = Some((Instant::now() + Duration::from_secs(1), 0));
+                    }
+                }
+                EVREventType_EVREventType_VREvent_ApplicationMimeTypeLoad => {
+                    let launch = unsafe { event.data.applicationLaunch };
+                    match applications.get_application_launch_arguments(launch.unArgsHandle) {
+                        Ok(arguments) => launches.arguments(launch.pid, arguments),
+                        Err(error) => debug!("Launch arguments not available: {:?}", error),
+                    }
+                }
+                EVREventType_EVREventType_VREvent_EnterStandbyMode => {
+                    info!("Headset entered standby");
+                    mqtt.send_occurrence_because(Occurrence::StandbyEntered, "idle")
+                        .context("Failed to queue standby event")?;
+                    mqtt.set_reason(Reason::Standby)
+                        .context("Failed to queue reason update")?;
+                    mqtt.set_active(false)
+                        .context("Failed to queue standby update")?
+                }
+                EVREventType_EVREventType_VREvent_LeaveStandbyMode => {
+                    mqtt.send_occurrence_because(Occurrence::StandbyLeft, "activity")
+                        .context("Failed to queue standby event")?;
+                    mqtt.set_active(true)
+                        .context("Failed to queue standby update")?
+                }
+                EVREventType_EVREventType_VREvent_PropertyChanged => {
+                    let property = unsafe { event.data.property };
+                    properties.invalidate(event.trackedDeviceIndex, property.prop);
+                    if event.trackedDeviceIndex == k_unTrackedDeviceIndex_Hmd {
+                        update_display_status(system, vr_settings, mqtt)?
+                    }
+                }
+                EVREventType_EVREventType_VREvent_Compositor_DisplayDisconnected
+                | EVREventType_EVREventType_VREvent_Compositor_HDCPError => {
+                    warn!("Headset display is not working");
+                    mqtt.set_display_problem(true)
+                        .context("Failed to queue display problem update")?
+                }
+                EVREventType_EVREventType_VREvent_ChaperoneUniverseHasChanged
+                | EVREventType_EVREventType_VREvent_ChaperoneRoomSetupFinished => {
+                    info!("Room setup changed");
+                    next_state_check = Instant::now();
+                }
+                EVREventType_EVREventType_VREvent_Compositor_DisplayReconnected => mqtt
+                    .set_display_problem(false)
+                    .context("Failed to queue display problem update")?,
+                EVREventType_EVREventType_VREvent_TrackedCamera_StartVideoStream
+                | EVREventType_EVREventType_VREvent_TrackedCamera_ResumeVideoStream => {
+                    set_camera_active(&mut camera, true, mqtt)?
+                }
+                EVREventType_EVREventType_VREvent_TrackedCamera_StopVideoStream
+                | EVREventType_EVREventType_VREvent_TrackedCamera_PauseVideoStream => {
+                    set_camera_active(&mut camera, false, mqtt)?
+                }
+                EVREventType_EVREventType_VREvent_TrackedDeviceActivated => {
+                    properties.forget_device(event.trackedDeviceIndex)
+                }
+                EVREventType_EVREventType_VREvent_TrackedDeviceDeactivated => {
+                    properties.forget_device(event.trackedDeviceIndex);
+                    disconnects.record(Instant::now());
+                    let status = disconnects.status(Instant::now());
+                    if status.flapping && !mqtt.disconnects.borrow().flapping {
+                        warn!(
+                            "Tracked devices disconnected {} times recently; check the USB connections",
+                            status.recent
+                        );
+                    }
+                    mqtt.set_disconnects(status)
+                        .context("Failed to queue disconnect update")?;
+                    next_device_check = Instant::now();
+                }
+                EVREventType_EVREventType_VREvent_ScreenshotTaken => mqtt
+                    .send_occurrence(Occurrence::Screenshot)
+                    .context("Failed to queue screenshot event")?,
+                EVREventType_EVREventType_VREvent_TrackedDeviceUserInteractionStarted
+                    if event.trackedDeviceIndex == k_unTrackedDeviceIndex_Hmd =>
+                {
+                    mqtt.send_occurrence_because(Occurrence::HeadsetDonned, "proximity")
+                        .context("Failed to queue headset event")?
+                }
+                EVREventType_EVREventType_VREvent_TrackedDeviceUserInteractionEnded
+                    if event.trackedDeviceIndex == k_unTrackedDeviceIndex_Hmd =>
+                {
+                    mqtt.send_occurrence_because(Occurrence::HeadsetDoffed, "proximity")
+                        .context("Failed to queue headset event")?
+                }
+                EVREventType_EVREventType_VREvent_Quit => {
+                    mqtt.send_occurrence(Occurrence::QuitRequested)
+                        .context("Failed to queue quit event")?;
+                    let reason = Reason::quit(applications, &event);
+                    info!("SteamVR is quitting: {}", reason.to_json());
+                    if let Reason::Quit {
+                        connection_lost: true,
+                        ..
+                    } = reason
+                    {
+                        notifications
+                            .send(Notification {
+                                title: "SteamVR error".into(),
+                                message: "SteamVR lost its connection and is shutting down".into(),
+                            })
+                            .map_err(|_| anyhow!("Failed to send notification"))?;
+                    }
+                    mqtt.set_reason(reason)
+                        .context("Failed to queue reason update")?;
+                    system.acknowledge_quit_exiting();
+                    return Ok(LoopExit::Quit);
+                }
+                _ => {}
+            }
+        }
+
+        slowest_poll = slowest_poll.max(poll_started.elapsed());
+        if Instant::now() >= next_poll_latency {
+            next_poll_latency = Instant::now() + POLL_LATENCY_INTERVAL;
+            mqtt.set_poll_latency(Some(slowest_poll.as_millis() as u64))
+                .context("Failed to queue poll latency update")?;
+            slowest_poll = Duration::default();
+        }
+        tokio::time::sleep(poll_interval).await;
+    }
 }
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() {
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let last_error = logger::init();
 
-    if let Err(error) = run().await {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args.as_slice() {
+        ["--watchdog", worker_args @ ..] => match load_settings().await {
+            Ok(settings) => supervise(&settings.watchdog, worker_args).await,
+            Err(error) => Err(error),
+        },
+        // Started by the watchdog, which wants heartbeats on stdout and restarts this on
+        // errors rather than having them wait for someone to close a message box.
+        ["--watched", worker_args @ ..] => {
+            let progress = Arc::new(Progress::default());
+            let result = tokio::select! {
+                result = run_worker(worker_args, last_error, progress.clone()) => result,
+                _ = heartbeat(progress) => unreachable!(),
+            };
+            if let Err(error) = result {
+                error!("{:?}", error);
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        ["--cleanup"] => match load_settings().await {
+            Ok(settings) => cleanup(&settings).await.map(|cleared| {
+                show_message(&format!(
+                    "Cleared {} MQTT topics published for {}. They come back if vr-status \
+                     runs again with the same id.",
+                    cleared, settings.id
+                ))
+            }),
+            Err(error) => Err(error),
+        },
+        ["--install-startup"] => install_startup().map(|()| {
+            show_message(
+                "vr-status will start when you sign in to Windows. It turns off SteamVR auto \
+                 launch the next time SteamVR runs.",
+            )
+        }),
+        ["--remove-startup"] => remove_startup().map(|()| {
+            show_message(
+                "vr-status will no longer start with Windows. Run vr-status.exe while SteamVR \
+                 is running to turn SteamVR auto launch back on.",
+            )
+        }),
+        worker_args => run_worker(worker_args, last_error, Arc::new(Progress::default())).await,
+    };
+    if let Err(error) = result {
         unsafe {
             MessageBoxW(
                 None,
@@ -163,3 +1575,38 @@ async fn main() {
         }
     }
 }
+
+async fn run_worker(
+    args: &[&str],
+    last_error: tokio::sync::watch::Receiver<Option<String>>,
+    progress: Arc<Progress>,
+) -> Result<()> {
+    match args {
+        [] => {
+            let mut sessions = None;
+            loop {
+                sessions = run(false, sessions, last_error.clone(), progress.clone()).await?;
+                if sessions.is_none() {
+                    return Ok(());
+                }
+            }
+        }
+        // Keep running between SteamVR sessions.
+        ["--startup"] => {
+            let mut sessions = None;
+            loop {
+                sessions = run(true, sessions, last_error.clone(), progress.clone()).await?;
+            }
+        }
+        [argument, ..] => Err(anyhow!(
+            "Unknown argument {}. Expected --startup, --watchdog, --install-startup, --remove-startup, or --cleanup.",
+            argument
+        )),
+    }
+}
+
+fn show_message(message: &str) {
+    unsafe {
+        MessageBoxW(None, message, "vr-status", MB_OK | MB_ICONINFORMATION);
+    }
+}
@@ -0,0 +1,88 @@
+use std::{ffi::c_void, ptr::null_mut};
+
+use anyhow::{bail, Result};
+use bindings::Windows::Win32::{
+    Foundation::HANDLE,
+    System::Power::{
+        PowerRegisterSuspendResumeNotification, PowerUnregisterSuspendResumeNotification,
+        DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS,
+    },
+    UI::WindowsAndMessaging::{DEVICE_NOTIFY_CALLBACK, PBT_APMRESUMEAUTOMATIC, PBT_APMSUSPEND},
+};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PowerEvent {
+    Suspend,
+    Resume,
+}
+
+/// Receives system suspend and resume notifications for as long as it is alive.
+pub struct PowerNotifications {
+    handle: *mut c_void,
+    receiver: UnboundedReceiver<PowerEvent>,
+    // Referenced by the registration until it is dropped.
+    _parameters: Box<DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS>,
+    _sender: Box<UnboundedSender<PowerEvent>>,
+}
+
+unsafe extern "system" fn power_callback(
+    context: *mut c_void,
+    notification: u32,
+    _setting: *mut c_void,
+) -> u32 {
+    let sender = &*(context as *const UnboundedSender<PowerEvent>);
+    let event = match notification {
+        PBT_APMSUSPEND => Some(PowerEvent::Suspend),
+        PBT_APMRESUMEAUTOMATIC => Some(PowerEvent::Resume),
+        _ => None,
+    };
+    if let Some(event) = event {
+        let _ = sender.send(event);
+    }
+    0
+}
+
+impl PowerNotifications {
+    pub fn register() -> Result<Self> {
+        let (sender, receiver) = unbounded_channel();
+        let sender = Box::new(sender);
+        let mut parameters = Box::new(DEVICE_NOTIFY_SUBSCRIBE_PARAMETERS {
+            Callback: Some(power_callback),
+            Context: &*sender as *const _ as *mut c_void,
+        });
+        let mut handle = null_mut();
+        unsafe {
+            let error = PowerRegisterSuspendResumeNotification(
+                DEVICE_NOTIFY_CALLBACK,
+                HANDLE(&mut *parameters as *mut _ as isize),
+                &mut handle,
+            );
+            if error != 0 {
+                bail!("PowerRegisterSuspendResumeNotification error {}", error);
+            }
+        }
+        Ok(Self {
+            handle,
+            receiver,
+            _parameters: parameters,
+            _sender: sender,
+        })
+    }
+
+    pub fn try_recv(&mut self) -> Option<PowerEvent> {
+        self.receiver.try_recv().ok()
+    }
+
+    pub async fn recv(&mut self) -> Option<PowerEvent> {
+        self.receiver.recv().await
+    }
+}
+
+impl Drop for PowerNotifications {
+    fn drop(&mut self) {
+        unsafe {
+            PowerUnregisterSuspendResumeNotification(self.handle);
+        }
+    }
+}
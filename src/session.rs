@@ -0,0 +1,104 @@
+use std::{cell::RefCell, ptr::null_mut};
+
+use anyhow::{bail, Result};
+use bindings::Windows::Win32::{
+    Foundation::{HWND, LPARAM, LRESULT, WPARAM},
+    System::{
+        LibraryLoader::GetModuleHandleW,
+        RemoteDesktop::{WTSRegisterSessionNotification, NOTIFY_FOR_THIS_SESSION},
+    },
+    UI::WindowsAndMessaging::{
+        CreateWindowExW, DefWindowProcW, DispatchMessageW, GetMessageW, RegisterClassW, MSG,
+        WM_WTSSESSION_CHANGE, WNDCLASSW, WTS_SESSION_LOCK, WTS_SESSION_UNLOCK,
+    },
+};
+use log::error;
+use tokio::sync::watch;
+
+// Messages for a window are delivered on the thread that created it, so the window
+// procedure finds the sender here.
+thread_local! {
+    static LOCKED: RefCell<Option<watch::Sender<bool>>> = RefCell::new(None);
+}
+
+unsafe extern "system" fn window_proc(
+    window: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if message == WM_WTSSESSION_CHANGE {
+        let locked = match wparam.0 as u32 {
+            WTS_SESSION_LOCK => Some(true),
+            WTS_SESSION_UNLOCK => Some(false),
+            _ => None,
+        };
+        if let Some(locked) = locked {
+            LOCKED.with(|sender| {
+                if let Some(sender) = &*sender.borrow() {
+                    let _ = sender.send(locked);
+                }
+            });
+        }
+        return LRESULT(0);
+    }
+    DefWindowProcW(window, message, wparam, lparam)
+}
+
+/// Session notifications need a window, so this creates a message-only window on its own
+/// thread and reports whether the Windows session is locked until the process exits.
+///
+/// The session is assumed to be unlocked at startup since vr-status is launched from it.
+pub fn watch_lock_state(locked: watch::Sender<bool>) -> Result<()> {
+    let (ready_send, ready_receive) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        LOCKED.with(|sender| *sender.borrow_mut() = Some(locked));
+        let window = unsafe {
+            let instance = GetModuleHandleW(None);
+            let class = WNDCLASSW {
+                lpfnWndProc: Some(window_proc),
+                hInstance: instance,
+                lpszClassName: "vr-status-session".into(),
+                ..Default::default()
+            };
+            RegisterClassW(&class);
+            // HWND_MESSAGE
+            let window = CreateWindowExW(
+                Default::default(),
+                "vr-status-session",
+                "vr-status",
+                Default::default(),
+                0,
+                0,
+                0,
+                0,
+                HWND(-3),
+                None,
+                instance,
+                null_mut(),
+            );
+            if window.is_null() {
+                let _ = ready_send.send(Err("CreateWindowExW failed"));
+                return;
+            }
+            if !WTSRegisterSessionNotification(window, NOTIFY_FOR_THIS_SESSION).as_bool() {
+                let _ = ready_send.send(Err("WTSRegisterSessionNotification failed"));
+                return;
+            }
+            window
+        };
+        let _ = ready_send.send(Ok(()));
+        let mut message = MSG::default();
+        unsafe {
+            while GetMessageW(&mut message, window, 0, 0).as_bool() {
+                DispatchMessageW(&message);
+            }
+        }
+        error!("Session notifications stopped");
+    });
+    match ready_receive.recv() {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(error)) => bail!("{}", error),
+        Err(_) => bail!("Session notification thread exited"),
+    }
+}
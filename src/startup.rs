@@ -0,0 +1,46 @@
+use std::{ffi::OsStr, os::windows::ffi::OsStrExt};
+
+use anyhow::{bail, Context, Result};
+use bindings::Windows::Win32::{
+    Foundation::{ERROR_FILE_NOT_FOUND, ERROR_SUCCESS},
+    System::Registry::{RegDeleteKeyValueW, RegSetKeyValueW, HKEY_CURRENT_USER, REG_SZ},
+};
+
+const RUN_KEY: &str = "Software\\Microsoft\\Windows\\CurrentVersion\\Run";
+const RUN_VALUE: &str = "vr-status";
+
+/// Starts vr-status in startup mode whenever the current user signs in to Windows.
+pub fn install_startup() -> Result<()> {
+    let exe = ::std::env::current_exe().context("Could not find installation directory")?;
+    let command = format!("\"{}\" --startup", exe.display());
+    let data: Vec<u16> = OsStr::new(&command)
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect();
+    let result = unsafe {
+        RegSetKeyValueW(
+            HKEY_CURRENT_USER,
+            RUN_KEY,
+            RUN_VALUE,
+            REG_SZ,
+            data.as_ptr() as *const _,
+            (data.len() * 2) as u32,
+        )
+    };
+    if result != ERROR_SUCCESS {
+        bail!("Failed to add vr-status to startup: error {:?}", result);
+    }
+    Ok(())
+}
+
+/// Undoes `install_startup`. Succeeds if vr-status wasn't set to start with Windows.
+pub fn remove_startup() -> Result<()> {
+    let result = unsafe { RegDeleteKeyValueW(HKEY_CURRENT_USER, RUN_KEY, RUN_VALUE) };
+    if result != ERROR_SUCCESS && result != ERROR_FILE_NOT_FOUND {
+        bail!(
+            "Failed to remove vr-status from startup: error {:?}",
+            result
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use std::{mem::size_of, time::Instant};
+
+use anyhow::{bail, Result};
+use bindings::Windows::Win32::{
+    Foundation::{CloseHandle, FILETIME},
+    System::{
+        ProcessStatus::{K32GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS},
+        SystemInformation::{GetSystemInfo, SYSTEM_INFO},
+        Threading::{GetProcessTimes, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION},
+    },
+};
+use vr_status_core::process::ProcessUsage;
+
+fn filetime_ticks(time: &FILETIME) -> u64 {
+    (time.dwHighDateTime as u64) << 32 | time.dwLowDateTime as u64
+}
+
+/// Returns the total CPU time in 100ns ticks and the working set in bytes of `pid`.
+fn read_process_counters(pid: u32) -> Result<(u64, usize)> {
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid);
+        if process.is_null() {
+            bail!("OpenProcess failed for {}", pid);
+        }
+        let mut creation = FILETIME::default();
+        let mut exit = FILETIME::default();
+        let mut kernel = FILETIME::default();
+        let mut user = FILETIME::default();
+        let times_ok =
+            GetProcessTimes(process, &mut creation, &mut exit, &mut kernel, &mut user).as_bool();
+        let mut memory = PROCESS_MEMORY_COUNTERS::default();
+        let memory_ok = K32GetProcessMemoryInfo(
+            process,
+            &mut memory,
+            size_of::<PROCESS_MEMORY_COUNTERS>() as u32,
+        )
+        .as_bool();
+        CloseHandle(process);
+        if !times_ok {
+            bail!("GetProcessTimes failed for {}", pid);
+        }
+        if !memory_ok {
+            bail!("K32GetProcessMemoryInfo failed for {}", pid);
+        }
+        Ok((
+            filetime_ticks(&kernel) + filetime_ticks(&user),
+            memory.WorkingSetSize,
+        ))
+    }
+}
+
+/// Samples CPU and memory use of the scene application. CPU use is averaged since the
+/// previous sample and scaled like Task Manager, so 100% means every core is busy.
+pub struct UsageSampler {
+    processors: u32,
+    previous: Option<(u32, Instant, u64)>,
+}
+
+impl UsageSampler {
+    pub fn new() -> Self {
+        let mut info = SYSTEM_INFO::default();
+        unsafe { GetSystemInfo(&mut info) };
+        Self {
+            processors: info.dwNumberOfProcessors.max(1),
+            previous: None,
+        }
+    }
+
+    /// Returns `None` for the first sample of a process, which has nothing to compare with.
+    pub fn sample(&mut self, pid: u32) -> Result<Option<ProcessUsage>> {
+        let now = Instant::now();
+        let (ticks, working_set) = match read_process_counters(pid) {
+            Ok(counters) => counters,
+            Err(error) => {
+                self.previous = None;
+                return Err(error);
+            }
+        };
+        let usage = match self.previous {
+            Some((previous_pid, previous_time, previous_ticks)) if previous_pid == pid => {
+                let elapsed = now.duration_since(previous_time).as_secs_f64();
+                let cpu_secs = ticks.saturating_sub(previous_ticks) as f64 / 10_000_000.0;
+                Some(ProcessUsage {
+                    pid,
+                    cpu_percent: (cpu_secs / elapsed / self.processors as f64 * 100.0) as f32,
+                    working_set_mb: working_set as f64 / (1024.0 * 1024.0),
+                })
+            }
+            _ => None,
+        };
+        self.previous = Some((pid, now, ticks));
+        Ok(usage)
+    }
+
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+}
@@ -0,0 +1,87 @@
+use std::{ffi::OsString, os::windows::ffi::OsStringExt, ptr::null_mut};
+
+use anyhow::{bail, Result};
+use bindings::Windows::Win32::{
+    Foundation::{ERROR_SUCCESS, PWSTR},
+    System::{
+        Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_DWORD, RRF_RT_REG_SZ},
+        WindowsProgramming::GetUserNameW,
+    },
+};
+use vr_status_core::user::UserInfo;
+
+const STEAM_KEY: &str = "Software\\Valve\\Steam";
+const STEAM_ACTIVE_PROCESS_KEY: &str = "Software\\Valve\\Steam\\ActiveProcess";
+
+/// Reads the Windows user vr-status is running as and the Steam account logged in for
+/// that user.
+pub fn read_user() -> Result<UserInfo> {
+    Ok(UserInfo {
+        windows_user: windows_user()?,
+        steam_account: steam_account(),
+    })
+}
+
+fn windows_user() -> Result<String> {
+    // UNLEN + 1
+    let mut buffer = vec![0u16; 257];
+    let mut size = buffer.len() as u32;
+    unsafe {
+        if !GetUserNameW(PWSTR(buffer.as_mut_ptr()), &mut size).as_bool() {
+            bail!("GetUserNameW failed");
+        }
+    }
+    // The size includes the terminator.
+    Ok(
+        OsString::from_wide(&buffer[..size.saturating_sub(1) as usize])
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
+
+fn steam_account() -> Option<String> {
+    // ActiveUser is the account ID while someone is logged in and 0 otherwise, and
+    // AutoLoginUser names the account that last logged in.
+    let mut active_user = 0u32;
+    let mut size = std::mem::size_of::<u32>() as u32;
+    let result = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            STEAM_ACTIVE_PROCESS_KEY,
+            "ActiveUser",
+            RRF_RT_REG_DWORD,
+            null_mut(),
+            &mut active_user as *mut u32 as *mut _,
+            &mut size,
+        )
+    };
+    if result != ERROR_SUCCESS || active_user == 0 {
+        return None;
+    }
+
+    let mut buffer = vec![0u16; 256];
+    let mut size = (buffer.len() * 2) as u32;
+    let result = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            STEAM_KEY,
+            "AutoLoginUser",
+            RRF_RT_REG_SZ,
+            null_mut(),
+            buffer.as_mut_ptr() as *mut _,
+            &mut size,
+        )
+    };
+    if result != ERROR_SUCCESS {
+        return None;
+    }
+    // The size is in bytes and includes the terminator.
+    let account = OsString::from_wide(&buffer[..(size as usize / 2).saturating_sub(1)])
+        .to_string_lossy()
+        .into_owned();
+    if account.is_empty() {
+        None
+    } else {
+        Some(account)
+    }
+}
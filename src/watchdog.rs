@@ -0,0 +1,72 @@
+use std::{process::Stdio, sync::Arc, time::Duration};
+
+use anyhow::{Context, Result};
+use log::{debug, error, info, warn};
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    process::Command,
+};
+use vr_status_core::{settings::WatchdogSettings, watchdog::Progress};
+
+/// Set on the worker when the watchdog restarted it, so it can publish the restart.
+pub const RESTARTED_VAR: &str = "VR_STATUS_WATCHDOG_RESTARTED";
+
+const HEARTBEAT: &str = "heartbeat";
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Runs vr-status as a child process with `args`, restarting it if it stops printing
+/// heartbeats for `timeout_secs` or exits with an error.
+pub async fn supervise(settings: &WatchdogSettings, args: &[&str]) -> Result<()> {
+    let exe = ::std::env::current_exe().context("Could not find installation directory")?;
+    let timeout = Duration::from_secs(settings.timeout_secs);
+    let mut restarted = false;
+    loop {
+        let mut command = Command::new(&exe);
+        command
+            .arg("--watched")
+            .args(args)
+            .stdout(Stdio::piped())
+            .kill_on_drop(true);
+        if restarted {
+            command.env(RESTARTED_VAR, "1");
+        }
+        let mut child = command.spawn().context("Failed to start vr-status")?;
+        let mut lines =
+            BufReader::new(child.stdout.take().context("No output from vr-status")?).lines();
+        loop {
+            match tokio::time::timeout(timeout, lines.next_line()).await {
+                Ok(Ok(Some(line))) if line == HEARTBEAT => {}
+                Ok(Ok(Some(line))) => debug!("vr-status printed {:?}", line),
+                Ok(Ok(None)) | Ok(Err(_)) => break,
+                Err(_) => {
+                    error!("vr-status stopped making progress; restarting it");
+                    if let Err(error) = child.kill().await {
+                        warn!("Failed to stop vr-status: {:?}", error);
+                    }
+                    break;
+                }
+            }
+        }
+        let status = child.wait().await.context("Failed to wait for vr-status")?;
+        if status.success() {
+            info!("vr-status exited");
+            return Ok(());
+        }
+        warn!("vr-status exited with {}; restarting it", status);
+        restarted = true;
+        tokio::time::sleep(HEARTBEAT_INTERVAL).await;
+    }
+}
+
+/// Prints a heartbeat for the supervisor whenever every running loop has made progress.
+pub async fn heartbeat(progress: Arc<Progress>) {
+    loop {
+        tokio::time::sleep(HEARTBEAT_INTERVAL).await;
+        let stalled = progress.stalled();
+        if stalled.is_empty() {
+            println!("{}", HEARTBEAT);
+        } else {
+            warn!("No progress from {}", stalled.join(", "));
+        }
+    }
+}